@@ -0,0 +1,279 @@
+use sdp::{MediaDescription, MediaType, Attr, RtpMapValue, FmtPValue};
+
+/// A codec the media engine can handle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Codec {
+    pub media: MediaType,
+    pub name: String,
+    pub clock_rate: u32,
+    /// Number of audio channels, 1 if not set.
+    pub channels: Option<u32>,
+    /// Format parameters answered when the offer has none.
+    pub fmtp: Option<String>,
+}
+
+impl Codec {
+    pub fn new(media: MediaType, name: &str, clock_rate: u32, channels: Option<u32>) -> Codec {
+        Codec {
+            media: media,
+            name: name.to_string(),
+            clock_rate: clock_rate,
+            channels: channels,
+            fmtp: None,
+        }
+    }
+
+    pub fn with_fmtp(mut self, fmtp: &str) -> Codec {
+        self.fmtp = Some(fmtp.to_string());
+        self
+    }
+
+    /// Whether an "a=rtpmap" describes this codec. Encoding names are case
+    /// insensitive (RFC 4855, section 3).
+    pub fn matches(&self, rtpmap: &RtpMapValue) -> bool {
+        self.name.to_lowercase() == rtpmap.encoding_name.to_lowercase() &&
+            self.clock_rate == rtpmap.clock_rate &&
+            self.channels.unwrap_or(1) == rtpmap.encoding_params.unwrap_or(1)
+    }
+}
+
+/// Encodings of the static audio/video payload types (RFC 3551, section 6),
+/// for when the offer has no "a=rtpmap" for them.
+fn static_rtpmap(payload_type: u32) -> Option<RtpMapValue> {
+    let (name, clock_rate) = match payload_type {
+        0 => ("PCMU", 8000),
+        3 => ("GSM", 8000),
+        4 => ("G723", 8000),
+        8 => ("PCMA", 8000),
+        9 => ("G722", 8000),
+        18 => ("G729", 8000),
+        26 => ("JPEG", 90000),
+        31 => ("H261", 90000),
+        32 => ("MPV", 90000),
+        34 => ("H263", 90000),
+        _ => return None,
+    };
+
+    Some(RtpMapValue {
+        payload_type: payload_type,
+        encoding_name: name.to_string(),
+        clock_rate: clock_rate,
+        encoding_params: None,
+    })
+}
+
+/// Encoding of the payload type `payload_type` of a media description.
+pub fn find_rtpmap(media: &MediaDescription, payload_type: u32) -> Option<RtpMapValue> {
+    media.attrs.iter().filter_map(|attr| match *attr {
+        Attr::RtpMap(ref x) if x.payload_type == payload_type => Some(x.clone()),
+        _ => None,
+    }).next().or(static_rtpmap(payload_type))
+}
+
+/// Codecs the media engine supports, in order of preference.
+#[derive(Clone, Debug)]
+pub struct CodecRegistry {
+    codecs: Vec<Codec>,
+}
+
+impl CodecRegistry {
+    pub fn new() -> CodecRegistry {
+        CodecRegistry {
+            codecs: vec![],
+        }
+    }
+
+    /// Codecs `MemberSession` decodes and encodes.
+    pub fn platform() -> CodecRegistry {
+        let mut registry = CodecRegistry::new();
+        registry.add(Codec::new(MediaType::AUDIO, "opus", 48000, Some(2))
+                     .with_fmtp("minptime=10;useinbandfec=1"));
+
+        registry
+    }
+
+    pub fn add(&mut self, codec: Codec) {
+        self.codecs.push(codec);
+    }
+
+    pub fn codecs(&self) -> &[Codec] {
+        &self.codecs
+    }
+
+    /// Prefers the codecs named in `names`, in that order, to the others.
+    pub fn set_preference(&mut self, names: &[&str]) {
+        let rank = |codec: &Codec| {
+            names.iter()
+                .position(|name| name.to_lowercase() == codec.name.to_lowercase())
+                .unwrap_or(names.len())
+        };
+
+        // Stable, so the others keep their order
+        self.codecs.sort_by_key(|codec| rank(codec));
+    }
+
+    pub fn find(&self, media: &MediaType, rtpmap: &RtpMapValue) -> Option<&Codec> {
+        self.codecs.iter().find(|codec| codec.media == *media && codec.matches(rtpmap))
+    }
+
+    /// Codec of the payload type `payload_type` of a media description, if
+    /// supported.
+    pub fn find_format(&self, media: &MediaDescription, payload_type: u32) -> Option<&Codec> {
+        find_rtpmap(media, payload_type).and_then(|rtpmap| self.find(&media.media.media, &rtpmap))
+    }
+
+    /// Formats of a media description whose codecs are supported, in order
+    /// of preference.
+    pub fn intersect(&self, media: &MediaDescription) -> Vec<String> {
+        let mut formats = media.media.fmt.iter()
+            .filter_map(|fmt| fmt.parse::<u32>().ok())
+            .filter_map(|pt| {
+                self.find_format(media, pt)
+                    .and_then(|codec| self.codecs.iter().position(|c| c == codec))
+                    .map(|rank| (rank, pt))
+            })
+            .collect::<Vec<(usize, u32)>>();
+        formats.sort_by_key(|&(rank, _)| rank);
+
+        formats.into_iter().map(|(_, pt)| pt.to_string()).collect()
+    }
+}
+
+/// Agrees on the parameters of a format, from the offered ones and ours.
+/// Fails if they are incompatible, the format not being answered then.
+/// Parameters of codecs without rules are taken from the offer.
+pub fn negotiate_fmtp(encoding_name: &str, offered: &FmtPValue, local: &FmtPValue) -> Result<FmtPValue, String> {
+    match encoding_name.to_lowercase().as_str() {
+        "opus" => Ok(negotiate_opus(offered, local)),
+        "h264" => negotiate_h264(offered, local),
+        "vp9" => negotiate_vp9(offered, local),
+        _ => Ok(offered.clone()),
+    }
+}
+
+fn is_set(fmtp: &FmtPValue, key: &str) -> bool {
+    fmtp.get(key) == Some("1")
+}
+
+fn param_u32(fmtp: &FmtPValue, key: &str) -> Option<u32> {
+    fmtp.get(key).and_then(|v| v.parse::<u32>().ok())
+}
+
+/// Opus parameters (RFC 7587, section 6.1).
+fn negotiate_opus(offered: &FmtPValue, local: &FmtPValue) -> FmtPValue {
+    let mut answer = FmtPValue::new(offered.format);
+
+    // Stereo is only worth receiving if the offerer may send it, and
+    // sending if it wants it
+    if is_set(local, "stereo") && offered.get("sprop-stereo") != Some("0") {
+        answer.set("stereo", "1");
+    }
+    if is_set(local, "sprop-stereo") && is_set(offered, "stereo") {
+        answer.set("sprop-stereo", "1");
+    }
+
+    for key in ["useinbandfec", "usedtx"].iter() {
+        if is_set(local, key) && is_set(offered, key) {
+            answer.set(key, "1");
+        }
+    }
+
+    // Limits on what is received, so ours whatever the offerer's are
+    for key in ["maxplaybackrate", "minptime"].iter() {
+        if let Some(value) = param_u32(local, key) {
+            answer.set(key, &value.to_string());
+        }
+    }
+
+    answer
+}
+
+/// H.264 profiles, as told by profile_idc and the constraint_set flags of
+/// profile-iop (RFC 6184, section 8.1).
+#[derive(Debug, PartialEq)]
+enum H264Profile {
+    ConstrainedBaseline,
+    Baseline,
+    Main,
+    Extended,
+    Other(u8),
+}
+
+impl H264Profile {
+    fn new(profile_idc: u8, profile_iop: u8) -> H264Profile {
+        let (set0, set1) = (profile_iop & 0x80 != 0, profile_iop & 0x40 != 0);
+
+        match profile_idc {
+            0x42 if set1 => H264Profile::ConstrainedBaseline,
+            0x42 => H264Profile::Baseline,
+            0x4d if set0 => H264Profile::ConstrainedBaseline,
+            0x4d => H264Profile::Main,
+            0x58 if set0 && set1 => H264Profile::ConstrainedBaseline,
+            0x58 if set0 => H264Profile::Baseline,
+            0x58 => H264Profile::Extended,
+            _ => H264Profile::Other(profile_idc),
+        }
+    }
+
+    /// Whether a stream of this profile can be decoded as of `other`:
+    /// Constrained Baseline being a subset of Baseline, either does.
+    fn is_compatible(&self, other: &H264Profile) -> bool {
+        let baseline = |p: &H264Profile| *p == H264Profile::ConstrainedBaseline || *p == H264Profile::Baseline;
+
+        self == other || (baseline(self) && baseline(other))
+    }
+}
+
+/// H.264 parameters (RFC 6184, section 8.2.2). The packetization mode must
+/// be the same and the profiles compatible, the offered one being answered.
+/// So is our level if levels may differ, else the lower one.
+fn negotiate_h264(offered: &FmtPValue, local: &FmtPValue) -> Result<FmtPValue, String> {
+    let mode = |fmtp: &FmtPValue| fmtp.get("packetization-mode").unwrap_or("0").to_string();
+    if mode(offered) != mode(local) {
+        return Err(format!("packetization-mode {} not supported", mode(offered)))
+    }
+
+    // Baseline profile, level 1.0, if not set
+    let profile_level_id = |fmtp: &FmtPValue| -> Result<(u8, u8, u8), String> {
+        let id = fmtp.get("profile-level-id").unwrap_or("42000a").to_lowercase();
+        let byte = |i: usize| id.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok());
+
+        match (id.len(), byte(0), byte(2), byte(4)) {
+            (6, Some(idc), Some(iop), Some(level)) => Ok((idc, iop, level)),
+            _ => Err(format!("invalid profile-level-id {}", id)),
+        }
+    };
+    let (offered_idc, offered_iop, offered_level) = profile_level_id(offered)?;
+    let (local_idc, local_iop, local_level) = profile_level_id(local)?;
+    let offered_profile = H264Profile::new(offered_idc, offered_iop);
+    if !offered_profile.is_compatible(&H264Profile::new(local_idc, local_iop)) {
+        return Err(format!("profile {:?} not supported", offered_profile))
+    }
+
+    let asymmetry = is_set(offered, "level-asymmetry-allowed") && is_set(local, "level-asymmetry-allowed");
+    let level = if asymmetry || local_level < offered_level { local_level } else { offered_level };
+
+    let mut answer = FmtPValue::new(offered.format);
+    if asymmetry {
+        answer.set("level-asymmetry-allowed", "1");
+    }
+    answer.set("packetization-mode", &mode(offered));
+    answer.set("profile-level-id", &format!("{:02x}{:02x}{:02x}", offered_idc, offered_iop, level));
+
+    Ok(answer)
+}
+
+/// VP9 parameters (RFC 9628, section 6). The profile must be the same.
+fn negotiate_vp9(offered: &FmtPValue, local: &FmtPValue) -> Result<FmtPValue, String> {
+    let profile_id = |fmtp: &FmtPValue| fmtp.get("profile-id").unwrap_or("0").to_string();
+    if profile_id(offered) != profile_id(local) {
+        return Err(format!("profile-id {} not supported", profile_id(offered)))
+    }
+
+    let mut answer = FmtPValue::new(offered.format);
+    if offered.get("profile-id").is_some() {
+        answer.set("profile-id", &profile_id(offered));
+    }
+
+    Ok(answer)
+}
@@ -2,21 +2,49 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::{thread, time};
 use convo::member::Member;
-use sdp::{SessionDescription};
+use convo::session_negotiation::SessionError;
+use sdp::SessionDescription;
+use offer_answer::LocalParams;
+use ports::PortAllocator;
+use ice::InterfaceFilter;
 
 pub struct Conferences {
     pub by_name: Mutex<HashMap<String, Arc<Conference>>>,
+    // Parameters of the platform, handed to every new conference
+    local_params: LocalParams,
+    // Media ports of all the members, of all the conferences
+    ports: Arc<PortAllocator>,
+    // Interfaces the members' candidates are gathered on
+    interfaces: InterfaceFilter,
 }
 
 impl Conferences {
     pub fn init() -> Conferences {
+        Conferences::with_params(LocalParams::new())
+    }
+
+    pub fn with_params(local_params: LocalParams) -> Conferences {
         let m = HashMap::new();
 
         Conferences {
             by_name: Mutex::new(m),
+            local_params: local_params,
+            ports: Arc::new(PortAllocator::default()),
+            interfaces: InterfaceFilter::new(),
         }
     }
 
+    /// Sets the allocator of the members' media ports, e.g. of the range
+    /// open in the firewall.
+    pub fn set_port_allocator(&mut self, ports: PortAllocator) {
+        self.ports = Arc::new(ports);
+    }
+
+    /// Sets which interfaces the members' candidates are gathered on.
+    pub fn set_interface_filter(&mut self, interfaces: InterfaceFilter) {
+        self.interfaces = interfaces;
+    }
+
     pub fn new_convo(&self, id: &str) -> Arc<Conference> {
         if self.by_name.lock().unwrap().contains_key(id) {
             return self.by_name.lock().unwrap().get(id).unwrap().clone();
@@ -28,6 +56,9 @@ impl Conferences {
             id: id.to_string(),
             members: Arc::new(Mutex::new(HashMap::new())),
             sdp: Mutex::new(None),
+            local_params: self.local_params.clone(),
+            ports: self.ports.clone(),
+            interfaces: self.interfaces.clone(),
         };
 
         self.by_name.lock().unwrap().insert(id.to_string(), Arc::new(convo));
@@ -49,49 +80,62 @@ pub struct Conference {
     // SDP bound to the conference. The first member to arrive sets
     // sets the SDP which the other member will have to accept.
     sdp: Mutex<Option<SessionDescription>>,
+    // Parameters put in the answers to the members
+    local_params: LocalParams,
+    ports: Arc<PortAllocator>,
+    interfaces: InterfaceFilter,
 }
 
 impl Conference {
 
-    pub fn add_member(&self, member: Member) -> Option<SessionDescription> {
+    /// Adds `member`, answering its SDP. Fails if it can't be negotiated,
+    /// or if no ports are left for its media.
+    pub fn add_member(&self, member: Member) -> Result<SessionDescription, SessionError> {
         let mut mutex = self.sdp.lock().unwrap();
 
-        member.init_session();
+        member.set_port_allocator(self.ports.clone());
+        member.set_interface_filter(self.interfaces.clone());
 
-        let sdp_answer_to_ret;
-        let var = match *mutex {
+        let sdp_answer = match *mutex {
             // If there's still no SDP bound to this convo, this is
             // the one
             Some(ref convo) => { 
                 debug!("Negotiating SDP with the conference");
-                member.negotiate_session(Some(convo.clone()));
-                let sdp_answer = member.get_session_answer();
+                member.negotiate_session(Some(convo.clone()), &self.local_params)?;
 
-                sdp_answer_to_ret = Some(sdp_answer.clone());
-
-                Some(sdp_answer.clone())
+                member.get_session_answer()
             },
             None => {
-                // TODO Even though this is the first SDP, it still
-                //      needs to be negotiated with the platform
+                // The first SDP is negotiated with the codecs the
+                // platform supports
                 debug!("Negotiating SDP with the platform");
-                member.negotiate_session(None);
+                member.negotiate_session(None, &self.local_params)?;
                 let sdp_answer = member.get_session_answer();
 
-                sdp_answer_to_ret = Some(sdp_answer.clone());
-
                 /* Start engine, this is the first bound SDP */
                 self.process_engine();
 
-                Some(sdp_answer.clone())
+                sdp_answer
             },
         };
 
-        *mutex = sdp_answer_to_ret;
+        member.init_session();
+
+        *mutex = Some(sdp_answer.clone());
 
         self.members.lock().unwrap().insert(member.id.clone(), Arc::new(member));
 
-        var
+        Ok(sdp_answer)
+    }
+
+    /// Removes member `id`, tearing its session down.
+    pub fn remove_member(&self, id: &str) -> Option<Arc<Member>> {
+        let member = self.members.lock().unwrap().remove(id);
+        if let Some(ref member) = member {
+            member.close();
+        }
+
+        member
     }
 
     pub fn get_member(&self, id: &str) -> Option<Arc<Member>>  {
@@ -140,7 +184,7 @@ impl Conference {
     }
 }
 
-fn sum_payload(payload1: [u8; 3840], payload2: [u8; 3840]) -> [u8; 3840] {
+pub fn sum_payload(payload1: [u8; 3840], payload2: [u8; 3840]) -> [u8; 3840] {
     let mut result: [u8; 3840] = [0; 3840];
 
     for i in 0..payload1.len() {
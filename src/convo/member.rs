@@ -3,55 +3,141 @@ extern crate opus;
 extern crate byteorder;
 
 use self::uuid::Uuid;
-use std::sync::{Arc, Mutex};
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{thread, time};
-use self::opus::{Decoder, Encoder, Application, Channels};
+use self::opus::{Decoder, Encoder, Application, Channels, Bitrate};
 use self::byteorder::{ByteOrder, LittleEndian};
 
-use sdp::{SessionDescription};
+use sdp::{SessionDescription, MediaType};
+use offer_answer::LocalParams;
+use ports::PortAllocator;
+use ice::InterfaceFilter;
 use rir::rtp::{RtpPkt, RtpHeader};
-use convo::session_negotiation::{Session};
-
-struct MemberSession {
-    session: Session,
+use convo::session_negotiation::{Session, SessionError, MediaStream};
+use convo::convo::sum_payload;
+
+/// Audio of a media description. Packets received are decoded into its
+/// read payload, and the conference's mix is encoded onto its stream.
+struct AudioPipeline {
+    media_idx: usize,
+    stream_id: String,
+    sending: bool,
+    decoder: Mutex<Decoder>,
+    encoder: Mutex<Encoder>,
     r_payload: Mutex<Vec<u8>>,
-    w_payload: Mutex<Vec<u8>>,
     init_packet: Mutex<Option<RtpPkt>>,
     counter: Mutex<u16>,
     ts: Mutex<u32>,
 }
 
-impl MemberSession {
-    fn read_and_decode(&self, decoder: &mut opus::Decoder) -> Option<[u8; 3840]> {
-        let mut buffer: [u8; 3840] = [0; 3840];
+/// Video of a media description. Video isn't mixed, so packets received
+/// are only accounted for.
+struct VideoPipeline {
+    media_idx: usize,
+    received: Mutex<u64>,
+}
 
-        let rtp_pkt = self.read_audio();
-        let mut tmp_buffer: Vec<i16> = vec![0; 1920];
+enum MediaPipeline {
+    Audio(AudioPipeline),
+    Video(VideoPipeline),
+}
+
+impl MediaPipeline {
+    fn new(stream: &MediaStream) -> Option<MediaPipeline> {
+        match stream.media_type {
+            MediaType::AUDIO => Some(MediaPipeline::Audio(AudioPipeline {
+                media_idx: stream.media_idx,
+                stream_id: stream.stream_id.clone(),
+                sending: stream.sending,
+                decoder: Mutex::new(Decoder::new(48000, Channels::Stereo).unwrap()),
+                encoder: Mutex::new(Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap()),
+                r_payload: Mutex::new(Vec::new()),
+                init_packet: Mutex::new(None),
+                counter: Mutex::new(0),
+                ts: Mutex::new(0),
+            })),
+            MediaType::VIDEO => Some(MediaPipeline::Video(VideoPipeline {
+                media_idx: stream.media_idx,
+                received: Mutex::new(0),
+            })),
+            _ => None,
+        }
+    }
+}
 
-        debug!("Read from ssrc {} csrc {:?} seq {} ts {}...", rtp_pkt.header.ssrc, rtp_pkt.header.csrc, rtp_pkt.header.seq_number, rtp_pkt.header.timestamp);
+impl AudioPipeline {
+    /// Has the encoder keep to the `bitrate` negotiated, within what Opus
+    /// supports (RFC 6716, section 2.1.1), or pick its own if there's none.
+    fn set_bitrate(&self, bitrate: Option<u64>) {
+        let value = match bitrate {
+            Some(bps) => Bitrate::Bits(cmp::max(6000, cmp::min(bps, 510000)) as i32),
+            None => Bitrate::Auto,
+        };
 
-        if rtp_pkt.payload.len() == 0 {
-            return None
+        match self.encoder.lock().unwrap().set_bitrate(value) {
+            Ok(_) => debug!("Encoding media {} at {:?}", self.media_idx, value),
+            Err(e) => warn!("Failed to set bitrate of media {}: {:?}", self.media_idx, e),
         }
+    }
+
+    fn decode(&self, rtp_pkt: RtpPkt) {
+        let mut buffer: [u8; 3840] = [0; 3840];
+        let mut tmp_buffer: Vec<i16> = vec![0; 1920];
 
         debug!("Before decoding1... {}", rtp_pkt.payload.len());
-        let size = decoder.decode(&rtp_pkt.payload, &mut tmp_buffer, false).unwrap();
+        let size = match self.decoder.lock().unwrap().decode(&rtp_pkt.payload, &mut tmp_buffer, false) {
+            Ok(size) => size,
+            Err(e) => {
+                debug!("Failed to decode packet of media {}: {:?}", self.media_idx, e);
+                return;
+            },
+        };
         debug!("After decoding... {}", size);
         debug!("After decoding2... {}", tmp_buffer.len());
 
         convert_i16_to_u8(&mut tmp_buffer, &mut buffer);
+        self.r_payload.lock().unwrap().extend_from_slice(&buffer);
 
         let mut packet_lock = self.init_packet.lock().unwrap();
         if !packet_lock.is_some() {
             *packet_lock = Some(rtp_pkt);
         }
+    }
+
+    fn read_payload(&self) -> Option<[u8; 3840]> {
+        let mut x: [u8; 3840] = [0; 3840];
+
+        let mut payload = self.r_payload.lock().unwrap();
+        if payload.len() < 3840 {
+            return None
+        }
+
+        let first = (*payload).split_off(3840);
+        x.clone_from_slice(&(*payload));
+        /*  Start dropping data after 1 sec */
+        if first.len() >= 192000 {
+            debug!("Deleting payload...");
+            *payload = Vec::new();
+        } else {
+            *payload = first;
+        }
 
-        return Some(buffer)
+        Some(x)
     }
 
-    fn encode_and_write(&self, encoder: &mut opus::Encoder, raw_payload: [u8; 3840]) {
+    fn encode_and_write(&self, session: &Session, raw_payload: [u8; 3840]) {
         let mut rtp_pkt;
         let packet_lock = self.init_packet.lock().unwrap();
+        let ssrc = match session.local_ssrc(self.media_idx) {
+            Some(ssrc) => ssrc,
+            None => {
+                debug!("No source declared on media {}, not writing", self.media_idx);
+                return;
+            },
+        };
         if packet_lock.is_some() {
             rtp_pkt = RtpPkt {
                 header: RtpHeader {
@@ -63,8 +149,8 @@ impl MemberSession {
                     payload_type: packet_lock.as_ref().unwrap().header.payload_type,
                     seq_number: packet_lock.as_ref().unwrap().header.seq_number + *(self.counter.lock().unwrap()),
                     timestamp: packet_lock.as_ref().unwrap().header.timestamp + *(self.ts.lock().unwrap()),
-                    ssrc: packet_lock.as_ref().unwrap().header.ssrc,
-                    csrc: packet_lock.as_ref().unwrap().header.csrc.clone(),
+                    ssrc: ssrc,
+                    csrc: vec![],
                 },
                 payload: vec![],
             };
@@ -84,7 +170,7 @@ impl MemberSession {
 
         debug!("Before encoding1... {}", tmp_buffer.len());
 
-        let size = encoder.encode(&tmp_buffer, &mut encoded).unwrap();
+        let size = self.encoder.lock().unwrap().encode(&tmp_buffer, &mut encoded).unwrap();
 
         debug!("After encoding1... {}", encoded.len());
 
@@ -93,42 +179,124 @@ impl MemberSession {
 
         debug!("Writing packet with payload of size {}", rtp_pkt.payload.len());
 
-        self.write_audio(&rtp_pkt);
+        session.write(&self.stream_id, &rtp_pkt);
     }
+}
 
-    fn write_audio(&self, rtp_pkt: &RtpPkt) {
-        let sessions_map = self.session.media_sessions.read().unwrap();
-        // TODO(tlam): Need to fix when we allocate more than 1 candidate, since this loop won't
-        // work
-        for (_, rtp_session) in sessions_map.iter() {
-            rtp_session.write(rtp_pkt);
+struct MemberSession {
+    session: Session,
+    // Media pipelines, by mid
+    pipelines: RwLock<HashMap<String, MediaPipeline>>,
+    w_payload: Mutex<Vec<u8>>,
+    // Tells the workers to stop, as the member leaves
+    closed: AtomicBool,
+}
+
+impl MemberSession {
+    /// Creates a pipeline for each media accepted, keeping the ones already
+    /// there across re-offers. Encoders follow the bitrate last negotiated.
+    fn update_pipelines(&self) {
+        let mut pipelines = self.pipelines.write().unwrap();
+
+        for stream in self.session.media_streams() {
+            if !pipelines.contains_key(&stream.mid) {
+                match MediaPipeline::new(&stream) {
+                    Some(pipeline) => {
+                        debug!("Created {:?} pipeline for mid {}", stream.media_type, stream.mid);
+                        pipelines.insert(stream.mid.clone(), pipeline);
+                    },
+                    None => debug!("No pipeline for media {} of type {:?}", stream.mid, stream.media_type),
+                }
+            }
+
+            if let Some(&MediaPipeline::Audio(ref audio)) = pipelines.get(&stream.mid) {
+                audio.set_bitrate(stream.bitrate);
+            }
         }
     }
 
-    fn read_audio(&self) -> RtpPkt {
-        let mut rtp_pkt = RtpPkt {
-            header: RtpHeader {
-                version: 0,
-                padding: 0,
-                ext: 0,
-                cc: 0,
-                marker: 0,
-                payload_type: 0,
-                seq_number: 0,
-                timestamp: 0,
-                ssrc: 0,
-                csrc: vec![],
-            }, 
-            payload: vec![],
-        };
-
+    /// Reads a packet from each stream, and hands it to the pipeline of
+    /// the media it belongs to.
+    fn read_media(&self) {
         let sessions_map = self.session.media_sessions.read().unwrap();
 
-        for (_, rtp_session) in sessions_map.iter() {
+        for (stream_id, rtp_session) in sessions_map.iter() {
+            let mut rtp_pkt = RtpPkt {
+                header: RtpHeader {
+                    version: 0,
+                    padding: 0,
+                    ext: 0,
+                    cc: 0,
+                    marker: 0,
+                    payload_type: 0,
+                    seq_number: 0,
+                    timestamp: 0,
+                    ssrc: 0,
+                    csrc: vec![],
+                }, 
+                payload: vec![],
+            };
+
             rtp_session.read(&mut rtp_pkt);
+
+            if rtp_pkt.payload.len() == 0 {
+                continue;
+            }
+
+            debug!("Read from ssrc {} csrc {:?} seq {} ts {}...", rtp_pkt.header.ssrc, rtp_pkt.header.csrc, rtp_pkt.header.seq_number, rtp_pkt.header.timestamp);
+
+            let mid = match self.session.demux(stream_id, &rtp_pkt.header).and_then(|i| self.session.mid(i)) {
+                Some(mid) => mid,
+                None => {
+                    debug!("Dropping packet of unknown ssrc {}", rtp_pkt.header.ssrc);
+                    continue;
+                },
+            };
+
+            if let Some(track) = self.session.remote_track(rtp_pkt.header.ssrc) {
+                debug!("Read ssrc {} of track {:?}", rtp_pkt.header.ssrc, track.msid);
+            }
+
+            match self.pipelines.read().unwrap().get(&mid) {
+                Some(&MediaPipeline::Audio(ref audio)) => audio.decode(rtp_pkt),
+                Some(&MediaPipeline::Video(ref video)) => {
+                    *video.received.lock().unwrap() += 1;
+                    debug!("Received video packet of ssrc {} on media {}", rtp_pkt.header.ssrc, video.media_idx);
+                },
+                None => debug!("No pipeline for mid {}", mid),
+            }
+        }
+    }
+
+    /// Audio read on every audio media, mixed together.
+    fn read_payload(&self) -> Option<[u8; 3840]> {
+        let pipelines = self.pipelines.read().unwrap();
+
+        let mut mix = None;
+        for pipeline in pipelines.values() {
+            if let MediaPipeline::Audio(ref audio) = *pipeline {
+                if let Some(payload) = audio.read_payload() {
+                    mix = Some(match mix {
+                        Some(mix) => sum_payload(mix, payload),
+                        None => payload,
+                    });
+                }
+            }
         }
 
-        rtp_pkt
+        mix
+    }
+
+    /// Writes the audio of the conference on every audio media we send.
+    fn write_payload(&self, raw_payload: [u8; 3840]) {
+        for pipeline in self.pipelines.read().unwrap().values() {
+            match *pipeline {
+                MediaPipeline::Audio(ref audio) if audio.sending => {
+                    audio.encode_and_write(&self.session, raw_payload);
+                },
+                _ => {},
+            }
+        }
     }
 }
 
@@ -151,19 +319,18 @@ impl MemberWorker {
         }
     }
 
-    fn start_worker(self) {
+    fn start_worker(self) -> thread::JoinHandle<()> {
         match self.wtype {
             WorkerType::ReadWorker => self.start_read_worker(),
             WorkerType::WriteWorker => self.start_write_worker(),
         }
     }
 
-    fn start_read_worker(self) {
+    fn start_read_worker(self) -> thread::JoinHandle<()> {
         thread::spawn(move || {
-            let mut decoder = Decoder::new(48000, Channels::Stereo).unwrap();
             let mut count = 0;
 
-            loop {
+            while !self.msess.closed.load(Ordering::SeqCst) {
                 // TODO(tlam): Read audio in session into the buffer
                 if count < 100 {
                     thread::sleep(time::Duration::from_millis(5));
@@ -171,25 +338,14 @@ impl MemberWorker {
                     debug!("Audio worker waiting...");
                 }
 
-                debug!("Audio worker...1");
-                let some_payload = self.msess.read_and_decode(&mut decoder);
-                debug!("Audio worker...2");
-
-                let mut payload = self.msess.r_payload.lock().unwrap();
-                debug!("Audio worker...3");
-                if some_payload.is_some() {
-                    (*payload).extend_from_slice(&some_payload.unwrap());
-                    //debug!("Audio worker payload... {:?}", (*payload));
-                }
+                self.msess.read_media();
             }
-        });
+        })
     }
 
-    fn start_write_worker(self) {
+    fn start_write_worker(self) -> thread::JoinHandle<()> {
         thread::spawn(move || {
-            let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
-
-            loop {
+            while !self.msess.closed.load(Ordering::SeqCst) {
                 // TODO(tlam): Write audio passed into the buffer into session
                 thread::sleep(time::Duration::from_millis(10));
 
@@ -204,9 +360,9 @@ impl MemberWorker {
                 let first = (*payload).split_off(3840);
                 slice.clone_from_slice(&(*payload));
                 *payload = first;
-                self.msess.encode_and_write(&mut encoder, slice);
+                self.msess.write_payload(slice);
             }
-        });
+        })
     }
 }
 
@@ -214,6 +370,7 @@ pub struct Member {
     pub id: String,
     pub sdp: SessionDescription,
     member_session: Arc<MemberSession>,
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
 }
 
 impl Member {
@@ -229,38 +386,18 @@ impl Member {
             sdp: sdp,
             member_session: Arc::new(MemberSession {
                 session: session,
-                r_payload: Mutex::new(Vec::new()),
+                pipelines: RwLock::new(HashMap::new()),
                 w_payload: Mutex::new(Vec::new()),
-                init_packet: Mutex::new(None),
-                counter: Mutex::new(0),
-                ts: Mutex::new(0),
+                closed: AtomicBool::new(false),
             }),
+            workers: Mutex::new(vec![]),
         };
 
         member
     }
 
     pub fn get_read_payload(&self) -> Option<[u8; 3840]> {
-        let member_session = self.member_session.clone();
-
-        let mut x: [u8; 3840] = [0; 3840];
-
-        let mut payload = member_session.r_payload.lock().unwrap();
-        if payload.len() < 3840 {
-            return None
-        }
-
-        let first = (*payload).split_off(3840);
-        x.clone_from_slice(&(*payload));
-        /*  Start dropping data after 1 sec */
-        if first.len() >= 192000 {
-            debug!("Deleting payload...");
-            *payload = Vec::new();
-        } else {
-            *payload = first;
-        }
-
-        Some(x)
+        self.member_session.read_payload()
     }
 
     pub fn set_write_payload(&self, payload: [u8; 3840]) {
@@ -273,25 +410,62 @@ impl Member {
     pub fn init_session(&self) {
         //self.session.init(Box::new(self.set_default_session));
 
-        // TODO(tlam): Remove logic from init function
-        self.member_session.session.process_offer();
-
+        let mut workers = self.workers.lock().unwrap();
         let rworker = MemberWorker::new(WorkerType::ReadWorker,
                                         self.member_session.clone());
-        rworker.start_worker();
+        workers.push(rworker.start_worker());
         let wworker = MemberWorker::new(WorkerType::WriteWorker,
                                         self.member_session.clone());
-        wworker.start_worker();
+        workers.push(wworker.start_worker());
     }
 
-    pub fn negotiate_session(&self, base_sdp: Option<SessionDescription>) {
+    pub fn negotiate_session(&self, base_sdp: Option<SessionDescription>, local_params: &LocalParams) -> Result<(), SessionError> {
         // Pass base SDP and negotiate with session's offer
-        let sdp_answer = self.member_session.session.negotiate_with_base_sdp(base_sdp);
+        if let Err(e) = self.member_session.session.negotiate_with_base_sdp(base_sdp, local_params) {
+            warn!("Failed to negotiate session: {}", e.to_string());
+            return Err(e);
+        }
+
+        // Streams are only created now that it's known what was bundled
+        if let Err(e) = self.member_session.session.process_offer() {
+            warn!("Failed to create the streams of the session: {}", e.to_string());
+            self.close();
+            return Err(e);
+        }
 
         // Now that we have the answer we can process it
-        self.member_session.session.process_answer();
+        if let Err(e) = self.member_session.session.process_answer() {
+            warn!("Failed to start the media of the session: {}", e.to_string());
+            self.close();
+            return Err(e);
+        }
 
-        sdp_answer
+        self.member_session.update_pipelines();
+
+        Ok(())
+    }
+
+    /// Sets the allocator the member's media ports are taken from.
+    pub fn set_port_allocator(&self, ports: Arc<PortAllocator>) {
+        self.member_session.session.set_port_allocator(ports);
+    }
+
+    /// Sets which interfaces the member's candidates are gathered on.
+    pub fn set_interface_filter(&self, interfaces: InterfaceFilter) {
+        self.member_session.session.set_interface_filter(interfaces);
+    }
+
+    /// Tears the member's session down, stopping its workers and releasing
+    /// its ports.
+    pub fn close(&self) {
+        debug!("Closing member {}", self.id);
+        self.member_session.closed.store(true, Ordering::SeqCst);
+        // Closing the session also wakes the read worker up
+        self.member_session.session.close();
+
+        for worker in self.workers.lock().unwrap().drain(..) {
+            let _ = worker.join();
+        }
     }
 
     pub fn get_session_answer(&self) -> SessionDescription {
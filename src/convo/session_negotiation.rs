@@ -1,19 +1,60 @@
-use std::collections::HashMap;
+extern crate rand;
+
+use std::collections::{HashMap, HashSet};
 use std::net::{UdpSocket, SocketAddr};
 use std::boxed::Box;
 use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use self::rand::Rng;
 
-use rir::rtp::{RtpSession, RirHandler};
+use rir::rtp::{RtpSession, RirHandler, RtpHeader, RtpPkt};
 use rir::handlers::{CallbackType};
-use sdp::{SessionDescription, Attr, CandidateValue};
+use sdp::{SessionDescription, Attr, CandidateValue, MsidValue};
+use offer_answer::LocalParams;
 use convo::member::{Member};
 use ice;
 use sdp;
+use ports::PortAllocator;
+use transport::{Transport, Checker};
+use offer_answer;
 
 enum SessionState {
     CheckingOffer,
 }
 
+/// Why a session couldn't be set up.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionError {
+    /// The offer can't be answered, e.g. nothing in it is supported.
+    Negotiation(String),
+    /// No ports are left for the media of the offer.
+    Ports(String),
+    /// No candidates could be gathered for the media, e.g. as no address
+    /// of the host's interfaces passes the filter.
+    Candidates(String),
+}
+
+impl From<ice::GatherError> for SessionError {
+    fn from(e: ice::GatherError) -> SessionError {
+        match e {
+            ice::GatherError::Ports(_) => SessionError::Ports(e.to_string()),
+            ice::GatherError::NoAddress => SessionError::Candidates(e.to_string()),
+            ice::GatherError::NoStream(_) => SessionError::Negotiation(e.to_string()),
+        }
+    }
+}
+
+impl ToString for SessionError {
+    fn to_string(&self) -> String {
+        match *self {
+            SessionError::Negotiation(ref e) => format!("negotiation failed: {}", e),
+            SessionError::Ports(ref e) => format!("no ports for the media: {}", e),
+            SessionError::Candidates(ref e) => format!("no candidates for the media: {}", e),
+        }
+    }
+}
+
 pub struct Session {
     offer_sdp: RwLock<SessionDescription>,
     base_sdp: RwLock<Option<SessionDescription>>,
@@ -25,58 +66,131 @@ pub struct Session {
      * be a better way.
      */
     sdp_to_ice: RwLock<Vec<String>>,
+    // Stream of each media carrying a transport, by mid, kept across
+    // re-offers
+    mid_streams: RwLock<HashMap<String, String>>,
     pub media_sessions: Arc<RwLock<HashMap<String, RtpSession>>>,
+    // Sockets of the streams, relaying their media to the media sessions
+    transports: Arc<RwLock<HashMap<String, Transport>>>,
+    // Sends the checks of the agent, if full
+    checker: Mutex<Option<Checker>>,
+    // Demultiplexers of the media bundled on each stream, by stream_id
+    demuxers: RwLock<HashMap<String, MediaDemux>>,
+    // Streams on which RTP and RTCP are multiplexed
+    rtcp_muxed: RwLock<HashSet<String>>,
+    // Canonical name and media stream of the sources we send
+    cname: String,
+    msid_stream: String,
+    // Source we send on each media description, by index in the answer
+    local_sources: RwLock<HashMap<usize, (u32, MsidValue)>>,
+    // Tracks declared by the offerer, by SSRC
+    remote_tracks: RwLock<HashMap<u32, RemoteTrack>>,
     set_session: Option<Arc<Fn(&mut Member) + Send + Sync>>,
 }
 
+/// A media description accepted in the answer, and the stream carrying it.
+#[derive(Clone, Debug)]
+pub struct MediaStream {
+    pub media_idx: usize,
+    pub mid: String,
+    pub media_type: sdp::MediaType,
+    pub stream_id: String,
+    pub sending: bool,
+    /// Bits per second negotiated for the media, if limited.
+    pub bitrate: Option<u64>,
+}
+
+/// A source declared by the offerer with "a=ssrc".
+#[derive(Clone, Debug)]
+pub struct RemoteTrack {
+    pub media_idx: usize,
+    pub msid: Option<MsidValue>,
+}
+
+/// Tells which media description the packets received on a (possibly
+/// bundled) transport belong to. Known SSRCs are used first, falling back to
+/// the payload type, which is unique within a BUNDLE group (RFC 8843,
+/// section 9.1). The SSRC is then learned.
+struct MediaDemux {
+    ssrcs: HashMap<u32, usize>,
+    payload_types: HashMap<u8, usize>,
+}
+
+impl MediaDemux {
+    fn new() -> MediaDemux {
+        MediaDemux {
+            ssrcs: HashMap::new(),
+            payload_types: HashMap::new(),
+        }
+    }
+
+    fn add_media(&mut self, media_idx: usize, media: &sdp::MediaDescription, offer_media: &sdp::MediaDescription) {
+        for &(ssrc, _) in offer_media.sources().iter() {
+            self.ssrcs.insert(ssrc, media_idx);
+        }
+
+        for fmt in media.media.fmt.iter() {
+            if let Ok(pt) = fmt.parse::<u8>() {
+                self.payload_types.insert(pt, media_idx);
+            }
+        }
+    }
+
+    fn demux(&mut self, header: &RtpHeader) -> Option<usize> {
+        if let Some(media_idx) = self.ssrcs.get(&header.ssrc) {
+            return Some(*media_idx);
+        }
+
+        let media_idx = match self.payload_types.get(&header.payload_type) {
+            Some(media_idx) => *media_idx,
+            None => return None,
+        };
+
+        debug!("Learned ssrc {} for media {}", header.ssrc, media_idx);
+        self.ssrcs.insert(header.ssrc, media_idx);
+
+        Some(media_idx)
+    }
+}
+
 struct SessionRtp {
     stream_id: String,
     component_id: u16,
-    ice: Arc<Mutex<ice::Agent>>,
-    local_candidate: ice::Candidate,
 }
 
 impl RirHandler for SessionRtp {
     fn handle_event(&self, callback_type: CallbackType) {
-        debug!("Received callback {:?} with component_id {}!", callback_type, self.component_id);
-
-        match callback_type {
-            CallbackType::USE_CANDIDATE(addr) => {
-                self.ice.lock().unwrap().add_pair_candidate(&self.stream_id, &self.component_id, self.local_candidate.port, addr.port());
-                // TODO(tlam): Move this inside ice.rs, so this check happens automatically when a
-                // stream is completed
-                self.ice.lock().unwrap().set_ice_complete();
-            }
-        }
+        // Checks never reach rir, the transport hands them to the agent
+        debug!("Ignoring callback {:?} of stream {} with component_id {}", callback_type, self.stream_id, self.component_id);
     }
 }
 
 #[derive(Clone)]
 struct SessionIce {
-    media_sessions: Arc<RwLock<HashMap<String, RtpSession>>>,
+    transports: Arc<RwLock<HashMap<String, Transport>>>,
 }
 
 // TODO(tlam): Get callbacks from ICE lib and eliminate / deallocate unused sessions.
 impl ice::Handler for SessionIce {
     fn handle_callback(&mut self, stream_id: &str, peer: ice::Candidate) {
-        debug!("Received ICE callback for stream_id {} and media_sessions {}", stream_id, self.media_sessions.read().unwrap().len());
-        let media_lock = self.media_sessions.read().unwrap();
-        let media_session = media_lock.get(stream_id);
-
-        match media_session {
-            Some(s) => {
-                debug!("Set member's media session for stream {}", stream_id);
-                if peer.component_id.unwrap() == 1 {
-                    debug!("Set member's rtp peer to port {} for stream {}", peer.port, stream_id);
-                    s.change_transport(SocketAddr::new(peer.conn, peer.port));
-                }
-                if peer.component_id.unwrap() == 2 {
-                    debug!("Set member's rtcp peer to port {} for stream {}", peer.port, stream_id);
-                    s.change_rtcp_transport(SocketAddr::new(peer.conn, peer.port));
-                }
+        debug!("Received ICE callback for stream_id {} and transports {}", stream_id, self.transports.read().unwrap().len());
+
+        // Offered candidates are resolved when added to the agent
+        let peer_addr = match peer.socket_addr() {
+            Some(peer_addr) => peer_addr,
+            None => {
+                warn!("Peer candidate {} of stream {} has no address", peer.conn.to_string(), stream_id);
+                return;
+            },
+        };
+
+        match self.transports.read().unwrap().get(stream_id) {
+            Some(transport) => {
+                debug!("Set member's peer of component {} to {} for stream {}", peer.component_id.unwrap(), peer_addr, stream_id);
+                transport.set_peer(peer.component_id.unwrap(), peer_addr);
             },
             None => {
-                info!("No media found for stream_id {}", stream_id);
+                info!("No transport found for stream_id {}", stream_id);
             },
         }
     }
@@ -86,10 +200,10 @@ impl Session {
     // TODO(tlam): Do NOT assume ICE support
     pub fn new(offer_sdp: SessionDescription) -> Session {
 
-        let media_sessions = Arc::new(RwLock::new(HashMap::new()));
+        let transports = Arc::new(RwLock::new(HashMap::new()));
 
         let session_ice = SessionIce {
-            media_sessions: media_sessions.clone(),
+            transports: transports.clone(),
         };
 
         let ice = ice::Agent::new(Box::new(session_ice));
@@ -100,7 +214,16 @@ impl Session {
             state: SessionState::CheckingOffer,
             ice: Arc::new(Mutex::new(ice)),
             sdp_to_ice: RwLock::new(Vec::new()),
-            media_sessions: media_sessions,
+            mid_streams: RwLock::new(HashMap::new()),
+            media_sessions: Arc::new(RwLock::new(HashMap::new())),
+            transports: transports,
+            checker: Mutex::new(None),
+            demuxers: RwLock::new(HashMap::new()),
+            rtcp_muxed: RwLock::new(HashSet::new()),
+            cname: random_token(16),
+            msid_stream: random_token(16),
+            local_sources: RwLock::new(HashMap::new()),
+            remote_tracks: RwLock::new(HashMap::new()),
             set_session: None,
         };
 
@@ -116,16 +239,73 @@ impl Session {
         self.set_session = Some(set_session)
     }
 
-    pub fn process_offer(&self) {
+    /// Creates the streams of the offer, gathering their candidates. Fails if
+    /// the ports for them can't be allocated. On re-offers, media keep the
+    /// stream they had, and streams no media carries anymore are removed.
+    pub fn process_offer(&self) -> Result<(), SessionError> {
+        // Media bundled in the answer share a single stream, the one of the
+        // media whose transport is used
+        let (owners, muxed, rejected) = match *self.answer_sdp.read().unwrap() {
+            Some(ref answer) => {
+                (answer.transport_owners(),
+                 answer.media.iter().map(|m| m.rtcp_mux()).collect(),
+                 answer.media.iter().map(|m| m.is_rejected()).collect())
+            },
+            None => {
+                let nr_media = self.offer_sdp.read().unwrap().media.len();
+                ((0..nr_media).collect(), vec![false; nr_media], vec![false; nr_media])
+            },
+        };
+
+        let mut stream_ids: Vec<Option<String>> = vec![None; owners.len()];
+
+        // The same credentials are used by the peer on all the streams
+        let offer_sdp = self.offer_sdp.read().unwrap();
+        let remote_credentials = (0..offer_sdp.media.len())
+            .filter(|i| !rejected[*i])
+            .filter_map(|i| offer_sdp.ice_credentials(i))
+            .next();
+        match remote_credentials {
+            Some(credentials) => self.ice.lock().unwrap().set_remote_credentials(credentials),
+            None => warn!("No ICE credentials in the offer"),
+        }
+
+        let mut mid_streams = self.mid_streams.write().unwrap();
+        let mut previous_streams: HashMap<String, String> = mid_streams.drain().collect();
+
         // Create media stream and gather candidates for each stream
-        for media in self.offer_sdp.read().unwrap().media.iter() {
+        for (i, media) in offer_sdp.media.iter().enumerate() {
+            // No transport for rejected media
+            if owners.get(i) != Some(&i) || rejected[i] {
+                continue;
+            }
+
+            let mid = media.mid().map_or(i.to_string(), |mid| mid.to_string());
+            if let Some(stream_id) = previous_streams.remove(&mid) {
+                debug!("Media {} kept on stream {}", mid, stream_id);
+                stream_ids[i] = Some(stream_id.clone());
+                mid_streams.insert(mid, stream_id);
+                continue;
+            }
+
             let mut ice = self.ice.lock().unwrap();
-            let stream_id = ice.add_stream();
+            // A single component when RTCP is multiplexed with RTP
+            let stream_id = if muxed[i] {
+                let stream_id = ice.add_stream(1);
+                ice.gather_candidates(&stream_id, &ice::RTP_COMPONENT_ID)?;
+                self.rtcp_muxed.write().unwrap().insert(stream_id.clone());
+
+                stream_id
+            } else {
+                let stream_id = ice.add_stream(2);
+                ice.gather_candidates(&stream_id, &ice::RTP_COMPONENT_ID)?;
+                ice.gather_candidates(&stream_id, &ice::RTCP_COMPONENT_ID)?;
 
-            ice.gather_candidates(&stream_id, &ice::RTP_COMPONENT_ID);
-            ice.gather_candidates(&stream_id, &ice::RTCP_COMPONENT_ID);
+                stream_id
+            };
 
-            self.sdp_to_ice.write().unwrap().push(stream_id.clone());
+            stream_ids[i] = Some(stream_id.clone());
+            mid_streams.insert(mid, stream_id.clone());
 
             for attr in media.attrs.iter() {
                 match *attr {
@@ -136,22 +316,206 @@ impl Session {
                 }
             }
         }
+        self.lookup_names();
+
+        // E.g. rejected now, or bundled with other media
+        for (_, stream_id) in previous_streams {
+            self.remove_stream(&stream_id);
+        }
+
+        let mut sdp_to_ice = self.sdp_to_ice.write().unwrap();
+        sdp_to_ice.clear();
+        for (i, owner) in owners.iter().enumerate() {
+            match stream_ids.get(*owner) {
+                Some(&Some(ref stream_id)) => sdp_to_ice.push(stream_id.clone()),
+                _ if rejected[i] => sdp_to_ice.push(String::new()),
+                _ => {
+                    warn!("No stream for media {}", i);
+                    sdp_to_ice.push(String::new());
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the names of the offered candidates apart, as long as it
+    /// takes without holding the agent.
+    fn lookup_names(&self) {
+        let (querier, names, candidates) = {
+            let mut ice = self.ice.lock().unwrap();
+            let (querier, names) = ice.take_mdns_lookups();
+            (querier, names, ice.take_dns_lookups())
+        };
+
+        for (stream_id, component_id, candidate) in candidates {
+            let ice = self.ice.clone();
+            thread::spawn(move || {
+                let resolved = candidate.resolve();
+                ice.lock().unwrap().add_resolved_candidates(&stream_id, &component_id, &candidate, resolved);
+            });
+        }
+
+        for name in names {
+            let (ice, querier) = (self.ice.clone(), querier.clone());
+            thread::spawn(move || {
+                let ip = querier.resolve(&name);
+                ice.lock().unwrap().add_mdns_address(&name, ip);
+            });
+        }
     }
 
-    pub fn process_answer(&self) {
+    /// Removes a stream along with its media session, closing its sockets
+    /// before its ports are released.
+    fn remove_stream(&self, stream_id: &str) {
+        // Closed out of the lock, which its workers may be waiting on
+        let transport = self.transports.write().unwrap().remove(stream_id);
+        drop(transport);
+        self.media_sessions.write().unwrap().remove(stream_id);
+        self.demuxers.write().unwrap().remove(stream_id);
+        self.rtcp_muxed.write().unwrap().remove(stream_id);
+        self.ice.lock().unwrap().remove_stream(stream_id);
+    }
+
+    /// Sets the allocator the ports of the streams are taken from.
+    pub fn set_port_allocator(&self, ports: Arc<PortAllocator>) {
+        self.ice.lock().unwrap().set_port_allocator(ports);
+    }
+
+    /// Sets which interfaces the candidates of the streams are gathered on.
+    pub fn set_interface_filter(&self, interfaces: ice::InterfaceFilter) {
+        self.ice.lock().unwrap().set_interface_filter(interfaces);
+    }
+
+    /// Stops the media sessions and closes the sockets of the streams, as
+    /// the session is torn down. Their ports are released only then, not
+    /// to be allocated while still bound.
+    pub fn close(&self) {
+        *self.checker.lock().unwrap() = None;
+        // Closed out of the lock, which their workers may be waiting on
+        let transports = self.transports.write().unwrap().drain().collect::<Vec<_>>();
+        drop(transports);
+        self.media_sessions.write().unwrap().clear();
+        self.ice.lock().unwrap().release_ports();
+    }
+
+    /// Type of the media description of index `media_idx` in the answer.
+    pub fn media_type(&self, media_idx: usize) -> Option<sdp::MediaType> {
+        match *self.answer_sdp.read().unwrap() {
+            Some(ref answer) => answer.media.get(media_idx).map(|m| m.media.media.clone()),
+            None => None,
+        }
+    }
+
+    /// Index of the media description, in the answer, an RTP packet received
+    /// on `stream_id` belongs to.
+    pub fn demux(&self, stream_id: &str, header: &RtpHeader) -> Option<usize> {
+        match self.demuxers.write().unwrap().get_mut(stream_id) {
+            Some(demuxer) => demuxer.demux(header),
+            None => None,
+        }
+    }
+
+    /// Identification tag of the media description of index `media_idx` in
+    /// the answer. Media without "a=mid" are identified by their index.
+    pub fn mid(&self, media_idx: usize) -> Option<String> {
+        match *self.answer_sdp.read().unwrap() {
+            Some(ref answer) => answer.media.get(media_idx).map(|media| match media.mid() {
+                Some(mid) => mid.to_string(),
+                None => media_idx.to_string(),
+            }),
+            None => None,
+        }
+    }
+
+    /// Media descriptions accepted in the answer, with a stream to carry
+    /// them.
+    pub fn media_streams(&self) -> Vec<MediaStream> {
+        let answer_lock = self.answer_sdp.read().unwrap();
+        let answer = match *answer_lock {
+            Some(ref answer) => answer,
+            None => return vec![],
+        };
+
+        let sdp_to_ice = self.sdp_to_ice.read().unwrap();
+        answer.media.iter().enumerate()
+            .filter(|&(i, media)| !media.is_rejected() && sdp_to_ice.get(i).map_or(false, |s| !s.is_empty()))
+            .map(|(i, media)| MediaStream {
+                media_idx: i,
+                mid: media.mid().map_or(i.to_string(), |mid| mid.to_string()),
+                media_type: media.media.media.clone(),
+                stream_id: sdp_to_ice[i].clone(),
+                sending: media.is_sending(),
+                bitrate: answer.media_bitrate(i),
+            })
+            .collect()
+    }
+
+    /// SSRC we send on the media description of index `media_idx`.
+    pub fn local_ssrc(&self, media_idx: usize) -> Option<u32> {
+        self.local_sources.read().unwrap().get(&media_idx).map(|&(ssrc, _)| ssrc)
+    }
+
+    /// Writes `rtp_pkt` on the stream `stream_id`.
+    pub fn write(&self, stream_id: &str, rtp_pkt: &RtpPkt) {
+        match self.media_sessions.read().unwrap().get(stream_id) {
+            Some(rtp_session) => rtp_session.write(rtp_pkt),
+            None => debug!("No media session for stream {}", stream_id),
+        }
+    }
+
+    /// RTP header extensions agreed for the media description of index
+    /// `media_idx` in the answer, by ID.
+    pub fn header_extensions(&self, media_idx: usize) -> HashMap<u16, String> {
+        let answer_lock = self.answer_sdp.read().unwrap();
+        let answer = match *answer_lock {
+            Some(ref answer) => answer,
+            None => return HashMap::new(),
+        };
+
+        // Session-level extensions apply to every media (RFC 8285, section 5)
+        let mut extmaps = HashMap::new();
+        for attr in answer.attrs.iter() {
+            if let Attr::ExtMap { id, ref uri, .. } = *attr {
+                extmaps.insert(id, uri.to_string());
+            }
+        }
+        if let Some(media) = answer.media.get(media_idx) {
+            extmaps.extend(media.extmaps());
+        }
+
+        extmaps
+    }
+
+    /// Track the offerer declared for `ssrc`, if any.
+    pub fn remote_track(&self, ssrc: u32) -> Option<RemoteTrack> {
+        self.remote_tracks.read().unwrap().get(&ssrc).cloned()
+    }
+
+    pub fn process_answer(&self) -> Result<(), SessionError> {
         // Add final candidates gathered for each stream
 
         let mut i = 0;
+        let owners = self.answer_sdp.read().unwrap().as_ref().unwrap().transport_owners();
         // TODO(tlam): We are cloning here because there would be an immutable
         // reference to iter_mut vs the mutable reference to call
         // init_media_session
         for media in self.answer_sdp.write().unwrap().as_mut().unwrap().media.iter_mut() {
             let ref stream_id = self.sdp_to_ice.read().unwrap()[i];
 
-            //let tmp_candidates = Vec::new();
+            // Bundled media only carry candidates in the media whose
+            // transport is shared
+            if owners[i] != i || stream_id.is_empty() {
+                i += 1;
+                continue;
+            }
+
             let ice = self.ice.lock().unwrap();
-            let mut candidates = ice.get_stream_candidates(stream_id, &ice::RTP_COMPONENT_ID).unwrap().clone();/*_or(&mut tmp_candidates)*/;
-            let candidates_rtcp = ice.get_stream_candidates(stream_id, &ice::RTCP_COMPONENT_ID).unwrap();
+            let mut candidates = match ice.get_stream_candidates(stream_id, &ice::RTP_COMPONENT_ID) {
+                Some(candidates) => candidates.clone(),
+                None => return Err(SessionError::Candidates(format!("none gathered for stream {}", stream_id))),
+            };
+            let candidates_rtcp = ice.get_stream_candidates(stream_id, &ice::RTCP_COMPONENT_ID).cloned().unwrap_or(vec![]);
             for candidate_rtcp in candidates_rtcp.iter() {
                 candidates.push(candidate_rtcp.clone());
             }
@@ -168,30 +532,74 @@ impl Session {
         }
 
         i = 0;
+        let offer_media = self.offer_sdp.read().unwrap().media.clone();
         // Start new media session on the candidate
-        for _ in self.answer_sdp.read().unwrap().as_ref().unwrap().media.iter() {
+        for media in self.answer_sdp.read().unwrap().as_ref().unwrap().media.iter() {
             let ref stream_id = self.sdp_to_ice.read().unwrap()[i];
 
-            let ice = self.ice.lock().unwrap();
-            let mut rtp_candidates = ice.get_stream_candidates(stream_id, &ice::RTP_COMPONENT_ID).unwrap().clone();
-            let rtcp_candidates = ice.get_stream_candidates(stream_id, &ice::RTCP_COMPONENT_ID).unwrap();
-            if rtp_candidates.len() != rtcp_candidates.len() {
-                warn!("Different number of candidates for RTP and RTCP {}!={}", rtp_candidates.len(), rtcp_candidates.len());
+            if stream_id.is_empty() {
+                i += 1;
+                continue;
+            }
+
+            for (ssrc, msid) in offer_media[i].sources() {
+                debug!("Media {} declares ssrc {} of track {:?}", i, ssrc, msid);
+                self.remote_tracks.write().unwrap().insert(ssrc, RemoteTrack {
+                    media_idx: i,
+                    msid: msid,
+                });
             }
 
-            for it in rtp_candidates.iter().zip(rtcp_candidates.iter()) {
-                let (rtp_candidate, rtcp_candidate) = it;
-                // Start new media session on the candidates
-                debug!("Init candidate stream {}:{}", rtp_candidate.conn.to_string(), rtp_candidate.port);
-                let media_session = self.init_media_session(stream_id.to_string(), rtp_candidate, rtcp_candidate);
-                self.media_sessions.write().unwrap().insert(stream_id.to_string(), media_session);
+            self.demuxers.write().unwrap()
+                .entry(stream_id.to_string())
+                .or_insert(MediaDemux::new())
+                .add_media(i, media, &offer_media[i]);
+
+            // A single media session for all the media bundled together
+            if self.media_sessions.read().unwrap().contains_key(stream_id) {
+                i += 1;
+                continue;
+            }
+
+            let mut ice = self.ice.lock().unwrap();
+
+            // Sockets were bound as their ports were allocated
+            let rtp_conns = ice.take_sockets(stream_id, &ice::RTP_COMPONENT_ID);
+            if rtp_conns.is_empty() {
+                warn!("No socket bound for stream {}", stream_id);
+                i += 1;
+                continue;
+            }
+            let rtcp_conns = if self.rtcp_muxed.read().unwrap().contains(stream_id) {
+                vec![]
+            } else {
+                ice.take_sockets(stream_id, &ice::RTCP_COMPONENT_ID)
+            };
+
+            // Start new media session on the sockets
+            debug!("Init stream {} on {:?}", stream_id, rtp_conns.iter().map(|conn| conn.local_addr()).collect::<Vec<_>>());
+            match self.init_media_session(stream_id.to_string(), owners[i], rtp_conns, rtcp_conns) {
+                Ok(media_session) => {
+                    self.media_sessions.write().unwrap().insert(stream_id.to_string(), media_session);
+                },
+                Err(e) => warn!("Failed to start media of stream {}: {}", stream_id, e),
             }
 
             i += 1;
         }
+
+        // Full agents check the pairs of the streams now their sockets are up
+        let mut ice = self.ice.lock().unwrap();
+        let mut checker = self.checker.lock().unwrap();
+        if !ice.is_lite() && checker.is_none() {
+            ice.start(Box::new(SessionIce { transports: self.transports.clone() }));
+            *checker = Some(Checker::start(self.ice.clone(), self.transports.clone()));
+        }
+
+        Ok(())
     }
 
-    pub fn negotiate_with_base_sdp(&self, base_sdp: Option<SessionDescription>) {
+    pub fn negotiate_with_base_sdp(&self, base_sdp: Option<SessionDescription>, local_params: &LocalParams) -> Result<(), SessionError> {
         // Negotiate base SDP with SDP offer
         // The SDP answer will come out of this, and will need to be put
         // through process_answer
@@ -204,48 +612,123 @@ impl Session {
         let mut bsdp_lock = self.base_sdp.write().unwrap();
         *bsdp_lock = base_sdp;
 
-        let sdp_answer = sdp::negotiate_with(bsdp_lock.as_ref(), &self.offer_sdp.read().unwrap());
-
+        // A previous answer means this is a re-offer
         let mut asdp_lock = self.answer_sdp.write().unwrap();
+        // Answer with the credentials of this session's agent
+        let mut local_params = local_params.clone();
+        {
+            let mut ice = self.ice.lock().unwrap();
+            local_params.ice_credentials = Some(ice.local_credentials().clone());
+
+            // Full agents control lite peers, and are controlled by full
+            // offerers (RFC 8445, section 6.1.1)
+            ice.set_lite(local_params.ice_lite);
+            if !local_params.ice_lite {
+                let offer_lite = self.offer_sdp.read().unwrap().attrs.iter().any(|attr| match *attr {
+                    Attr::IceLite => true,
+                    _ => false,
+                });
+                ice.set_role(if offer_lite { ice::Role::Controlling } else { ice::Role::Controlled });
+            }
+        }
+
+        let mut sdp_answer = offer_answer::negotiate_with(bsdp_lock.as_ref(), &self.offer_sdp.read().unwrap(), asdp_lock.as_ref(), &local_params)
+            .map_err(SessionError::Negotiation)?;
+
+        // Declare the source we send on each media, the same across
+        // re-offers
+        let mut local_sources = self.local_sources.write().unwrap();
+        for (i, media) in sdp_answer.media.iter_mut().enumerate() {
+            if media.is_rejected() || !media.is_sending() {
+                continue;
+            }
+
+            let &mut (ssrc, ref msid) = local_sources.entry(i).or_insert_with(|| {
+                (rand::random::<u32>(), MsidValue {
+                    stream_id: self.msid_stream.clone(),
+                    track_id: Some(random_token(16)),
+                })
+            });
+            media.add_source(ssrc, &self.cname, msid);
+        }
+
         *asdp_lock = Some(sdp_answer);
-    }
 
-    pub fn init_media_session(&self, stream_id: String, rtp_candidate: &ice::Candidate, rtcp_candidate: &ice::Candidate) -> RtpSession {
+        Ok(())
+    }
 
-        let component_id = rtp_candidate.component_id.unwrap();
-        let rtp_handler = SessionRtp {
-            stream_id: stream_id.clone(),
-            component_id: component_id,
-            ice: self.ice.clone(),
-            local_candidate: rtp_candidate.clone(),
+    /// Starts the media session of `stream_id`, which carries the transport
+    /// of the media description of index `media_idx`, on the sockets of its
+    /// components.
+    pub fn init_media_session(&self, stream_id: String, media_idx: usize, rtp_conns: Vec<UdpSocket>, rtcp_conns: Vec<UdpSocket>) -> Result<RtpSession, String> {
+        let muxed = rtcp_conns.is_empty();
+        let local_ip = match rtp_conns.first() {
+            Some(rtp_conn) => rtp_conn.local_addr().map_err(|e| e.to_string())?.ip(),
+            None => return Err(format!("no RTP socket for stream {}", stream_id)),
         };
-        let rtp_cb: Box<RirHandler + Send> = Box::new(rtp_handler);
-        let rtp_conn = UdpSocket::bind(SocketAddr::new(rtp_candidate.conn, rtp_candidate.port));
+        let (transport, media) = Transport::open(&stream_id, self.ice.clone(), rtp_conns, rtcp_conns)?;
+
+        // Until ICE picks a peer, send where the media was offered, if its
+        // address is not a name
+        {
+            let offer_sdp = self.offer_sdp.read().unwrap();
+            let media = &offer_sdp.media[media_idx];
+            let ip_addr = media.conns.first().or(offer_sdp.conn.as_ref())
+                .and_then(|conn| conn.ip_address.ip())
+                .unwrap_or(local_ip);
+
+            transport.set_peer(ice::RTP_COMPONENT_ID, SocketAddr::new(ip_addr, media.media.port));
+            if !muxed {
+                // RTCP is on the next port, unless told otherwise
+                let rtcp_peer = match media.rtcp() {
+                    Some(rtcp) => {
+                        let rtcp_ip = rtcp.conn.as_ref().and_then(|conn| conn.ip_address.ip()).unwrap_or(ip_addr);
+                        Some(SocketAddr::new(rtcp_ip, rtcp.port))
+                    },
+                    None => media.media.port.checked_add(1).map(|port| SocketAddr::new(ip_addr, port)),
+                };
+                match rtcp_peer {
+                    Some(rtcp_peer) => transport.set_peer(ice::RTCP_COMPONENT_ID, rtcp_peer),
+                    None => debug!("No RTCP port after {} for stream {}", media.media.port, stream_id),
+                }
+            }
+        }
 
-        let component_id = rtcp_candidate.component_id.unwrap();
-        let rtcp_handler = SessionRtp {
+        let rtp_cb: Box<RirHandler + Send> = Box::new(SessionRtp {
             stream_id: stream_id.clone(),
-            component_id: component_id,
-            ice: self.ice.clone(),
-            local_candidate: rtcp_candidate.clone(),
-        };
-        let rtcp_cb: Box<RirHandler + Send> = Box::new(rtcp_handler);
-        let rtcp_conn = UdpSocket::bind(SocketAddr::new(rtcp_candidate.conn, rtcp_candidate.port));
+            component_id: ice::RTP_COMPONENT_ID,
+        });
+        let rtcp_cb: Box<RirHandler + Send> = Box::new(SessionRtp {
+            stream_id: stream_id.clone(),
+            component_id: if muxed { ice::RTP_COMPONENT_ID } else { ice::RTCP_COMPONENT_ID },
+        });
+
+        // The media session talks to the transport, which relays to the peer
+        let (rtp_relay, rtcp_relay) = transport.relay_addrs();
+        let rtp_session = new_rtp_session(media.rtp, media.rtcp, rtp_relay, rtp_cb, rtcp_cb);
+        rtp_session.change_rtcp_transport(rtcp_relay);
 
-        let rtp_session = new_rtp_session(rtp_conn.unwrap(), rtcp_conn.unwrap(), self.offer_sdp.read().unwrap().clone(), rtp_cb, rtcp_cb);
+        self.transports.write().unwrap().insert(stream_id, transport);
 
-        rtp_session
+        Ok(rtp_session)
     }
 }
 
-pub fn new_rtp_session(rtp_conn: UdpSocket, rtcp_conn: UdpSocket, sdp: SessionDescription, rtp_cb: Box<RirHandler + Send>, rtcp_cb: Box<RirHandler + Send>) -> RtpSession {
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn random_token(len: usize) -> String {
+    rand::thread_rng().gen_ascii_chars().take(len).collect()
+}
 
-    let ip_addr = rtp_conn.local_addr().unwrap().ip();
-    let port = sdp.media[0].media.port;
+pub fn new_rtp_session(rtp_conn: UdpSocket, rtcp_conn: UdpSocket, remote_addr: SocketAddr, rtp_cb: Box<RirHandler + Send>, rtcp_cb: Box<RirHandler + Send>) -> RtpSession {
 
-    debug!("Connecting to remote endpoint {}:{}", ip_addr, port);
+    debug!("Connecting to remote endpoint {}", remote_addr);
 
-    let rtp_stream = RtpSession::connect_to(rtp_conn, rtcp_conn, SocketAddr::new(ip_addr, port), rtp_cb, rtcp_cb);
+    let rtp_stream = RtpSession::connect_to(rtp_conn, rtcp_conn, remote_addr, rtp_cb, rtcp_cb);
 
     rtp_stream
 }
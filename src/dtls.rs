@@ -0,0 +1,42 @@
+extern crate sha2;
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use self::sha2::{Sha256, Digest};
+
+use sdp::{FingerprintValue, HashFunction};
+
+/// Certificate presented by hibrido in DTLS handshakes. Only its DER
+/// encoding is needed, to advertise its fingerprint in SDP answers.
+pub struct Certificate {
+    der: Vec<u8>,
+}
+
+impl Certificate {
+    pub fn from_der(der: Vec<u8>) -> Certificate {
+        Certificate {
+            der: der,
+        }
+    }
+
+    /// Load a DER encoded certificate, e.g. one converted with
+    /// `openssl x509 -outform der`.
+    pub fn from_der_file<P: AsRef<Path>>(path: P) -> io::Result<Certificate> {
+        let mut f = File::open(path)?;
+        let mut der = vec![];
+        f.read_to_end(&mut der)?;
+
+        Ok(Certificate::from_der(der))
+    }
+
+    /// SHA-256 fingerprint of the certificate (RFC 8122, section 5).
+    pub fn fingerprint(&self) -> FingerprintValue {
+        FingerprintValue {
+            hash_fn: HashFunction::Sha256,
+            digest: Sha256::digest(&self.der).to_vec(),
+        }
+    }
+}
@@ -2,23 +2,42 @@ extern crate ifaces;
 extern crate rustun;
 extern crate fibers;
 extern crate uuid;
-extern crate timer;
-extern crate time;
+extern crate rand;
+extern crate sha2;
 
+use std::cmp;
 use std::str::FromStr;
-use std::net::IpAddr;
-use std::net::SocketAddr;
-use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use self::uuid::Uuid;
-use std::sync::{mpsc};
 
-use self::timer::Timer;
-use self::time::Duration;
+use self::rand::Rng;
+use self::sha2::{Sha256, Digest};
+
+use sdp::Address;
+use mdns;
+use stun;
+use ports::PortAllocator;
 
 pub const RTP_COMPONENT_ID: u16 = 1;
 pub const RTCP_COMPONENT_ID: u16 = 2;
 
-#[derive(Clone, Debug, PartialEq)]
+/// Pacing of the checks, Ta (RFC 8445, section 14.2).
+const DEFAULT_TA_MS: u64 = 50;
+/// Lower bound of the retransmission timeout of checks (RFC 8445, section
+/// 14.3).
+const MIN_RTO_MS: u64 = 500;
+/// Transmissions of a check before its pair fails, Rc (RFC 5389, section
+/// 7.2.1).
+const MAX_TRANSMISSIONS: u32 = 7;
+/// Pairs kept in the check list of a component (RFC 8445, section 6.1.2.5).
+const MAX_CHECK_LIST_SIZE: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Proto {
     Udp,
     Tcp,
@@ -47,7 +66,8 @@ impl FromStr for Proto {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CandidateType {
     Host,
     Srflx,
@@ -66,6 +86,19 @@ impl ToString for CandidateType {
     }
 }
 
+impl CandidateType {
+    /// Type preference, the higher the more preferred (RFC 8445, section
+    /// 5.1.2.2).
+    pub fn preference(&self) -> u32 {
+        match *self {
+            CandidateType::Host => 126,
+            CandidateType::Prflx => 110,
+            CandidateType::Srflx => 100,
+            CandidateType::Relay => 0,
+        }
+    }
+}
+
 impl FromStr for CandidateType {
     type Err = ();
 
@@ -80,23 +113,273 @@ impl FromStr for CandidateType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Candidate {
-    pub conn: IpAddr,
+    pub conn: Address,
     pub port: u16,
     pub proto: Proto,
     pub foundation: String,
     pub component_id: Option<u16>,
     pub priority: u32,
     pub candidate_type: CandidateType,
-    pub rel_addr: Option<IpAddr>,
+    pub rel_addr: Option<Address>,
     pub rel_port: Option<u16>,
+    /// Extension attributes, e.g. "generation 0" (RFC 8839, section 5.1).
+    pub extensions: Vec<(String, String)>,
 }
 
+impl Candidate {
+    /// Transport address of the candidate, unless its address is a name
+    /// yet to be resolved.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.conn.ip().map(|ip| SocketAddr::new(ip, self.port))
+    }
+
+    /// The candidate with a name resolved into one candidate per address
+    /// found (RFC 8839, section 5.1). The lookup blocks, so is done without
+    /// the agent locked. mDNS names are left to the agent's querier.
+    pub fn resolve(&self) -> Vec<Candidate> {
+        let name = match self.conn {
+            Address::Ip(_) => return vec![self.clone()],
+            Address::Fqdn(ref name) => name,
+        };
+
+        if mdns::is_mdns_name(name) {
+            debug!("Not resolving mDNS candidate {} through DNS", name);
+            return vec![]
+        }
+
+        match (name.as_str(), self.port).to_socket_addrs() {
+            Ok(addrs) => addrs.map(|addr| {
+                let mut candidate = self.clone();
+                candidate.conn = Address::Ip(addr.ip());
+
+                candidate
+            }).collect(),
+            Err(e) => {
+                debug!("Failed to resolve candidate {}: {}", name, e);
+                vec![]
+            },
+        }
+    }
+}
+
+/// Whether two addresses are of the same IP family, which candidates have
+/// to share to be paired (RFC 8445, section 6.1.2.2).
+pub fn is_same_family(a: &Address, b: &Address) -> bool {
+    match (a.ip(), b.ip()) {
+        (Some(IpAddr::V4(_)), Some(IpAddr::V4(_))) => true,
+        (Some(IpAddr::V6(_)), Some(IpAddr::V6(_))) => true,
+        _ => false,
+    }
+}
+
+/// `addr` as an IPv4 one if it is IPv4-mapped, the way IPv6 sockets see
+/// IPv4 peers.
+pub fn unmap_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().segments() {
+            [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new((hi >> 8) as u8, hi as u8, (lo >> 8) as u8, lo as u8)), v6.port())
+            },
+            _ => addr,
+        },
+        _ => addr,
+    }
+}
+
+/// `addr` as an IPv4-mapped one if it is IPv4, for IPv6 sockets to reach it.
+pub fn map_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) => SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port()),
+        _ => addr,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Controlling,
+    Controlled,
+}
+
+impl Role {
+    fn reverse(&self) -> Role {
+        match *self {
+            Role::Controlling => Role::Controlled,
+            Role::Controlled => Role::Controlling,
+        }
+    }
+}
+
+/// State of a candidate pair (RFC 8445, section 6.1.2.6).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PairState {
+    Frozen,
+    Waiting,
+    InProgress,
+    Succeeded,
+    Failed,
+}
+
+/// Check in flight for a pair.
+#[derive(Clone)]
+struct Transaction {
+    id: [u8; 12],
+    transmissions: u32,
+    rto: Duration,
+    retransmit_at: Instant,
+    // Role the check was sent with
+    role: Role,
+    // Whether the check carries USE-CANDIDATE
+    nominating: bool,
+}
+
+#[derive(Clone)]
 pub struct PairCandidate {
     // TODO(tlam): Use references and lifetimes here
     local_candidate: Candidate,
     peer_candidate: Candidate,
+    priority: u64,
+    state: PairState,
+    nominated: bool,
+    // To be checked before the ordinary checks (RFC 8445, section 7.3.1.4)
+    triggered: bool,
+    // The peer nominated it before our check of it succeeded
+    remote_nominated: bool,
+    transaction: Option<Transaction>,
+}
+
+impl PairCandidate {
+    fn new(local_candidate: Candidate, peer_candidate: Candidate, role: Role) -> PairCandidate {
+        let mut pair = PairCandidate {
+            local_candidate: local_candidate,
+            peer_candidate: peer_candidate,
+            priority: 0,
+            state: PairState::Frozen,
+            nominated: false,
+            triggered: false,
+            remote_nominated: false,
+            transaction: None,
+        };
+        pair.set_priority(role);
+
+        pair
+    }
+
+    fn set_priority(&mut self, role: Role) {
+        self.priority = match role {
+            Role::Controlling => pair_priority(self.local_candidate.priority, self.peer_candidate.priority),
+            Role::Controlled => pair_priority(self.peer_candidate.priority, self.local_candidate.priority),
+        };
+    }
+
+    pub fn local_candidate(&self) -> &Candidate {
+        &self.local_candidate
+    }
+
+    pub fn peer_candidate(&self) -> &Candidate {
+        &self.peer_candidate
+    }
+
+    pub fn priority(&self) -> u64 {
+        self.priority
+    }
+
+    pub fn state(&self) -> PairState {
+        self.state
+    }
+
+    pub fn is_nominated(&self) -> bool {
+        self.nominated
+    }
+
+    /// Pairs are unfrozen together by foundation (RFC 8445, section 6.1.2.6).
+    fn foundation(&self) -> String {
+        format!("{}:{}", self.local_candidate.foundation, self.peer_candidate.foundation)
+    }
+
+    fn is_pair_of(&self, local_addr: Option<SocketAddr>, remote_addr: Option<SocketAddr>) -> bool {
+        self.local_candidate.socket_addr() == local_addr && self.peer_candidate.socket_addr() == remote_addr
+    }
+}
+
+/// Priority of a candidate (RFC 8445, section 5.1.2.1).
+pub fn candidate_priority(candidate_type: &CandidateType, local_preference: u16, component_id: u16) -> u32 {
+    (candidate_type.preference() << 24) +
+        ((local_preference as u32) << 8) +
+        (256 - cmp::min(component_id, 256) as u32)
+}
+
+/// Local preference of a host address, by its rank amongst those of its
+/// family, the first one the most preferred. Families are interleaved, IPv6
+/// ahead of IPv4 (RFC 8421, section 4).
+pub fn local_preference(ip: &IpAddr, rank: usize) -> u16 {
+    let rank = cmp::min(rank, 0x7ffe) as u16;
+
+    65535 - 2 * rank - if ip.is_ipv4() { 1 } else { 0 }
+}
+
+/// Foundation of a candidate, the same for those of the same type, base
+/// address, server and protocol (RFC 8445, section 5.1.1.3).
+pub fn foundation(candidate_type: &CandidateType, base: &IpAddr, server: Option<&SocketAddr>, proto: &Proto) -> String {
+    let key = format!("{} {} {} {}",
+                      candidate_type.to_string(),
+                      base,
+                      server.map_or("".to_string(), |s| s.to_string()),
+                      proto.to_string());
+
+    Sha256::digest(key.as_bytes())[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Priority of a pair, out of those of the controlling and the controlled
+/// agent's candidates (RFC 8445, section 6.1.2.3).
+pub fn pair_priority(controlling: u32, controlled: u32) -> u64 {
+    let (g, d) = (controlling as u64, controlled as u64);
+
+    (1 << 32) * cmp::min(g, d) + 2 * cmp::max(g, d) + if g > d { 1 } else { 0 }
+}
+
+/// Check to be sent to the peer, from the local candidate's address.
+#[derive(Clone, Debug)]
+pub struct Transmit {
+    pub stream_id: String,
+    pub component_id: u16,
+    pub local_addr: SocketAddr,
+    pub destination: SocketAddr,
+    pub packet: Vec<u8>,
+}
+
+/// Next check of a check list.
+enum Check {
+    Retransmit,
+    New { nominating: bool },
+}
+
+/// Characters allowed in ICE credentials (RFC 8445, section 15.4).
+const ICE_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Username fragment and password of an ICE agent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub ufrag: String,
+    pub pwd: String,
+}
+
+impl Credentials {
+    /// Random credentials, of 96 and 144 bits, above the 24 and 128 bits
+    /// required (RFC 8445, section 5.3).
+    pub fn generate() -> Credentials {
+        Credentials {
+            ufrag: random_ice_chars(16),
+            pwd: random_ice_chars(24),
+        }
+    }
+}
+
+fn random_ice_chars(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..len).map(|_| ICE_CHARS[rng.gen_range(0, ICE_CHARS.len())] as char).collect()
 }
 
 #[derive(PartialEq)]
@@ -108,9 +391,12 @@ enum StreamState {
 struct Stream {
     id: String,
     state: StreamState,
+    components: Vec<u16>,
     check_list: HashMap<u16, Vec<PairCandidate>>,
     valid_list: HashMap<u16, Vec<PairCandidate>>,
     offer_candidates: HashMap<u16, Vec<Candidate>>,
+    // Offered candidates whose name didn't resolve, or not yet
+    unresolved_candidates: HashMap<u16, Vec<Candidate>>,
     local_candidates: HashMap<u16, Vec<Candidate>>,
 }
 
@@ -123,35 +409,215 @@ pub struct Agent {
     state: IceState,
     streams: HashMap<String, Stream>,
     handler: Option<Box<Handler + Send>>,
+    local_credentials: Credentials,
+    // Credentials of the peer, used to check its connectivity checks
+    remote_credentials: Option<Credentials>,
+    mdns: mdns::Querier,
+    // Addresses of the mDNS names looked up, None if no one answered
+    mdns_names: HashMap<String, Option<IpAddr>>,
+    // mDNS names offered, yet to be looked up
+    mdns_pending: Vec<String>,
+    // Candidates offered with a DNS name, yet to be resolved
+    dns_pending: Vec<(String, u16, Candidate)>,
+    // Lite agents only answer checks (RFC 8445, section 2.5)
+    lite: bool,
+    role: Role,
+    tie_breaker: u64,
+    ta: Duration,
+    last_check: Option<Instant>,
+    ports: Arc<PortAllocator>,
+    interfaces: InterfaceFilter,
+    // Sockets bound when gathering, one per family, until taken by the
+    // media sessions
+    sockets: HashMap<(String, u16), Vec<UdpSocket>>,
+    // Ports allocated for the components of each stream
+    allocated_ports: HashMap<String, Vec<u16>>,
+}
+
+/// Whether `ip` is link-local, which host candidates are not gathered on
+/// as IPv6 ones would need a scope.
+fn is_link_local(ip: &IpAddr) -> bool {
+    match *ip {
+        IpAddr::V4(ref ip) => ip.is_link_local(),
+        IpAddr::V6(ref ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
 }
 
-static mut START_PORT: u16 = 6000;
+/// Interfaces, or networks, host candidates are gathered on or not.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InterfaceRule {
+    /// Interface of that name, or starting with it if ending in '*', e.g.
+    /// "docker*".
+    Name(String),
+    /// Addresses within a network, e.g. "10.0.0.0/8".
+    Cidr(IpAddr, u8),
+}
 
-/// Get IPv4 addresses only.
-fn get_ipv4_address() -> Option<SocketAddr> {
+impl InterfaceRule {
+    pub fn matches(&self, name: &str, ip: &IpAddr) -> bool {
+        match *self {
+            InterfaceRule::Name(ref rule) if rule.ends_with('*') => name.starts_with(&rule[..rule.len() - 1]),
+            InterfaceRule::Name(ref rule) => name == rule,
+            InterfaceRule::Cidr(ref network, prefix_len) => {
+                match (*network, *ip) {
+                    (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                        prefix_matches(&network.octets(), &ip.octets(), prefix_len)
+                    },
+                    (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                        prefix_matches(&network.octets(), &ip.octets(), prefix_len)
+                    },
+                    _ => false,
+                }
+            },
+        }
+    }
+}
 
-    let mut ipv4_addr = None;
-    for iface in
-        ifaces::Interface::get_all().unwrap()
-                                    .into_iter() {
-        debug!("{}\t{:?}\t{:?}", iface.name, iface.kind, iface.addr);
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let prefix_len = prefix_len as usize;
+    let full = prefix_len / 8;
+    if network[..full] != ip[..full] {
+        return false;
+    }
 
-        // Avoid localhost IPs
-        if iface.name != "lo" {
-            match iface.kind {
-                ifaces::Kind::Ipv4 => {
-                    ipv4_addr = iface.addr;
-                    debug!("Chosen {:?}", iface.addr);
+    let rest = prefix_len % 8;
+    rest == 0 || (network[full] ^ ip[full]) >> (8 - rest) == 0
+}
 
-                    // Return first IPv4 address found
-                    break;
-                },
-                _ => {},
-            };
+impl ToString for InterfaceRule {
+    fn to_string(&self) -> String {
+        match *self {
+            InterfaceRule::Name(ref name) => name.clone(),
+            InterfaceRule::Cidr(ref network, prefix_len) => format!("{}/{}", network, prefix_len),
+        }
+    }
+}
+
+impl FromStr for InterfaceRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InterfaceRule, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty interface rule".to_string())
+        }
+
+        let mut parts = s.splitn(2, '/');
+        let network = parts.next().unwrap();
+        match (network.parse::<IpAddr>(), parts.next()) {
+            (Ok(network), Some(prefix_len)) => {
+                let max_len = if network.is_ipv4() { 32 } else { 128 };
+                match prefix_len.parse::<u8>() {
+                    Ok(prefix_len) if prefix_len <= max_len => Ok(InterfaceRule::Cidr(network, prefix_len)),
+                    _ => Err(format!("invalid prefix length in '{}'", s)),
+                }
+            },
+            (Ok(network), None) => {
+                let prefix_len = if network.is_ipv4() { 32 } else { 128 };
+                Ok(InterfaceRule::Cidr(network, prefix_len))
+            },
+            (Err(_), None) => Ok(InterfaceRule::Name(s.to_string())),
+            (Err(_), Some(_)) => Err(format!("invalid network '{}'", s)),
+        }
+    }
+}
+
+/// Why candidates couldn't be gathered for a component.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GatherError {
+    NoStream(String),
+    /// No address of the host's interfaces passes the filter.
+    NoAddress,
+    /// No port is left to allocate, or it couldn't be bound.
+    Ports(String),
+}
+
+impl ToString for GatherError {
+    fn to_string(&self) -> String {
+        match *self {
+            GatherError::NoStream(ref stream_id) => format!("no stream {}", stream_id),
+            GatherError::NoAddress => "no address passes the interface filter".to_string(),
+            GatherError::Ports(ref e) => e.clone(),
+        }
+    }
+}
+
+/// Which of the host's addresses candidates are gathered on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InterfaceFilter {
+    /// Interfaces gathered on, all of them if empty.
+    pub include: Vec<InterfaceRule>,
+    /// Interfaces never gathered on, even if included.
+    pub exclude: Vec<InterfaceRule>,
+    /// Whether loopback addresses are gathered on, for local testing.
+    pub loopback: bool,
+}
+
+impl InterfaceFilter {
+    pub fn new() -> InterfaceFilter {
+        Default::default()
+    }
+
+    /// Rules separated by commas, e.g. "eth0, 10.0.0.0/8".
+    pub fn parse_rules(s: &str) -> Result<Vec<InterfaceRule>, String> {
+        s.split(',').filter(|r| !r.trim().is_empty()).map(|r| r.parse()).collect()
+    }
+
+    pub fn is_eligible(&self, name: &str, ip: &IpAddr) -> bool {
+        // IPv6 link-local addresses would need a scope
+        if is_link_local(ip) {
+            return false;
+        }
+
+        if !self.loopback && (name == "lo" || ip.is_loopback()) {
+            return false;
+        }
+
+        (self.include.is_empty() || self.include.iter().any(|r| r.matches(name, ip))) &&
+            !self.exclude.iter().any(|r| r.matches(name, ip))
+    }
+
+    /// The eligible addresses amongst `addresses`, of the host's interfaces
+    /// in order, each with its rank within its family: the earlier the
+    /// interface, the more preferred.
+    pub fn select(&self, addresses: &[(String, IpAddr)]) -> Vec<(usize, IpAddr)> {
+        let mut selected: Vec<(usize, IpAddr)> = vec![];
+        for &(ref name, ip) in addresses.iter() {
+            if !self.is_eligible(name, &ip) || selected.iter().any(|&(_, s)| s == ip) {
+                continue;
+            }
+
+            let rank = selected.iter().filter(|&&(_, s)| s.is_ipv4() == ip.is_ipv4()).count();
+            debug!("Chosen {:?} of {}", ip, name);
+            selected.push((rank, ip));
         }
+
+        selected
     }
+}
+
+/// Get the addresses of the host's interfaces allowed by `filter`, for a
+/// host candidate on each, along with their rank.
+fn get_host_addresses(filter: &InterfaceFilter) -> Vec<(usize, IpAddr)> {
+    let interfaces = match ifaces::Interface::get_all() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            warn!("Failed to list interfaces: {}", e);
+            return vec![]
+        },
+    };
 
-    ipv4_addr
+    let mut addresses = vec![];
+    for iface in interfaces.into_iter() {
+        debug!("{}\t{:?}\t{:?}", iface.name, iface.kind, iface.addr);
+
+        match iface.addr {
+            Some(addr) => addresses.push((iface.name, addr.ip())),
+            None => continue,
+        }
+    }
+
+    filter.select(&addresses)
 }
 
 pub trait Handler {
@@ -166,49 +632,206 @@ impl Agent {
             // TODO(tlam): Miss the Option wrapper so we can use this without
             // having to place checks all around the callbacks code
             handler: Some(handler),
+            local_credentials: Credentials::generate(),
+            remote_credentials: None,
+            mdns: mdns::Querier::new(),
+            mdns_names: HashMap::new(),
+            mdns_pending: vec![],
+            dns_pending: vec![],
+            lite: true,
+            role: Role::Controlled,
+            tie_breaker: rand::thread_rng().gen(),
+            ta: Duration::from_millis(DEFAULT_TA_MS),
+            last_check: None,
+            ports: Arc::new(PortAllocator::default()),
+            interfaces: InterfaceFilter::new(),
+            sockets: HashMap::new(),
+            allocated_ports: HashMap::new(),
         }
     }
 
-    /// Start agent and initiate the regular functions
-    pub fn start(&mut self, handler: Box<Handler + Send>) {
-        let (tx, rx) = mpsc::channel();
-        let timer = Timer::new();
+    /// Sets the allocator the ports of the components are taken from,
+    /// usually shared by all the agents.
+    pub fn set_port_allocator(&mut self, ports: Arc<PortAllocator>) {
+        self.ports = ports;
+    }
 
-        self.handler = Some(handler);
+    /// Sets which interfaces candidates are gathered on.
+    pub fn set_interface_filter(&mut self, interfaces: InterfaceFilter) {
+        self.interfaces = interfaces;
+    }
 
-//        loop {
-            timer.schedule_with_delay(Duration::milliseconds(10),
-                move || {
-                    let err = tx.send(1);
-                    match err {
-                        Err(x) => {
-                            error!("Problem scheduling timer: {}", x);
-                        },
-                        _ => {},
-                    }
+    /// Sockets of a component, one per family of its candidates, bound when
+    /// they were gathered.
+    pub fn take_sockets(&mut self, stream_id: &str, component_id: &u16) -> Vec<UdpSocket> {
+        self.sockets.remove(&(stream_id.to_string(), *component_id)).unwrap_or(vec![])
+    }
+
+    /// Hands the ports of all the components back to the allocator.
+    pub fn release_ports(&mut self) {
+        self.sockets.clear();
+        for (_, ports) in self.allocated_ports.drain() {
+            for port in ports {
+                self.ports.release(port);
+            }
+        }
+    }
+
+    /// Whether the agent is lite, as it is by default, or full: sending
+    /// checks of its own and taking either role.
+    pub fn set_lite(&mut self, lite: bool) {
+        self.lite = lite;
+        if lite {
+            self.set_role(Role::Controlled);
+        }
+    }
+
+    pub fn is_lite(&self) -> bool {
+        self.lite
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Sets the role of the agent, which pair priorities depend on. Lite
+    /// agents are always controlled.
+    pub fn set_role(&mut self, role: Role) {
+        if role != self.role {
+            debug!("Agent role switched to {:?}", role);
+        }
+        self.role = role;
+
+        for (_, stream) in self.streams.iter_mut() {
+            for (_, pairs) in stream.check_list.iter_mut().chain(stream.valid_list.iter_mut()) {
+                for pair in pairs.iter_mut() {
+                    pair.set_priority(role);
                 }
-            );
+                pairs.sort_by(|a, b| b.priority.cmp(&a.priority));
+            }
+        }
+    }
 
-            let err = rx.recv();
-            match err {
-                Err(x) => {
-                    error!("Problem scheduling timer: {}", x);
-                    return;
-                },
-                _ => {},
+    /// Sets Ta, the interval between two checks of the agent.
+    pub fn set_ta(&mut self, ta: Duration) {
+        self.ta = ta;
+    }
+
+    pub fn ta(&self) -> Duration {
+        self.ta
+    }
+
+    /// Sets the querier offered mDNS candidates are resolved with.
+    pub fn set_mdns_querier(&mut self, querier: mdns::Querier) {
+        self.mdns = querier;
+    }
+
+    /// mDNS names of the offered candidates yet to be looked up, and the
+    /// querier to do so. Lookups block, so are left to the caller, without
+    /// the agent locked; checks from the candidates are answered meanwhile.
+    pub fn take_mdns_lookups(&mut self) -> (mdns::Querier, Vec<String>) {
+        (self.mdns.clone(), self.mdns_pending.drain(..).collect())
+    }
+
+    /// Candidates offered with a DNS name, with their stream and component,
+    /// to be resolved by the caller without the agent locked as for mDNS.
+    pub fn take_dns_lookups(&mut self) -> Vec<(String, u16, Candidate)> {
+        self.dns_pending.drain(..).collect()
+    }
+
+    /// Candidates `candidate`, offered with a DNS name, was resolved into.
+    pub fn add_resolved_candidates(&mut self, stream_id: &str, component_id: &u16, candidate: &Candidate, resolved: Vec<Candidate>) {
+        {
+            let stream = match self.streams.get_mut(stream_id) {
+                Some(stream) => stream,
+                None => return,
+            };
+
+            if let Some(unresolved) = stream.unresolved_candidates.get_mut(component_id) {
+                unresolved.retain(|c| c.conn != candidate.conn || c.port != candidate.port || c.foundation != candidate.foundation);
             }
 
-            let mut found = false;
-            for (_, stream) in self.streams.iter() {
-                if stream.state != StreamState::Completed {
-                    found = true;
+            let candidates: &mut Vec<Candidate> = stream.offer_candidates.entry(*component_id).or_insert(Vec::new());
+            for candidate in resolved {
+                // Unless already learnt from its checks
+                if !candidates.iter().any(|c| c.socket_addr() == candidate.socket_addr()) {
+                    candidates.push(candidate);
                 }
             }
-            if !found {
-                self.state = IceState::Completed;
+        }
+
+        self.update_check_list(stream_id, component_id);
+    }
+
+    /// Address the mDNS name `name` was looked up into, if anyone answered,
+    /// now offering the candidates behind it.
+    pub fn add_mdns_address(&mut self, name: &str, ip: Option<IpAddr>) {
+        let name = name.to_lowercase();
+        self.mdns_names.insert(name.clone(), ip);
+        let ip = match ip {
+            Some(ip) => ip,
+            None => {
+                debug!("No address for {}, waiting for checks from it", name);
+                return
+            },
+        };
+
+        let mut updated = vec![];
+        for stream in self.streams.values_mut() {
+            for (component_id, unresolved) in stream.unresolved_candidates.iter_mut() {
+                let (resolved, others): (Vec<Candidate>, Vec<Candidate>) = unresolved.drain(..).partition(|c| match c.conn {
+                    Address::Fqdn(ref n) => n.to_lowercase() == name,
+                    _ => false,
+                });
+                *unresolved = others;
+                if resolved.is_empty() {
+                    continue;
+                }
+
+                let candidates: &mut Vec<Candidate> = stream.offer_candidates.entry(*component_id).or_insert(Vec::new());
+                for mut candidate in resolved {
+                    candidate.conn = Address::Ip(ip);
+                    // Unless already learnt from its checks
+                    if !candidates.iter().any(|c| c.socket_addr() == candidate.socket_addr()) {
+                        candidates.push(candidate);
+                    }
+                }
+                updated.push((stream.id.clone(), *component_id));
             }
+        }
+
+        for (stream_id, component_id) in updated {
+            self.update_check_list(&stream_id, &component_id);
+        }
+    }
+
+    pub fn local_credentials(&self) -> &Credentials {
+        &self.local_credentials
+    }
+
+    pub fn remote_credentials(&self) -> Option<&Credentials> {
+        self.remote_credentials.as_ref()
+    }
 
-//        }
+    pub fn set_remote_credentials(&mut self, credentials: Credentials) {
+        debug!("Remote ufrag set to {}", credentials.ufrag);
+        self.remote_credentials = Some(credentials);
+    }
+
+    /// Start agent, forming the check lists of its streams. Checks are then
+    /// sent as `poll` hands them out, unless the agent is lite.
+    pub fn start(&mut self, handler: Box<Handler + Send>) {
+        self.handler = Some(handler);
+
+        let role = self.role;
+        for (_, stream) in self.streams.iter_mut() {
+            for component_id in stream.components.clone() {
+                form_check_list(stream, component_id, role);
+            }
+            unfreeze_check_list(stream);
+        }
+
+        self.set_ice_complete();
     }
 
     pub fn set_ice_complete(&mut self) {
@@ -221,16 +844,19 @@ impl Agent {
         self.state = IceState::Completed;
     }
 
-    /// Add new stream to the current agent, of the component provided
-    pub fn add_stream(&mut self) -> String {
+    /// Add new stream to the current agent, with `nr_components` components:
+    /// 1 when RTP and RTCP are multiplexed, 2 otherwise.
+    pub fn add_stream(&mut self, nr_components: u16) -> String {
         // Add stream to agent
         let stream_id: &str = &Uuid::new_v4().to_string();
         let stream = Stream {
             id: stream_id.to_string(),
             state: StreamState::Running,
+            components: (RTP_COMPONENT_ID..RTP_COMPONENT_ID + nr_components).collect(),
             check_list: HashMap::new(),
             valid_list: HashMap::new(),
             offer_candidates: HashMap::new(),
+            unresolved_candidates: HashMap::new(),
             local_candidates: HashMap::new(),
         };
 
@@ -239,14 +865,70 @@ impl Agent {
         stream_id.to_string()
     }
 
+    /// Adds a candidate of the offer. Those behind an mDNS name are resolved
+    /// with the agent's querier, or else wait for the peer's checks to be
+    /// learnt as peer-reflexive (RFC 8445, section 7.3.1.3).
     pub fn add_offer_candidate(&mut self, stream_id: &str, component_id: &u16, candidate: Candidate) {
         let stream = match self.streams.get_mut(stream_id) {
             Some(stream) => { stream },
             None => { return },
         };
 
+        let resolved = match candidate.conn {
+            Address::Fqdn(ref name) if mdns::is_mdns_name(name) => {
+                let key = name.to_lowercase();
+                match self.mdns_names.get(&key).cloned() {
+                    Some(Some(ip)) => {
+                        let mut resolved = candidate.clone();
+                        resolved.conn = Address::Ip(ip);
+
+                        vec![resolved]
+                    },
+                    looked_up => {
+                        if looked_up.is_none() && !self.mdns_pending.contains(&key) {
+                            self.mdns_pending.push(key);
+                        }
+                        debug!("Candidate {} unresolved, waiting for checks from it", name);
+                        stream.unresolved_candidates.entry(*component_id).or_insert(Vec::new()).push(candidate.clone());
+
+                        vec![]
+                    },
+                }
+            },
+            Address::Fqdn(ref name) => {
+                debug!("Candidate {} unresolved, waiting for checks from it", name);
+                stream.unresolved_candidates.entry(*component_id).or_insert(Vec::new()).push(candidate.clone());
+                self.dns_pending.push((stream_id.to_string(), *component_id, candidate.clone()));
+
+                vec![]
+            },
+            Address::Ip(_) => vec![candidate],
+        };
+
         let candidates: &mut Vec<Candidate> = stream.offer_candidates.entry(*component_id).or_insert(Vec::new());
-        candidates.push(candidate);
+        candidates.extend(resolved);
+
+        self.update_check_list(stream_id, component_id);
+    }
+
+    /// Removes a stream, handing the ports of its components back to the
+    /// allocator. Sockets taken from the agent are to be closed by then.
+    pub fn remove_stream(&mut self, stream_id: &str) {
+        debug!("Removing stream {}", stream_id);
+        self.streams.remove(stream_id);
+        self.sockets.retain(|&(ref id, _), _| id != stream_id);
+        for port in self.allocated_ports.remove(stream_id).unwrap_or(vec![]) {
+            self.ports.release(port);
+        }
+    }
+
+    pub fn get_offer_candidates(&self, stream_id: &str, component_id: &u16) -> Option<&Vec<Candidate>> {
+        let stream = match self.streams.get(stream_id) {
+            Some(stream) => { stream },
+            None => { return None },
+        };
+
+        stream.offer_candidates.get(component_id)
     }
 
     pub fn get_stream_candidates(&self, stream_id: &str, component_id: &u16) -> Option<&Vec<Candidate>> {
@@ -258,191 +940,678 @@ impl Agent {
         stream.local_candidates.get(component_id)
     }
 
-    /// Gather candidates for a particular stream
-    pub fn gather_candidates(&mut self, stream_id: &str, component_id: &u16) {
-        let stream = self.streams.get_mut(stream_id).unwrap();
+    /// Gather candidates for a particular stream, a host candidate on each
+    /// address of the eligible interfaces. Those of a family share the port
+    /// of a socket bound on all its addresses, IPv4 and IPv6 ones being
+    /// bound apart rather than relying on dual-stack sockets. Fails if no
+    /// address is eligible, or no port is left to allocate.
+    pub fn gather_candidates(&mut self, stream_id: &str, component_id: &u16) -> Result<(), GatherError> {
+        let stream = match self.streams.get_mut(stream_id) {
+            Some(stream) => stream,
+            None => return Err(GatherError::NoStream(stream_id.to_string())),
+        };
 
-        let ipv4_addr = get_ipv4_address();
-        if !ipv4_addr.is_some() {
-            return
+        let host_addrs = get_host_addresses(&self.interfaces);
+        if host_addrs.is_empty() {
+            return Err(GatherError::NoAddress)
+        }
+
+        let mut ports = HashMap::new();
+        for &bind_ip in [IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0))].iter() {
+            if !host_addrs.iter().any(|&(_, ip)| ip.is_ipv6() == bind_ip.is_ipv6()) {
+                continue;
+            }
+
+            let socket = self.ports.allocate(bind_ip).map_err(GatherError::Ports)?;
+            let port = socket.local_addr().map_err(|e| GatherError::Ports(e.to_string()))?.port();
+            self.allocated_ports.entry(stream_id.to_string()).or_insert(vec![]).push(port);
+            self.sockets.entry((stream_id.to_string(), *component_id)).or_insert(vec![]).push(socket);
+            ports.insert(bind_ip.is_ipv6(), port);
         }
 
         let candidates: &mut Vec<Candidate> = stream.local_candidates.entry(*component_id).or_insert(Vec::new());
 
-        let mut ipv4_addr = ipv4_addr.unwrap();
-        unsafe {
-            ipv4_addr.set_port(START_PORT);
-            START_PORT += 1;
+        for (rank, ip) in host_addrs {
+            // Get new candidate
+            let candidate = Candidate {
+                conn: Address::Ip(ip),
+                port: ports[&ip.is_ipv6()],
+                proto: Proto::Udp,
+                foundation: foundation(&CandidateType::Host, &ip, None, &Proto::Udp),
+                component_id: Some(*component_id),
+                priority: candidate_priority(&CandidateType::Host, local_preference(&ip, rank), *component_id),
+                candidate_type: CandidateType::Host,
+                rel_addr: None,
+                rel_port: None,
+                extensions: vec![],
+            };
+
+            candidates.push(candidate);
         }
-        //let conn = UdpSocket::bind(ipv4_addr).unwrap();
 
-        let port = ipv4_addr.port();
+        self.update_check_list(stream_id, component_id);
 
-        //let skt = SocketAddr::new(ipv4_addr, port);
+        Ok(())
+    }
 
-        //setup_stun_server(conn);
-        //let rtp_stream = RtpSession::connect_to(conn, "0.0.0.0:0".parse().unwrap())
+    /// Nominates the pair of the local candidate on `local_port` and the
+    /// offered one of address `remote_addr`, of the same family.
+    pub fn add_pair_candidate(&mut self, stream_id: &str, component_id: &u16, local_port: u16, remote_addr: SocketAddr) {
+        {
+            let stream = match self.streams.get_mut(stream_id) {
+                Some(stream) => { stream },
+                None => { return },
+            };
 
-        // Get new candidate
-        let mut candidate = Candidate {
-            conn: ipv4_addr.ip(),
-            port: port,
-            proto: Proto::Udp,
-            foundation: "deadbeef".to_string(),
-            component_id: Some(*component_id),
-            priority: 0,
-            candidate_type: CandidateType::Host,
-            rel_addr: None,
-            rel_port: None,
+            info!("New offered candidate for stream_id {}", stream_id);
+
+            if stream.state == StreamState::Completed {
+                debug!("Stream_id {} is complete", stream_id);
+                return;
+            }
+
+            let remote_addr = unmap_addr(remote_addr);
+
+            /* Find remote_addr amongst the offer candidates */
+            let peer_candidate = find_peer_candidate(stream, *component_id, remote_addr, None);
+
+            /* Find local_port amongst the local candidates */
+            let local_candidate = match find_local_candidate(stream, *component_id, local_port, remote_addr) {
+                Some(local_candidate) => local_candidate,
+                None => {
+                    debug!("No pair inserted for local port {} and remote address {}!", local_port, remote_addr);
+                    return;
+                },
+            };
+
+            add_valid_pair(stream, *component_id, &PairCandidate::new(local_candidate, peer_candidate, self.role), true);
+        }
+
+        self.complete_stream(stream_id);
+    }
+
+    /// Answers the STUN binding request `packet`, received from `source` on
+    /// `local_port` of a component (RFC 8445, section 7.3). Requests failing
+    /// FINGERPRINT are dropped, and those not authenticated with our
+    /// short-term credentials get a 401. Only authenticated requests with
+    /// USE-CANDIDATE nominate their pair. Responses to our own checks are
+    /// processed too. Returns the response to send back, if any.
+    pub fn handle_stun(&mut self, stream_id: &str, component_id: &u16, local_port: u16, packet: &[u8], source: SocketAddr) -> Option<Vec<u8>> {
+        let message = match stun::Message::parse(packet) {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("Dropping STUN message from {}: {}", source, e);
+                return None
+            },
         };
-        Agent::set_priority_candidate(&mut candidate, *component_id);
 
-        candidates.push(candidate);
+        if !stun::check_fingerprint(packet, &message) {
+            debug!("Dropping STUN message from {} without a valid fingerprint", source);
+            return None
+        }
+
+        match message.msg_type {
+            stun::BINDING_REQUEST => self.handle_request(stream_id, component_id, local_port, packet, &message, source),
+            stun::BINDING_SUCCESS | stun::BINDING_ERROR => {
+                self.handle_response(stream_id, component_id, packet, &message, source);
+                None
+            },
+            _ => {
+                debug!("Ignoring STUN message {:#06x} from {}", message.msg_type, source);
+                None
+            },
+        }
     }
 
-    pub fn add_pair_candidate(&mut self, stream_id: &str, component_id: &u16, local_port: u16, remote_port: u16) {
-        let stream = match self.streams.get_mut(stream_id) {
-            Some(stream) => { stream },
-            None => { return },
+    fn handle_request(&mut self, stream_id: &str, component_id: &u16, local_port: u16, packet: &[u8], request: &stun::Message, source: SocketAddr) -> Option<Vec<u8>> {
+        let username = match (request.username(), request.has(stun::ATTR_MESSAGE_INTEGRITY)) {
+            (Some(username), true) => username,
+            _ => {
+                debug!("Binding request from {} without credentials", source);
+                return Some(error_response(request, 400, "Bad Request", None))
+            },
         };
 
-        info!("New offered candidate for stream_id {}", stream_id);
+        // USERNAME is "<our ufrag>:<their ufrag>" (RFC 8445, section 7.2.2)
+        let key = stun::short_term_key(&self.local_credentials.pwd);
+        let authenticated = {
+            let mut ufrags = username.splitn(2, ':');
+            let local_ufrag = ufrags.next().unwrap_or("");
+            let remote_ufrag = ufrags.next();
 
-        if stream.state == StreamState::Completed {
-            debug!("Stream_id {} is complete", stream_id);
-            return;
+            local_ufrag == self.local_credentials.ufrag &&
+                remote_ufrag.map_or(false, |ufrag| self.remote_credentials.as_ref().map_or(false, |c| c.ufrag == ufrag)) &&
+                stun::check_integrity(packet, request, &key)
+        };
+        if !authenticated {
+            debug!("Binding request from {} as {} not authenticated", source, username);
+            return Some(error_response(request, 401, "Unauthorized", None))
+        }
+
+        if !self.lite && !self.resolve_role_conflict(request) {
+            debug!("Role conflict with {}, keeping role {:?}", source, self.role);
+            return Some(error_response(request, 487, "Role Conflict", Some(&key)))
+        }
+
+        let source = unmap_addr(source);
+        let use_candidate = request.has(stun::ATTR_USE_CANDIDATE);
+        let mut nominated = false;
+        if let Some(stream) = self.streams.get_mut(stream_id) {
+            let peer_candidate = find_peer_candidate(stream, *component_id, source, request.priority());
+            if !self.lite {
+                nominated = trigger_check(stream, *component_id, local_port, peer_candidate, use_candidate && self.role == Role::Controlled, self.role);
+            }
+        }
+
+        if use_candidate && (self.lite || nominated) {
+            debug!("Pair of {} nominated on stream_id {}", source, stream_id);
+            self.add_pair_candidate(stream_id, component_id, local_port, source);
+        }
+
+        let mut response = stun::Message::new(stun::BINDING_SUCCESS, request.transaction_id);
+        response.add_xor_mapped_address(source);
+
+        Some(response.encode(Some(&key), true))
+    }
+
+    /// Resolves a conflict between the role of the agent and the one the
+    /// request claims (RFC 8445, section 7.3.1.1), the agent with the larger
+    /// tie-breaker taking the controlling role. Whether the request is to
+    /// be processed, or rejected with a 487 otherwise.
+    fn resolve_role_conflict(&mut self, request: &stun::Message) -> bool {
+        match self.role {
+            Role::Controlling => match request.tie_breaker(stun::ATTR_ICE_CONTROLLING) {
+                Some(tie_breaker) if self.tie_breaker >= tie_breaker => false,
+                Some(_) => {
+                    self.set_role(Role::Controlled);
+                    true
+                },
+                None => true,
+            },
+            Role::Controlled => match request.tie_breaker(stun::ATTR_ICE_CONTROLLED) {
+                Some(tie_breaker) if self.tie_breaker >= tie_breaker => {
+                    self.set_role(Role::Controlling);
+                    true
+                },
+                Some(_) => false,
+                None => true,
+            },
+        }
+    }
+
+    /// Processes the response to one of our checks (RFC 8445, section
+    /// 7.2.5), which is valid only if from the address the check was sent to.
+    fn handle_response(&mut self, stream_id: &str, component_id: &u16, packet: &[u8], response: &stun::Message, source: SocketAddr) {
+        let key = match self.remote_credentials {
+            Some(ref credentials) => stun::short_term_key(&credentials.pwd),
+            None => return,
+        };
+        if !stun::check_integrity(packet, response, &key) {
+            debug!("Dropping response from {} not authenticated", source);
+            return
         }
 
+        let source = unmap_addr(source);
+        let mut switch_role = None;
+        let mut succeeded = None;
         {
-            /* Find remote_port amongst the offer candidates */
-            let offer_candidates: &mut Vec<Candidate> = stream.offer_candidates.entry(*component_id).or_insert(Vec::new());
-            let mut peer_candidate: Option<Candidate> = None;
-            for candidate in offer_candidates.iter() {
-                if candidate.port == remote_port {
-                    peer_candidate = Some(candidate.clone());
-                    break;
+            let stream = match self.streams.get_mut(stream_id) {
+                Some(stream) => stream,
+                None => return,
+            };
+            let pair = match stream.check_list.get_mut(component_id).and_then(|pairs| {
+                pairs.iter_mut().find(|p| p.transaction.as_ref().map_or(false, |t| t.id == response.transaction_id))
+            }) {
+                Some(pair) => pair,
+                None => {
+                    debug!("No check of stream_id {} for the response from {}", stream_id, source);
+                    return
+                },
+            };
+            let transaction = pair.transaction.take().unwrap();
+
+            if response.msg_type == stun::BINDING_ERROR {
+                if response.error_code() == Some(487) {
+                    // The peer kept its role, so we take the other and check again
+                    switch_role = Some(transaction.role.reverse());
+                    pair.state = PairState::Waiting;
+                    pair.triggered = true;
+                } else {
+                    debug!("Check from {} failed with {:?}", source, response.error_code());
+                    pair.state = PairState::Failed;
                 }
+            } else if pair.peer_candidate.socket_addr() != Some(source) {
+                debug!("Response from {} to a check sent elsewhere", source);
+                pair.state = PairState::Failed;
+            } else {
+                pair.state = PairState::Succeeded;
+                succeeded = Some((pair.clone(), transaction.nominating || pair.remote_nominated));
             }
+        }
 
-            /* Find local_port amongst the local candidates */
-            let local_candidates: &mut Vec<Candidate> = stream.local_candidates.entry(*component_id).or_insert(Vec::new());
-            let mut local_candidate: Option<Candidate> = None;
-            for candidate in local_candidates.iter() {
-                if candidate.port == local_port {
-                    local_candidate = Some(candidate.clone());
-                    break;
+        if let Some(role) = switch_role {
+            if role != self.role {
+                self.set_role(role);
+            }
+        }
+
+        if let Some((pair, nominated)) = succeeded {
+            let foundation = pair.foundation();
+            for (_, stream) in self.streams.iter_mut() {
+                for (_, pairs) in stream.check_list.iter_mut() {
+                    for p in pairs.iter_mut().filter(|p| p.state == PairState::Frozen && p.foundation() == foundation) {
+                        p.state = PairState::Waiting;
+                    }
                 }
             }
 
-            if peer_candidate.is_none() || local_candidate.is_none() {
-                debug!("No pair inserted for local port {} and remote_port {}!", local_port, remote_port);
-                return;
+            if let Some(stream) = self.streams.get_mut(stream_id) {
+                add_valid_pair(stream, *component_id, &pair, nominated);
             }
+            self.complete_stream(stream_id);
+        }
+    }
 
-            let pairs: &mut Vec<PairCandidate> = stream.valid_list.entry(*component_id).or_insert(Vec::new());
-            pairs.push(PairCandidate {
-                local_candidate: local_candidate.unwrap(),
-                peer_candidate: peer_candidate.unwrap(),
-            });
+    /// Next check of the agent, paced by Ta (RFC 8445, section 6.1.4.2):
+    /// a retransmission, a nomination, a triggered check, or else the
+    /// highest priority pair waiting, across the check lists of all the
+    /// streams. Lite agents never send checks.
+    pub fn poll(&mut self, now: Instant) -> Option<Transmit> {
+        if self.lite {
+            return None
+        }
+        let remote_credentials = match self.remote_credentials {
+            Some(ref credentials) => credentials.clone(),
+            None => return None,
+        };
+        if let Some(last_check) = self.last_check {
+            if now < last_check + self.ta {
+                return None
+            }
         }
 
-        /* Check if stream has a pair for all components, in which case it is considered completed */
-        if is_stream_complete(stream, &[RTP_COMPONENT_ID, RTCP_COMPONENT_ID]) {
-            stream.state = StreamState::Completed;
-            /* Choose which candidate pair to use and send it */
-            /* XXX(tlam): As of now we're only sending the first */
-            for (_, valid_list) in stream.valid_list.iter() {
-                for v in valid_list.iter() {
-                    self.handler.as_mut().unwrap().handle_callback(stream_id, v.peer_candidate.clone());
-                    break;
-                }
+        let rto = self.rto();
+        let role = self.role;
+        let mut stream_ids: Vec<String> = self.streams.keys().cloned().collect();
+        stream_ids.sort();
+
+        for stream_id in stream_ids {
+            let stream = self.streams.get_mut(&stream_id).unwrap();
+            if stream.state == StreamState::Completed {
+                continue;
+            }
+
+            let (component_id, index, check) = match next_check(stream, now, role) {
+                Some(next) => next,
+                None => continue,
+            };
+            let pair = &mut stream.check_list.get_mut(&component_id).unwrap()[index];
+
+            let transaction = match check {
+                Check::Retransmit => {
+                    let mut transaction = pair.transaction.take().unwrap();
+                    transaction.transmissions += 1;
+                    transaction.rto = transaction.rto * 2;
+                    transaction.retransmit_at = now + transaction.rto;
+
+                    transaction
+                },
+                Check::New { nominating } => {
+                    Transaction {
+                        id: rand::thread_rng().gen(),
+                        transmissions: 1,
+                        rto: rto,
+                        retransmit_at: now + rto,
+                        role: role,
+                        nominating: nominating,
+                    }
+                },
+            };
+            debug!("Checking {:?} from port {} (transmission {})",
+                   pair.peer_candidate.socket_addr(), pair.local_candidate.port, transaction.transmissions);
+
+            let transmit = Transmit {
+                stream_id: stream_id.clone(),
+                component_id: component_id,
+                local_addr: pair.local_candidate.socket_addr().unwrap(),
+                destination: pair.peer_candidate.socket_addr().unwrap(),
+                packet: binding_request(&transaction, &pair.local_candidate, self.tie_breaker, &self.local_credentials, &remote_credentials),
+            };
+
+            pair.state = PairState::InProgress;
+            pair.triggered = false;
+            pair.transaction = Some(transaction);
+            self.last_check = Some(now);
+
+            return Some(transmit)
+        }
+
+        None
+    }
+
+    /// Retransmission timeout of new checks, larger with more checks to be
+    /// paced (RFC 8445, section 14.3).
+    fn rto(&self) -> Duration {
+        let mut nr_checks = 0;
+        for (_, stream) in self.streams.iter() {
+            for (_, pairs) in stream.check_list.iter() {
+                nr_checks += pairs.iter().filter(|p| p.state == PairState::Waiting || p.state == PairState::InProgress).count();
             }
         }
+
+        cmp::max(Duration::from_millis(MIN_RTO_MS), self.ta * nr_checks as u32)
     }
 
-    fn pair_candidates(&mut self, stream_id: &str, component_id: &u16) {
-        // Compare remote candidates to local candidates as rfc#5245:
-        // - They have same component;
-        // - They utilize same transport protocol;
-        // - Same IP family (IPv4 and IPv6).
+    /// Completes the stream once each of its components has a nominated
+    /// pair, handing the peer candidate of each to the handler.
+    fn complete_stream(&mut self, stream_id: &str) {
         let stream = match self.streams.get_mut(stream_id) {
-            Some(stream) => { stream },
-            None => { return },
+            Some(stream) => stream,
+            None => return,
         };
 
-        for candidate in stream.local_candidates.get(component_id).unwrap().iter() {
-            for peer_candidate in stream.offer_candidates.get(component_id).unwrap().iter() {
-                if candidate.proto == peer_candidate.proto {
-                    if is_ipv4(&candidate.conn) && is_ipv4(&peer_candidate.conn) {
-                        debug!("Found pair candidate! {:?}:{:?}", candidate.conn, peer_candidate.conn);
-                        let pairs: &mut Vec<PairCandidate> = stream.valid_list.entry(*component_id).or_insert(Vec::new());
-                        pairs.push(PairCandidate {
-                            local_candidate: candidate.clone(),
-                            peer_candidate: peer_candidate.clone(),
-                        });
-                    }
-                }
-            }
+        /* Check if stream has a pair for all components, in which case it is considered completed */
+        if stream.state == StreamState::Completed || !is_stream_complete(stream, &stream.components) {
+            return;
         }
+        stream.state = StreamState::Completed;
 
-        if trigger_state_change(stream, component_id) {
-            // If there was a state change, trigger callback
-            match self.handler {
-                Some(ref mut _h) => {
-                    //h.handle_callback(stream_id);
-                },
-                None => {
-                    info!("Undelivered event. No callback set!");
-                },
+        /* Send the highest priority nominated pair of each component */
+        for component_id in stream.components.iter() {
+            let selected = stream.valid_list.get(component_id).and_then(|pairs| pairs.iter().find(|p| p.nominated));
+            if let Some(pair) = selected {
+                self.handler.as_mut().unwrap().handle_callback(stream_id, pair.peer_candidate.clone());
             }
         }
     }
 
-    fn set_priority_candidate(candidate: &mut Candidate, component_id: u16) {
-        let priority = ((2^24 as u32)*(126 as u32) +
-                        (2^8 as u32)*(65535 as u32) + // 65535 from #rfc5245
-                        (2^0 as u32)*((256 - component_id) as u32)) as u32;
+    /// Pairs of a component, ordered by priority, to be checked by a full
+    /// agent.
+    pub fn get_check_list(&self, stream_id: &str, component_id: &u16) -> Option<&Vec<PairCandidate>> {
+        self.streams.get(stream_id).and_then(|stream| stream.check_list.get(component_id))
+    }
+
+    /// Nominated pair of a component, the one media flows through.
+    pub fn get_selected_pair(&self, stream_id: &str, component_id: &u16) -> Option<&PairCandidate> {
+        self.streams.get(stream_id)
+            .and_then(|stream| stream.valid_list.get(component_id))
+            .and_then(|pairs| pairs.iter().find(|p| p.nominated))
+    }
+
+    /// Forms the check list of a component anew after its candidates change.
+    fn update_check_list(&mut self, stream_id: &str, component_id: &u16) {
+        if self.lite {
+            return
+        }
 
-        candidate.priority = priority;
+        let role = self.role;
+        if let Some(stream) = self.streams.get_mut(stream_id) {
+            form_check_list(stream, *component_id, role);
+            unfreeze_check_list(stream);
+        }
     }
 }
 
-fn is_stream_complete(stream: &Stream, components: &[u16]) -> bool {
-    for i in components.iter() {
-        let valid_list = stream.valid_list.get(i);
-        if valid_list.unwrap_or(&vec![]).len() < 1 {
-            return false;
+impl Drop for Agent {
+    fn drop(&mut self) {
+        self.release_ports();
+    }
+}
+
+/// Offered candidate of address `remote_addr`. If there's none, it is
+/// learnt as peer-reflexive (RFC 8445, section 7.3.1.3), likely hidden
+/// behind an mDNS name we couldn't resolve. Its priority is the one the
+/// peer's check carried, or else that of the unresolved candidate.
+fn find_peer_candidate(stream: &mut Stream, component_id: u16, remote_addr: SocketAddr, priority: Option<u32>) -> Candidate {
+    let unresolved_priority = stream.unresolved_candidates.get(&component_id)
+        .and_then(|c| c.first())
+        .map(|c| c.priority);
+
+    let offer_candidates: &mut Vec<Candidate> = stream.offer_candidates.entry(component_id).or_insert(Vec::new());
+    for candidate in offer_candidates.iter() {
+        if candidate.socket_addr() == Some(remote_addr) {
+            return candidate.clone();
         }
     }
 
-    return true;
+    let candidate = Candidate {
+        conn: Address::Ip(remote_addr.ip()),
+        port: remote_addr.port(),
+        proto: Proto::Udp,
+        foundation: random_ice_chars(8),
+        component_id: Some(component_id),
+        priority: priority.or(unresolved_priority).unwrap_or(0),
+        candidate_type: CandidateType::Prflx,
+        rel_addr: None,
+        rel_port: None,
+        extensions: vec![],
+    };
+    debug!("Learnt peer-reflexive candidate {} for stream_id {}", remote_addr, stream.id);
+
+    offer_candidates.push(candidate.clone());
+
+    candidate
 }
 
-fn trigger_state_change(stream: &mut Stream, _component_id: &u16) -> bool {
-    // Check if valid list has pairs for all components
-    // TODO(tlam): What if there is more than one candidate per component?
-    // (which can happen in dual IPv4 and IPv6 stacks)
-    if stream.local_candidates.len() == stream.valid_list.len() {
-        stream.state = StreamState::Completed;
+/// Local candidate on `local_port`, of the same family as `remote_addr`.
+fn find_local_candidate(stream: &Stream, component_id: u16, local_port: u16, remote_addr: SocketAddr) -> Option<Candidate> {
+    stream.local_candidates.get(&component_id).and_then(|candidates| {
+        candidates.iter()
+            .find(|c| c.port == local_port && is_same_family(&c.conn, &Address::Ip(remote_addr.ip())))
+            .cloned()
+    })
+}
 
-        return true
+/// Adds `pair` to the valid list of the component, unless there already,
+/// ordered by priority.
+fn add_valid_pair(stream: &mut Stream, component_id: u16, pair: &PairCandidate, nominated: bool) {
+    let pairs: &mut Vec<PairCandidate> = stream.valid_list.entry(component_id).or_insert(Vec::new());
+    let local_addr = pair.local_candidate.socket_addr();
+    let remote_addr = pair.peer_candidate.socket_addr();
+
+    if let Some(valid) = pairs.iter_mut().find(|p| p.is_pair_of(local_addr, remote_addr)) {
+        valid.nominated |= nominated;
+        return;
     }
 
-    false
+    let mut valid = pair.clone();
+    valid.nominated = nominated;
+    valid.transaction = None;
+    pairs.push(valid);
+    pairs.sort_by(|a, b| b.priority.cmp(&a.priority));
 }
 
-fn is_ipv4(conn: &IpAddr) -> bool {
-    match *conn {
-        IpAddr::V4(_) => {
-            debug!("Ipv4 address found");
+/// Pairs each local candidate of a component with the offered ones of the
+/// same protocol and family, ordered by priority (RFC 8445, section 6.1.2).
+/// Redundant pairs, of the same base and peer candidate, are pruned; host
+/// candidates being their own base.
+fn form_check_list(stream: &mut Stream, component_id: u16, role: Role) {
+    let local_candidates = stream.local_candidates.get(&component_id).cloned().unwrap_or(vec![]);
+    let offer_candidates = stream.offer_candidates.get(&component_id).cloned().unwrap_or(vec![]);
+    let pairs: &mut Vec<PairCandidate> = stream.check_list.entry(component_id).or_insert(Vec::new());
+
+    for candidate in local_candidates.iter() {
+        for peer_candidate in offer_candidates.iter() {
+            if candidate.proto != peer_candidate.proto || !is_same_family(&candidate.conn, &peer_candidate.conn) {
+                continue;
+            }
 
-            return true
-        },
-        IpAddr::V6(_) => {
-            debug!("Ipv6 address found");
+            if pairs.iter().any(|p| p.is_pair_of(candidate.socket_addr(), peer_candidate.socket_addr())) {
+                continue;
+            }
+
+            debug!("Found pair candidate! {:?}:{:?}", candidate.conn, peer_candidate.conn);
+            pairs.push(PairCandidate::new(candidate.clone(), peer_candidate.clone(), role));
+        }
+    }
+
+    pairs.sort_by(|a, b| b.priority.cmp(&a.priority));
+    pairs.truncate(MAX_CHECK_LIST_SIZE);
+}
+
+/// Sets the first pair of each foundation waiting, that of the lowest
+/// component and highest priority (RFC 8445, section 6.1.2.6). Pairs of
+/// foundations already being checked stay frozen.
+fn unfreeze_check_list(stream: &mut Stream) {
+    let mut components: Vec<u16> = stream.check_list.keys().cloned().collect();
+    components.sort();
+
+    let mut foundations = HashSet::new();
+    for (_, pairs) in stream.check_list.iter() {
+        for pair in pairs.iter().filter(|p| p.state != PairState::Frozen) {
+            foundations.insert(pair.foundation());
+        }
+    }
 
-            return false
+    for component_id in components {
+        for pair in stream.check_list.get_mut(&component_id).unwrap().iter_mut() {
+            if pair.state == PairState::Frozen && foundations.insert(pair.foundation()) {
+                pair.state = PairState::Waiting;
+            }
+        }
+    }
+}
+
+/// Pair of a check received from the peer, checked back soon in a
+/// triggered check (RFC 8445, section 7.3.1.4). Whether it was nominated,
+/// its check having succeeded already.
+fn trigger_check(stream: &mut Stream, component_id: u16, local_port: u16, peer_candidate: Candidate, nominating: bool, role: Role) -> bool {
+    let remote_addr = match peer_candidate.socket_addr() {
+        Some(remote_addr) => remote_addr,
+        None => return false,
+    };
+    let local_candidate = match find_local_candidate(stream, component_id, local_port, remote_addr) {
+        Some(local_candidate) => local_candidate,
+        None => return false,
+    };
+    let local_addr = local_candidate.socket_addr();
+
+    let pairs: &mut Vec<PairCandidate> = stream.check_list.entry(component_id).or_insert(Vec::new());
+    if !pairs.iter().any(|p| p.is_pair_of(local_addr, Some(remote_addr))) {
+        pairs.push(PairCandidate::new(local_candidate, peer_candidate, role));
+        pairs.sort_by(|a, b| b.priority.cmp(&a.priority));
+    }
+
+    let pair = pairs.iter_mut().find(|p| p.is_pair_of(local_addr, Some(remote_addr))).unwrap();
+    match pair.state {
+        PairState::Succeeded => return nominating,
+        // The check in flight will do
+        PairState::InProgress => {},
+        _ => {
+            pair.state = PairState::Waiting;
+            pair.triggered = true;
         },
     }
+    pair.remote_nominated |= nominating;
+
+    false
+}
+
+/// Next check of the stream, as the component and index of its pair.
+/// Pairs whose checks ran out of retransmissions fail on the way.
+fn next_check(stream: &mut Stream, now: Instant, role: Role) -> Option<(u16, usize, Check)> {
+    let mut components = stream.components.clone();
+    components.sort();
+
+    for component_id in components.iter() {
+        let pairs = match stream.check_list.get_mut(component_id) {
+            Some(pairs) => pairs,
+            None => continue,
+        };
+
+        for (i, pair) in pairs.iter_mut().enumerate() {
+            let due = pair.transaction.as_ref().map_or(false, |t| t.retransmit_at <= now);
+            if !due {
+                continue;
+            }
+
+            if pair.transaction.as_ref().unwrap().transmissions >= MAX_TRANSMISSIONS {
+                debug!("Check of {:?} timed out", pair.peer_candidate.socket_addr());
+                pair.state = PairState::Failed;
+                pair.transaction = None;
+                continue;
+            }
+
+            return Some((*component_id, i, Check::Retransmit))
+        }
+    }
+
+    // Regular nomination (RFC 8445, section 8.1.1): the controlling agent
+    // nominates the best valid pair of each component, once all have one
+    let all_valid = components.iter().all(|c| stream.valid_list.get(c).map_or(false, |p| !p.is_empty()));
+    if role == Role::Controlling && all_valid {
+        for component_id in components.iter() {
+            if stream.valid_list[component_id].iter().any(|p| p.nominated) {
+                continue;
+            }
+
+            let pairs = &stream.check_list[component_id];
+            if pairs.iter().any(|p| p.transaction.as_ref().map_or(false, |t| t.nominating)) {
+                continue;
+            }
+
+            let best = &stream.valid_list[component_id][0];
+            let local_addr = best.local_candidate.socket_addr();
+            let remote_addr = best.peer_candidate.socket_addr();
+            if let Some(i) = pairs.iter().position(|p| p.is_pair_of(local_addr, remote_addr) && p.transaction.is_none()) {
+                return Some((*component_id, i, Check::New { nominating: true }))
+            }
+        }
+    }
+
+    for state in [PairState::Waiting, PairState::Frozen].iter() {
+        for component_id in components.iter() {
+            let pairs = match stream.check_list.get(component_id) {
+                Some(pairs) => pairs,
+                None => continue,
+            };
+
+            let triggered = pairs.iter().position(|p| p.triggered && p.transaction.is_none());
+            let next = triggered.or_else(|| pairs.iter().position(|p| p.state == *state && p.transaction.is_none()));
+            if let Some(i) = next {
+                return Some((*component_id, i, Check::New { nominating: false }))
+            }
+        }
+    }
+
+    None
+}
+
+/// Check of a pair, from its local candidate, authenticated with the peer's
+/// password (RFC 8445, section 7.2.2).
+fn binding_request(transaction: &Transaction, local_candidate: &Candidate, tie_breaker: u64, local_credentials: &Credentials, remote_credentials: &Credentials) -> Vec<u8> {
+    let mut request = stun::Message::new(stun::BINDING_REQUEST, transaction.id);
+    request.add(stun::ATTR_USERNAME, format!("{}:{}", remote_credentials.ufrag, local_credentials.ufrag).into_bytes());
+    // Priority of the peer-reflexive candidate the check may discover
+    request.add_priority(CandidateType::Prflx.preference() << 24 | local_candidate.priority & 0xffffff);
+
+    match transaction.role {
+        Role::Controlling => request.add_tie_breaker(stun::ATTR_ICE_CONTROLLING, tie_breaker),
+        Role::Controlled => request.add_tie_breaker(stun::ATTR_ICE_CONTROLLED, tie_breaker),
+    }
+    if transaction.nominating {
+        request.add(stun::ATTR_USE_CANDIDATE, vec![]);
+    }
+
+    request.encode(Some(&stun::short_term_key(&remote_credentials.pwd)), true)
 }
 
+fn is_stream_complete(stream: &Stream, components: &[u16]) -> bool {
+    for i in components.iter() {
+        let valid_list = stream.valid_list.get(i);
+        if !valid_list.map_or(false, |pairs| pairs.iter().any(|p| p.nominated)) {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+/// Error response to `request`, authenticated with `key` when the request
+/// itself was.
+fn error_response(request: &stun::Message, code: u16, reason: &str, key: Option<&[u8]>) -> Vec<u8> {
+    let mut response = stun::Message::new(stun::BINDING_ERROR, request.transaction_id);
+    response.add_error_code(code, reason);
+
+    response.encode(key, true)
+}
@@ -3,9 +3,20 @@ extern crate log;
 extern crate log4rs;
 extern crate rir;
 extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
 pub mod sdp;
+pub mod offer_answer;
+pub mod codec;
 pub mod ice;
+pub mod mdns;
+pub mod stun;
+pub mod ports;
+pub mod transport;
+pub mod dtls;
 pub mod protos;
 pub mod convo;
 
@@ -6,17 +6,32 @@ extern crate opus;
 #[macro_use]
 extern crate nickel;
 extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 extern crate uuid;
 
 mod sdp;
+mod offer_answer;
+mod codec;
 mod ice;
+mod mdns;
+mod stun;
+mod ports;
+mod transport;
+mod dtls;
 mod protos;
 mod convo;
 
-use sdp::{SessionDescription, Origin};
+use sdp::{SessionDescriptionBuilder, Origin};
+use offer_answer::LocalParams;
+use std::env;
 use std::net::{IpAddr, Ipv6Addr};
 use protos::Handlers;
 use convo::convo::{Conferences};
+use ports::PortAllocator;
+use ice::InterfaceFilter;
 
 fn main() {
 
@@ -24,23 +39,15 @@ fn main() {
 
     info!("Firing up!...");
 
-    let mut s1 = SessionDescription::new();
-    s1.ver = Some(1);
-    s1.origin = Some(Origin {
-        username: "me".to_string(),
-        session_id: "sessA".to_string(),
-        session_version: 11,
-        net_type: sdp::NetType::IN,
-        addr_type: sdp::AddrType::IP4,
-        ip_address: IpAddr::V6(Ipv6Addr::new(0,0,0,0,0,0xffff,5,2))
-    });
+    let s1 = SessionDescriptionBuilder::new(Origin::new("me", "sessA", 11, IpAddr::V6(Ipv6Addr::new(0,0,0,0,0,0xffff,5,2))))
+        .build();
 
     let s3_res = s1.from_sdp("
         v=0
         o=jdoe 2890844526 2890842807 IN IP4 10.47.16.5
         s=-
         i=A Seminar on the session description protocol
-        c=IN IP4 224.2.17.12
+        c=IN IP4 224.2.17.12/127
         a=recvonly
         t=0 0
         m=audio 49170 RTP/AVP 0
@@ -52,6 +59,52 @@ fn main() {
     //let tcp_server = protos::tcpserver::tcp::new();
     //tcp_server.start_server();
     //protos::tcpserver::tcp::start_server();
-    protos::httpserver::HttpServer::start_server(Conferences::init());
+    let mut local_params = LocalParams::new();
+    match dtls::Certificate::from_der_file("config/dtls_cert.der") {
+        Ok(cert) => {
+            local_params.fingerprint = Some(cert.fingerprint());
+        },
+        Err(x) => {
+            warn!("Failed to load DTLS certificate: {}", x);
+        },
+    }
+
+    // Lite agents only answer checks, set to "0" for full ones checking
+    // the peers too, e.g. lite ones
+    local_params.ice_lite = env::var("HIBRIDO_ICE_LITE").map(|v| v != "0" && v != "false").unwrap_or(true);
+
+    let mut convos = Conferences::with_params(local_params);
+    // Media ports, e.g. "10000-10999", for a range open in the firewall
+    if let Ok(range) = env::var("HIBRIDO_PORT_RANGE") {
+        match range.parse::<PortAllocator>() {
+            Ok(ports) => {
+                info!("Allocating media ports in {:?}", ports.range());
+                convos.set_port_allocator(ports);
+            },
+            Err(x) => {
+                warn!("Ignoring HIBRIDO_PORT_RANGE: {}", x);
+            },
+        }
+    }
+
+    // Interfaces gathered on, by name or network, e.g. "eth0,10.0.0.0/8"
+    // and "docker*", and whether loopback is too
+    let mut interfaces = InterfaceFilter::new();
+    if let Ok(rules) = env::var("HIBRIDO_INTERFACES") {
+        match InterfaceFilter::parse_rules(&rules) {
+            Ok(rules) => interfaces.include = rules,
+            Err(x) => warn!("Ignoring HIBRIDO_INTERFACES: {}", x),
+        }
+    }
+    if let Ok(rules) = env::var("HIBRIDO_EXCLUDE_INTERFACES") {
+        match InterfaceFilter::parse_rules(&rules) {
+            Ok(rules) => interfaces.exclude = rules,
+            Err(x) => warn!("Ignoring HIBRIDO_EXCLUDE_INTERFACES: {}", x),
+        }
+    }
+    interfaces.loopback = env::var("HIBRIDO_LOOPBACK").map(|v| v == "1" || v == "true").unwrap_or(false);
+    convos.set_interface_filter(interfaces);
+
+    protos::httpserver::HttpServer::start_server(convos);
 }
 
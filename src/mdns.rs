@@ -0,0 +1,192 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Group mDNS queries are sent to (RFC 6762, section 3).
+pub const MDNS_GROUP: &'static str = "224.0.0.251:5353";
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+/// Asks for the answer to be unicast back (RFC 6762, section 5.4).
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Whether `name` is to be resolved with mDNS rather than DNS, as the
+/// names browsers hide host candidates behind.
+pub fn is_mdns_name(name: &str) -> bool {
+    name.trim_end_matches('.').to_lowercase().ends_with(".local")
+}
+
+/// One-shot mDNS querier (RFC 6762, section 5.1): queries are sent from an
+/// ephemeral port, and answered by unicast to it.
+#[derive(Clone, Debug)]
+pub struct Querier {
+    group: SocketAddr,
+    timeout: Duration,
+}
+
+impl Querier {
+    pub fn new() -> Querier {
+        Querier::with_group(MDNS_GROUP.parse().unwrap(), Duration::from_millis(500))
+    }
+
+    /// A querier sending to `group` instead, e.g. a responder on loopback.
+    pub fn with_group(group: SocketAddr, timeout: Duration) -> Querier {
+        Querier {
+            group: group,
+            timeout: timeout,
+        }
+    }
+
+    /// Resolves `name` into the first address answered for it, if any
+    /// responder answers before the timeout.
+    pub fn resolve(&self, name: &str) -> Option<IpAddr> {
+        let bind_addr = match self.group {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = match UdpSocket::bind(bind_addr) {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("Failed to bind mDNS socket: {}", e);
+                return None
+            },
+        };
+        // Responders ignore queries whose TTL isn't 255 (RFC 6762, section 11)
+        let _ = socket.set_multicast_ttl_v4(255);
+
+        if let Err(e) = socket.send_to(&encode_query(name), self.group) {
+            debug!("Failed to query {} for {}: {}", self.group, name, e);
+            return None
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let mut buffer = [0; 1500];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                debug!("No mDNS answer for {}", name);
+                return None
+            }
+            socket.set_read_timeout(Some(deadline - now)).unwrap();
+
+            let size = match socket.recv_from(&mut buffer) {
+                Ok((size, _)) => size,
+                Err(_) => continue,
+            };
+
+            if let Some(ip) = parse_response(&buffer[..size], name) {
+                debug!("Resolved {} to {}", name, ip);
+                return Some(ip)
+            }
+        }
+    }
+}
+
+/// Query for the IPv4 and IPv6 addresses of `name`.
+pub fn encode_query(name: &str) -> Vec<u8> {
+    // ID, flags, 2 questions and no records
+    let mut packet = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0];
+
+    for &qtype in [TYPE_A, TYPE_AAAA].iter() {
+        encode_name(&mut packet, name);
+        push_u16(&mut packet, qtype);
+        push_u16(&mut packet, CLASS_IN | UNICAST_RESPONSE);
+    }
+
+    packet
+}
+
+fn encode_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+fn push_u16(packet: &mut Vec<u8>, value: u16) {
+    packet.push((value >> 8) as u8);
+    packet.push(value as u8);
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    if offset + 2 > packet.len() {
+        return None
+    }
+
+    Some((packet[offset] as u16) << 8 | packet[offset + 1] as u16)
+}
+
+/// Reads the name at `offset`, following compression pointers, along with
+/// the offset past it.
+fn read_name(packet: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels = vec![];
+    let mut offset = offset;
+    let mut end = None;
+
+    // Bounded, so pointer loops can't hang us
+    for _ in 0..128 {
+        let len = *packet.get(offset)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(offset + 1)))
+        }
+
+        if len & 0xc0 == 0xc0 {
+            let pointer = read_u16(packet, offset)? as usize & 0x3fff;
+            end = end.or(Some(offset + 2));
+            offset = pointer;
+            continue;
+        }
+
+        let label = packet.get(offset + 1..offset + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        offset += 1 + len;
+    }
+
+    None
+}
+
+/// First address `packet`, an mDNS response, answers for `name`.
+pub fn parse_response(packet: &[u8], name: &str) -> Option<IpAddr> {
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0 {
+        // A query, not a response
+        return None
+    }
+
+    let nr_questions = read_u16(packet, 4)?;
+    let nr_records = read_u16(packet, 6)? as usize + read_u16(packet, 8)? as usize + read_u16(packet, 10)? as usize;
+
+    let mut offset = 12;
+    for _ in 0..nr_questions {
+        let (_, next) = read_name(packet, offset)?;
+        offset = next + 4;
+    }
+
+    let name = name.trim_end_matches('.');
+    for _ in 0..nr_records {
+        let (record_name, next) = read_name(packet, offset)?;
+        let rtype = read_u16(packet, next)?;
+        let rdlength = read_u16(packet, next + 8)? as usize;
+        let rdata = packet.get(next + 10..next + 10 + rdlength)?;
+        offset = next + 10 + rdlength;
+
+        if !record_name.eq_ignore_ascii_case(name) {
+            continue;
+        }
+
+        match (rtype, rdata.len()) {
+            (TYPE_A, 4) => {
+                return Some(IpAddr::V4(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])))
+            },
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(rdata);
+                return Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            },
+            _ => {},
+        }
+    }
+
+    None
+}
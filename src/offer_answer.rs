@@ -0,0 +1,715 @@
+extern crate rand;
+
+use std::cmp;
+use std::net::{IpAddr, Ipv4Addr};
+
+use sdp::{SessionDescription, Origin, Address, Connection, MediaDescription, Media, MediaType, MediaProto, Attr, Direction};
+use sdp::{Bandwidth, BandwidthType, bitrate_of};
+use sdp::{FingerprintValue, SetupValue, GroupValue, GroupSemantics};
+use sdp::{RtcpFbKind, RidValue, RidDirection, SimulcastValue, SimulcastRid};
+use sdp::{FmtPValue, IceUfragValue, IcePwdValue, SUPPORTED_EXTENSIONS};
+use codec::{CodecRegistry, find_rtpmap, negotiate_fmtp};
+use ice;
+
+/// Attributes hibrido doesn't interpret, but which hold in the answer as
+/// they were offered. Any other is stripped, as echoing it could claim
+/// an extension we don't implement, e.g. "ice-options:trickle" or
+/// "rtcp-rsize", or describe the offerer itself.
+const PASSTHROUGH_ATTRS: &'static [&'static str] = &[
+    // Role of the media, e.g. slides (RFC 4796)
+    "content",
+];
+
+/// Whether an attribute not understood by hibrido is passed through to the
+/// answer.
+fn is_passthrough_attr(attr: &Attr) -> bool {
+    match *attr {
+        Attr::Other { ref name, .. } => PASSTHROUGH_ATTRS.contains(&name.as_str()),
+        _ => false,
+    }
+}
+
+/// Removes the attributes not understood by hibrido that are not to be
+/// passed through.
+fn strip_unknown_attrs(attrs: &mut Vec<Attr>) {
+    attrs.retain(|attr| match *attr {
+        Attr::Other { .. } => is_passthrough_attr(attr),
+        _ => true,
+    });
+}
+
+/// Parameters of the local endpoint, put in the answers it produces.
+#[derive(Clone, Debug)]
+pub struct LocalParams {
+    /// Fingerprint of the certificate used in DTLS handshakes.
+    pub fingerprint: Option<FingerprintValue>,
+    /// Whether offered BUNDLE groups are accepted.
+    pub bundle: bool,
+    /// Whether RTP and RTCP are multiplexed, when offered.
+    pub rtcp_mux: bool,
+    /// RTCP feedback hibrido implements, only these are answered.
+    pub rtcp_fb: Vec<RtcpFbKind>,
+    /// ICE credentials of the session being answered.
+    pub ice_credentials: Option<ice::Credentials>,
+    /// Whether the agents are lite, only answering the peer's checks, or
+    /// full, sending checks of their own.
+    pub ice_lite: bool,
+    /// User and address the answers originate from, in their "o=" line.
+    pub username: String,
+    pub address: Address,
+    /// Name of the sessions answered, in their "s=" line.
+    pub session_name: String,
+    /// Codecs the first member's offer is answered with.
+    pub codecs: CodecRegistry,
+    /// Most bits per second received on each audio media, if limited.
+    pub audio_bitrate: Option<u64>,
+    /// Most bits per second received on each video media, if limited.
+    pub video_bitrate: Option<u64>,
+    /// Most bits per second received on a session, all media together.
+    pub session_bitrate: Option<u64>,
+}
+
+impl LocalParams {
+    pub fn new() -> LocalParams {
+        LocalParams {
+            fingerprint: None,
+            bundle: true,
+            rtcp_mux: true,
+            rtcp_fb: vec![],
+            ice_credentials: None,
+            ice_lite: true,
+            // Not meaningful, as addresses are in the candidates (RFC 8829,
+            // section 5.2.1)
+            username: "-".to_string(),
+            address: Address::Ip(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))),
+            session_name: "-".to_string(),
+            codecs: CodecRegistry::platform(),
+            audio_bitrate: None,
+            video_bitrate: None,
+            session_bitrate: None,
+        }
+    }
+
+    /// Most bits per second received on media of type `media`.
+    pub fn media_bitrate(&self, media: &MediaType) -> Option<u64> {
+        match *media {
+            MediaType::AUDIO => self.audio_bitrate,
+            MediaType::VIDEO => self.video_bitrate,
+            _ => None,
+        }
+    }
+}
+
+/// Answers the bandwidth of each of `types` with the lower of the offered
+/// one and `local_bitrate`, itself held to the offer's limits. Other types
+/// are dropped, as not understood (RFC 4566, section 5.8).
+fn negotiate_bandwidths(offered: &[Bandwidth], types: &[BandwidthType], local_bitrate: Option<u64>) -> Vec<Bandwidth> {
+    let local_bitrate = match (local_bitrate, bitrate_of(offered)) {
+        (Some(local), Some(offered)) => Some(cmp::min(local, offered)),
+        (local, _) => local,
+    };
+
+    types.iter().filter_map(|bwtype| {
+        let offer = offered.iter().filter(|b| b.bwtype == *bwtype).map(|b| b.bandwidth).next();
+        let local = local_bitrate.map(|bps| match *bwtype {
+            BandwidthType::TIAS => bps,
+            // In kilobits, rounded up
+            _ => bps.saturating_add(999) / 1000,
+        });
+
+        let bandwidth = match (offer, local) {
+            (Some(offer), Some(local)) => Some(cmp::min(offer, local)),
+            (offer, local) => offer.or(local),
+        };
+
+        bandwidth.map(|bandwidth| Bandwidth {
+            bwtype: bwtype.clone(),
+            bandwidth: bandwidth,
+        })
+    }).collect()
+}
+
+/// Answers with "a=rtcp-mux" the media offered with it, if multiplexing
+/// is wanted. The offerer's "a=rtcp" is never echoed back.
+fn negotiate_rtcp_mux(sdp_offer: &SessionDescription, sdp_answer: &mut SessionDescription, local: &LocalParams) {
+    for (offer_media, answer_media) in sdp_offer.media.iter().zip(sdp_answer.media.iter_mut()) {
+        answer_media.attrs.retain(|attr| match *attr {
+            Attr::RtcpMux | Attr::Rtcp(_) => false,
+            _ => true,
+        });
+
+        if !local.rtcp_mux || !offer_media.rtcp_mux() {
+            continue;
+        }
+
+        // Payload types 64-95 clash with RTCP packet types (RFC 5761,
+        // section 4)
+        for fmt in answer_media.media.fmt.iter() {
+            match fmt.parse::<u8>() {
+                Ok(pt) if pt >= 64 && pt <= 95 => {
+                    warn!("Payload type {} conflicts with multiplexed RTCP", pt);
+                },
+                _ => {},
+            }
+        }
+
+        answer_media.attrs.push(Attr::RtcpMux);
+    }
+}
+
+/// Answers, for each payload type kept, the offered RTCP feedback that is
+/// also implemented locally.
+fn negotiate_rtcp_fb(sdp_offer: &SessionDescription, sdp_answer: &mut SessionDescription, local: &LocalParams) {
+    for (offer_media, answer_media) in sdp_offer.media.iter().zip(sdp_answer.media.iter_mut()) {
+        answer_media.attrs.retain(|attr| match *attr {
+            Attr::RtcpFb(_) => false,
+            _ => true,
+        });
+
+        if !answer_media.media.proto.has_feedback() {
+            continue;
+        }
+
+        let answer_pts = answer_media.media.fmt.iter()
+            .filter_map(|fmt| fmt.parse::<u32>().ok())
+            .collect::<Vec<u32>>();
+
+        for attr in offer_media.attrs.iter() {
+            let rtcp_fb = match *attr {
+                Attr::RtcpFb(ref x) => x,
+                _ => continue,
+            };
+
+            if !local.rtcp_fb.contains(&rtcp_fb.kind) {
+                debug!("Feedback {} not supported", rtcp_fb.kind.to_string());
+                continue;
+            }
+
+            if rtcp_fb.payload_type.map_or(true, |pt| answer_pts.contains(&pt)) {
+                answer_media.attrs.push(attr.clone());
+            }
+        }
+    }
+}
+
+/// Keeps the header extensions hibrido supports, with the IDs the offerer
+/// chose (RFC 8285, section 6).
+fn negotiate_extmap_attrs(offer_attrs: &[Attr]) -> Vec<Attr> {
+    let mut answer_attrs = vec![];
+    for attr in offer_attrs.iter() {
+        match *attr {
+            Attr::ExtMap { id, ref direction, ref uri, ref attrs } => {
+                if !SUPPORTED_EXTENSIONS.contains(&uri.as_str()) {
+                    debug!("Header extension {} not supported", uri);
+                    continue;
+                }
+
+                answer_attrs.push(Attr::ExtMap {
+                    id: id,
+                    direction: direction.as_ref().map(|d| d.reverse()),
+                    uri: uri.to_string(),
+                    attrs: attrs.clone(),
+                });
+            },
+            _ => {},
+        }
+    }
+
+    answer_attrs
+}
+
+fn is_extmap_attr(attr: &Attr) -> bool {
+    match *attr {
+        Attr::ExtMap { .. } => true,
+        _ => false,
+    }
+}
+
+/// Answers the header extensions offered, at session and media level.
+fn negotiate_extmap(sdp_offer: &SessionDescription, sdp_answer: &mut SessionDescription) {
+    sdp_answer.attrs.retain(|attr| !is_extmap_attr(attr));
+    sdp_answer.attrs.extend(negotiate_extmap_attrs(&sdp_offer.attrs));
+
+    for (offer_media, answer_media) in sdp_offer.media.iter().zip(sdp_answer.media.iter_mut()) {
+        answer_media.attrs.retain(|attr| !is_extmap_attr(attr));
+        answer_media.attrs.extend(negotiate_extmap_attrs(&offer_media.attrs));
+    }
+}
+
+/// Accepts to receive the simulcast streams offered, reversing the RIDs'
+/// direction (RFC 8853, section 5.3). RIDs restricted to payload types not
+/// in the answer are dropped. Sending simulcast is not supported.
+fn negotiate_simulcast(sdp_offer: &SessionDescription, sdp_answer: &mut SessionDescription) {
+    for (offer_media, answer_media) in sdp_offer.media.iter().zip(sdp_answer.media.iter_mut()) {
+        answer_media.attrs.retain(|attr| match *attr {
+            Attr::Rid(_) | Attr::Simulcast(_) => false,
+            _ => true,
+        });
+
+        let offer_simulcast = match offer_media.simulcast() {
+            Some(simulcast) => simulcast,
+            None => continue,
+        };
+
+        let answer_pts = answer_media.media.fmt.iter()
+            .filter_map(|fmt| fmt.parse::<u32>().ok())
+            .collect::<Vec<u32>>();
+
+        let mut rids = vec![];
+        for attr in offer_media.attrs.iter() {
+            let rid = match *attr {
+                Attr::Rid(ref x) if x.direction == RidDirection::Send => x,
+                _ => continue,
+            };
+
+            let payload_types = rid.payload_types.iter()
+                .filter(|pt| answer_pts.contains(pt))
+                .cloned()
+                .collect::<Vec<u32>>();
+            if payload_types.is_empty() && !rid.payload_types.is_empty() {
+                debug!("No payload type left for rid {}", rid.id);
+                continue;
+            }
+
+            rids.push(RidValue {
+                id: rid.id.clone(),
+                direction: rid.direction.reverse(),
+                payload_types: payload_types,
+                restrictions: rid.restrictions.clone(),
+            });
+        }
+
+        let recv = offer_simulcast.send.iter()
+            .map(|alternatives| {
+                alternatives.iter()
+                    .filter(|sim_rid| rids.iter().any(|rid| rid.id == sim_rid.id))
+                    .cloned()
+                    .collect::<Vec<SimulcastRid>>()
+            })
+            .filter(|alternatives| !alternatives.is_empty())
+            .collect::<Vec<Vec<SimulcastRid>>>();
+        if recv.is_empty() {
+            continue;
+        }
+
+        for rid in rids {
+            answer_media.attrs.push(Attr::Rid(rid));
+        }
+        answer_media.attrs.push(Attr::Simulcast(SimulcastValue {
+            send: vec![],
+            recv: recv,
+        }));
+    }
+}
+
+/// Removes the sources the offerer declared, the answer only carries ours.
+fn strip_sources(sdp_answer: &mut SessionDescription) {
+    for answer_media in sdp_answer.media.iter_mut() {
+        answer_media.attrs.retain(|attr| match *attr {
+            Attr::Ssrc(_) | Attr::SsrcGroup(_) | Attr::Msid(_) => false,
+            _ => true,
+        });
+    }
+}
+
+/// Answers the BUNDLE groups of the offer, keeping only the media that was
+/// accepted, or removing the groups altogether if bundling is not wanted.
+fn negotiate_bundle(sdp_answer: &mut SessionDescription, rejected: &[bool], local: &LocalParams) {
+    let accepted_mids = sdp_answer.media.iter().enumerate()
+        .filter(|&(i, _)| !rejected.get(i).cloned().unwrap_or(false))
+        .filter_map(|(_, media)| media.mid().map(|mid| mid.to_string()))
+        .collect::<Vec<String>>();
+
+    let mut answer_attrs = vec![];
+    for attr in sdp_answer.attrs.drain(0..) {
+        match attr {
+            Attr::Group(GroupValue { semantics: GroupSemantics::Bundle, mids }) => {
+                if !local.bundle {
+                    debug!("Rejecting BUNDLE group {:?}", mids);
+                    continue;
+                }
+
+                // The first accepted media becomes the one whose transport
+                // is shared (RFC 8843, section 7.3.1)
+                let mids = mids.into_iter()
+                    .filter(|mid| accepted_mids.contains(mid))
+                    .collect::<Vec<String>>();
+                if mids.is_empty() {
+                    continue;
+                }
+
+                answer_attrs.push(Attr::Group(GroupValue {
+                    semantics: GroupSemantics::Bundle,
+                    mids: mids,
+                }));
+            },
+            _ => {
+                answer_attrs.push(attr);
+            },
+        }
+    }
+
+    sdp_answer.attrs = answer_attrs;
+}
+
+fn is_dtls_attr(attr: &Attr) -> bool {
+    match *attr {
+        Attr::Fingerprint(_) | Attr::Setup(_) => true,
+        _ => false,
+    }
+}
+
+fn find_fingerprint(attrs: &[Attr]) -> Option<&FingerprintValue> {
+    attrs.iter().filter_map(|attr| match *attr {
+        Attr::Fingerprint(ref x) => Some(x),
+        _ => None,
+    }).next()
+}
+
+fn find_setup(attrs: &[Attr]) -> Option<&SetupValue> {
+    attrs.iter().filter_map(|attr| match *attr {
+        Attr::Setup(ref x) => Some(x),
+        _ => None,
+    }).next()
+}
+
+/// Replaces the offerer's DTLS attributes in the answer with our own, for
+/// each media stream that is to be secured with DTLS.
+fn negotiate_dtls(sdp_offer: &SessionDescription, sdp_answer: &mut SessionDescription, local: &LocalParams) {
+    // Ours are always set at the media level
+    sdp_answer.attrs.retain(|attr| !is_dtls_attr(attr));
+
+    for (offer_media, answer_media) in sdp_offer.media.iter().zip(sdp_answer.media.iter_mut()) {
+        answer_media.attrs.retain(|attr| !is_dtls_attr(attr));
+
+        // Media-level attributes take precedence over session-level ones
+        let offer_fingerprint = find_fingerprint(&offer_media.attrs)
+            .or(find_fingerprint(&sdp_offer.attrs));
+        let offer_setup = find_setup(&offer_media.attrs)
+            .or(find_setup(&sdp_offer.attrs));
+
+        if offer_fingerprint.is_none() && offer_media.media.proto != MediaProto::UdpTlsRtpSavpf {
+            continue;
+        }
+
+        match local.fingerprint {
+            Some(ref fingerprint) => {
+                answer_media.attrs.push(Attr::Fingerprint(fingerprint.clone()));
+            },
+            None => {
+                warn!("No local certificate, DTLS handshake for {} is bound to fail",
+                      answer_media.media.media.to_string());
+            },
+        }
+
+        let setup = SetupValue::answer_to(offer_setup);
+        debug!("Answering setup {:?} with {:?}", offer_setup, setup);
+        answer_media.attrs.push(Attr::Setup(setup));
+    }
+}
+
+
+/// Direction to answer with, given the offered one and the one supported
+/// locally: only what the offerer receives is sent, and only what it sends
+/// is received (RFC 3264, section 6.1).
+pub fn answer_direction(offered: &Direction, local: &Direction) -> Direction {
+    let offered = offered.reverse();
+
+    match (offered.sends() && local.sends(), offered.receives() && local.receives()) {
+        (true, true) => Direction::SendRecv,
+        (true, false) => Direction::SendOnly,
+        (false, true) => Direction::RecvOnly,
+        (false, false) => Direction::Inactive,
+    }
+}
+
+/// Formats offered that are in the base media as well, with the same
+/// encoding.
+fn match_formats(base_media: &MediaDescription, offer_media: &MediaDescription) -> Vec<String> {
+    offer_media.media.fmt.iter()
+        .filter(|fmt| base_media.media.fmt.contains(fmt))
+        .filter(|fmt| match fmt.parse::<u32>() {
+            Ok(pt) => find_rtpmap(base_media, pt) == find_rtpmap(offer_media, pt),
+            // Not an RTP payload type, nothing to match against
+            Err(_) => false,
+        })
+        .cloned()
+        .collect()
+}
+
+/// What a media is accepted with.
+struct MediaMatch {
+    fmt: Vec<String>,
+    fmtps: Vec<FmtPValue>,
+    direction: Direction,
+}
+
+/// Agrees on the parameters of the formats `fmt`, given ours by
+/// `local_fmtp`. Formats whose parameters are incompatible are dropped.
+fn negotiate_formats<F>(offer_media: &MediaDescription, fmt: Vec<String>, local_fmtp: F) -> (Vec<String>, Vec<FmtPValue>)
+    where F: Fn(u32) -> Option<FmtPValue> {
+    let mut formats = vec![];
+    let mut fmtps = vec![];
+
+    for format in fmt {
+        let pt = match format.parse::<u32>() {
+            Ok(pt) => pt,
+            Err(_) => continue,
+        };
+        let rtpmap = match find_rtpmap(offer_media, pt) {
+            Some(rtpmap) => rtpmap,
+            None => continue,
+        };
+
+        let offered = offer_media.fmtp(pt).cloned().unwrap_or(FmtPValue::new(pt));
+        let local = local_fmtp(pt).unwrap_or(FmtPValue::new(pt));
+        match negotiate_fmtp(&rtpmap.encoding_name, &offered, &local) {
+            Ok(fmtp) => {
+                if !fmtp.params.is_empty() {
+                    fmtps.push(fmtp);
+                }
+                formats.push(format);
+            },
+            Err(e) => {
+                debug!("Format {} of {} dropped: {}", pt, rtpmap.encoding_name, e);
+            },
+        }
+    }
+
+    (formats, fmtps)
+}
+
+/// Formats, with their parameters, and direction to answer the media of
+/// index `media_idx` with, or `None` if it is to be rejected. Without a base
+/// SDP, the offered formats supported by the media engine are accepted.
+fn match_media(sdp_base: Option<&SessionDescription>, sdp_offer: &SessionDescription, media_idx: usize, local: &LocalParams) -> Option<MediaMatch> {
+    let offer_media = &sdp_offer.media[media_idx];
+    let offer_direction = sdp_offer.media_direction(media_idx);
+
+    if offer_media.is_rejected() {
+        debug!("Media {} disabled by the offerer", media_idx);
+        return None
+    }
+
+    if !offer_media.media.proto.is_rtp() {
+        debug!("Media proto {} not supported", offer_media.media.proto.to_string());
+        return None
+    }
+
+    let sdp_base = match sdp_base {
+        Some(sdp_base) => sdp_base,
+        None => {
+            let (fmt, fmtps) = negotiate_formats(offer_media, local.codecs.intersect(offer_media), |pt| {
+                local.codecs.find_format(offer_media, pt)
+                    .and_then(|codec| codec.fmtp.as_ref())
+                    .map(|fmtp| FmtPValue { format: pt, params: FmtPValue::parse_params(fmtp) })
+            });
+            if fmt.is_empty() {
+                debug!("No supported codec in {}", offer_media.to_string());
+                return None
+            }
+
+            return Some(MediaMatch {
+                fmt: fmt,
+                fmtps: fmtps,
+                direction: answer_direction(&offer_direction, &Direction::SendRecv),
+            })
+        },
+    };
+
+    // The base SDP describes what the conference handles
+    for (i, base_media) in sdp_base.media.iter().enumerate() {
+        if base_media.media.media != offer_media.media.media || base_media.is_rejected() {
+            continue;
+        }
+
+        let (fmt, fmtps) = negotiate_formats(offer_media, match_formats(base_media, offer_media), |pt| {
+            base_media.fmtp(pt).cloned()
+        });
+        if fmt.is_empty() {
+            debug!("Media {} and {} are not a match", base_media.to_string(), offer_media.to_string());
+            continue;
+        }
+
+        return Some(MediaMatch {
+            fmt: fmt,
+            fmtps: fmtps,
+            direction: answer_direction(&offer_direction, &sdp_base.media_direction(i)),
+        })
+    }
+
+    None
+}
+
+/// Answers the media of index `media_idx`, as `accepted`. Rejected media
+/// are given port 0 and keep the offered formats (RFC 3264, section 6).
+fn answer_media(sdp_offer: &SessionDescription, media_idx: usize, accepted: Option<MediaMatch>, local: &LocalParams) -> MediaDescription {
+    let offer_media = &sdp_offer.media[media_idx];
+
+    let (port, fmt) = match accepted {
+        Some(ref accepted) => (9, accepted.fmt.clone()),
+        None => (0, offer_media.media.fmt.clone()),
+    };
+
+    let mut answer_media = MediaDescription::new(Media {
+        media: offer_media.media.media.clone(),
+        port: port,
+        num_ports: None,
+        proto: offer_media.media.proto.clone(),
+        fmt: fmt,
+    });
+
+    if let Some(mid) = offer_media.mid() {
+        answer_media.attrs.push(Attr::Mid(mid.to_string()));
+    }
+
+    let accepted = match accepted {
+        Some(accepted) => accepted,
+        None => return answer_media,
+    };
+
+    answer_media.bandwidths = negotiate_bandwidths(&offer_media.bandwidths,
+                                                   &[BandwidthType::AS, BandwidthType::TIAS],
+                                                   local.media_bitrate(&offer_media.media.media));
+
+    let answer_pts = answer_media.media.fmt.iter()
+        .filter_map(|fmt| fmt.parse::<u32>().ok())
+        .collect::<Vec<u32>>();
+
+    for attr in offer_media.attrs.iter() {
+        match *attr {
+            Attr::RtpMap(ref x) if answer_pts.contains(&x.payload_type) => {
+                answer_media.attrs.push(attr.clone());
+            },
+            Attr::PTime(_) => {
+                answer_media.attrs.push(attr.clone());
+            },
+            Attr::Other { .. } if is_passthrough_attr(attr) => {
+                answer_media.attrs.push(attr.clone());
+            },
+            _ => {},
+        }
+    }
+
+    for fmtp in accepted.fmtps {
+        answer_media.attrs.push(Attr::FmtP(fmtp));
+    }
+
+    answer_media.attrs.push(accepted.direction.to_attr());
+
+    let offers_ice = offer_media.attrs.iter().chain(sdp_offer.attrs.iter()).any(|attr| match *attr {
+        Attr::IceUfrag(_) => true,
+        _ => false,
+    });
+    match local.ice_credentials {
+        Some(ref credentials) if offers_ice => {
+            answer_media.attrs.push(Attr::IceUfrag(IceUfragValue {
+                value: credentials.ufrag.clone()
+            }));
+            answer_media.attrs.push(Attr::IcePwd(IcePwdValue {
+                value: credentials.pwd.clone()
+            }));
+        },
+        None if offers_ice => {
+            warn!("No local ICE credentials, connectivity checks are bound to fail");
+        },
+        _ => {},
+    }
+
+    answer_media
+}
+
+/// Checks a re-offer against the previous answer: m-lines can't be removed,
+/// only disabled, nor change type unless reusing a disabled one (RFC 3264,
+/// section 8).
+fn check_reoffer(sdp_previous: &SessionDescription, sdp_offer: &SessionDescription) -> Result<(), String> {
+    if sdp_offer.media.len() < sdp_previous.media.len() {
+        return Err(format!("re-offer has {} m-lines, {} were negotiated",
+                           sdp_offer.media.len(), sdp_previous.media.len()))
+    }
+
+    for (i, previous_media) in sdp_previous.media.iter().enumerate() {
+        if !previous_media.is_rejected() && previous_media.media.media != sdp_offer.media[i].media.media {
+            return Err(format!("m-line {} changed from {} to {}", i + 1,
+                               previous_media.media.media.to_string(),
+                               sdp_offer.media[i].media.media.to_string()))
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers an offer, as of RFC 3264. Each m-line of the offer is answered
+/// in order, accepting the formats also in the base SDP, if any. When
+/// `sdp_previous` is set, the offer is a re-offer within the session it
+/// answered.
+pub fn negotiate_with(sdp_base: Option<&SessionDescription>, sdp_offer: &SessionDescription, sdp_previous: Option<&SessionDescription>, local: &LocalParams) -> Result<SessionDescription, String> {
+    let mut sdp_answer = sdp_offer.clone();
+
+    // The answer is of our own session, only its version changing across
+    // re-offers
+    sdp_answer.origin = match sdp_previous {
+        Some(sdp_previous) => {
+            check_reoffer(sdp_previous, sdp_offer)?;
+
+            sdp_previous.origin.clone().map(|mut origin| {
+                origin.session_version += 1;
+                origin
+            })
+        },
+        None => Some(Origin::new(&local.username, &rand::random::<u32>().to_string(), 1, local.address.clone())),
+    };
+    sdp_answer.name = Some(local.session_name.clone());
+    // Which describe the offerer's
+    sdp_answer.info = None;
+    sdp_answer.uri = None;
+    sdp_answer.emails.clear();
+    sdp_answer.phones.clear();
+    sdp_answer.encrypt_key = None;
+    sdp_answer.time_zones.clear();
+    for time in sdp_answer.times.iter_mut() {
+        time.repeat_times.clear();
+    }
+    // Media are sent to our ICE candidates, the default destination being
+    // left unspecified, as is its port of 9 (RFC 8839, section 5.1)
+    sdp_answer.conn = Some(Connection::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))));
+
+    // Directions and ICE credentials are set at the media level
+    strip_unknown_attrs(&mut sdp_answer.attrs);
+    sdp_answer.attrs.retain(|attr| match *attr {
+        Attr::IceUfrag(_) | Attr::IcePwd(_) | Attr::Candidate(_) | Attr::IceLite => false,
+        _ => Direction::from_attr(attr).is_none(),
+    });
+    sdp_answer.bandwidths = negotiate_bandwidths(&sdp_offer.bandwidths,
+                                                 &[BandwidthType::CT, BandwidthType::AS],
+                                                 local.session_bitrate);
+
+    sdp_answer.media = (0..sdp_offer.media.len())
+        .map(|i| answer_media(sdp_offer, i, match_media(sdp_base, sdp_offer, i, local), local))
+        .collect();
+
+    let rejected = sdp_answer.media.iter().map(|m| m.is_rejected()).collect::<Vec<bool>>();
+
+    negotiate_dtls(sdp_offer, &mut sdp_answer, local);
+    negotiate_rtcp_mux(sdp_offer, &mut sdp_answer, local);
+    negotiate_bundle(&mut sdp_answer, &rejected, local);
+    negotiate_rtcp_fb(sdp_offer, &mut sdp_answer, local);
+    negotiate_extmap(sdp_offer, &mut sdp_answer);
+    negotiate_simulcast(sdp_offer, &mut sdp_answer);
+    strip_sources(&mut sdp_answer);
+
+    // Rejected media only keep their identification tag
+    for answer_media in sdp_answer.media.iter_mut().filter(|m| m.is_rejected()) {
+        answer_media.attrs.retain(|attr| match *attr {
+            Attr::Mid(_) => true,
+            _ => false,
+        });
+    }
+
+    // Lite agents say so (RFC 8445, section 5.3)
+    if local.ice_lite {
+        sdp_answer.attrs.push(Attr::IceLite);
+    }
+
+    Ok(sdp_answer)
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Range media ports are allocated from unless configured, starting at
+/// 6000 as they always did.
+pub const DEFAULT_MIN_PORT: u16 = 6000;
+pub const DEFAULT_MAX_PORT: u16 = 6999;
+
+/// Allocates the UDP ports of the media, from a fixed range operators can
+/// open in their firewalls. Ports are bound as they are allocated, so one
+/// taken by anyone else is skipped rather than handed out.
+#[derive(Debug)]
+pub struct PortAllocator {
+    min: u16,
+    max: u16,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    // Where the search for a free port resumes
+    next: u16,
+    allocated: HashSet<u16>,
+}
+
+impl PortAllocator {
+    pub fn new(min: u16, max: u16) -> Result<PortAllocator, String> {
+        if min == 0 || min > max {
+            return Err(format!("invalid port range {}-{}", min, max))
+        }
+
+        Ok(PortAllocator {
+            min: min,
+            max: max,
+            state: Mutex::new(State {
+                next: min,
+                allocated: HashSet::new(),
+            }),
+        })
+    }
+
+    pub fn range(&self) -> (u16, u16) {
+        (self.min, self.max)
+    }
+
+    /// Binds a socket on `ip` to the next free port of the range.
+    pub fn allocate(&self, ip: IpAddr) -> Result<UdpSocket, String> {
+        let mut state = self.state.lock().unwrap();
+        let nr_ports = self.max as u32 - self.min as u32 + 1;
+
+        for _ in 0..nr_ports {
+            let port = state.next;
+            state.next = if port == self.max { self.min } else { port + 1 };
+
+            if state.allocated.contains(&port) {
+                continue;
+            }
+
+            match UdpSocket::bind(SocketAddr::new(ip, port)) {
+                Ok(socket) => {
+                    debug!("Allocated port {}", port);
+                    state.allocated.insert(port);
+
+                    return Ok(socket)
+                },
+                Err(e) => {
+                    debug!("Skipping port {}: {}", port, e);
+                },
+            }
+        }
+
+        Err(format!("no free port in range {}-{}", self.min, self.max))
+    }
+
+    /// Hands `port` back, to be allocated again once its socket is closed.
+    pub fn release(&self, port: u16) {
+        if self.state.lock().unwrap().allocated.remove(&port) {
+            debug!("Released port {}", port);
+        }
+    }
+
+    pub fn nr_allocated(&self) -> usize {
+        self.state.lock().unwrap().allocated.len()
+    }
+}
+
+impl Default for PortAllocator {
+    fn default() -> PortAllocator {
+        PortAllocator::new(DEFAULT_MIN_PORT, DEFAULT_MAX_PORT).unwrap()
+    }
+}
+
+/// Range as "<min>-<max>", e.g. "10000-10999".
+impl FromStr for PortAllocator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PortAllocator, String> {
+        let mut bounds = s.trim().splitn(2, '-');
+        let min = bounds.next().and_then(|p| p.trim().parse::<u16>().ok());
+        let max = bounds.next().and_then(|p| p.trim().parse::<u16>().ok());
+
+        match (min, max) {
+            (Some(min), Some(max)) => PortAllocator::new(min, max),
+            _ => Err(format!("invalid port range '{}'", s)),
+        }
+    }
+}
@@ -1,15 +1,18 @@
 extern crate nickel;
 
 use std::collections::BTreeMap;
+use std::io::Read;
 
-use self::nickel::{Nickel, HttpRouter, Request, Response, MiddlewareResult, JsonBody};
+use self::nickel::{Nickel, HttpRouter, Request, Response, MiddlewareResult, JsonBody, QueryString, MediaType};
 use self::nickel::status::StatusCode;
 use rustc_serialize::json::{Json, ToJson};
+use serde_json;
 use super::Handlers;
 
 use convo::convo::{Conferences};
 use convo::member::{Member};
-use sdp::{SessionDescription};
+use convo::session_negotiation::SessionError;
+use sdp::{SessionDescription, ParseMode};
 
 #[derive(RustcDecodable, RustcEncodable)]
 pub struct ConferencePost {
@@ -21,15 +24,49 @@ pub struct ConferenceResponse {
     pub convo_id: String,
 }
 
-#[derive(RustcDecodable, RustcEncodable)]
+/// A member's SDP, either as plain text or in its JSON representation
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MemberSdp {
+    Text(String),
+    Json(SessionDescription),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SdpFormat {
+    Text,
+    Json,
+}
+
+impl MemberSdp {
+    pub fn new(sdp: &SessionDescription, format: SdpFormat) -> MemberSdp {
+        match format {
+            SdpFormat::Text => MemberSdp::Text(sdp.to_string()),
+            SdpFormat::Json => MemberSdp::Json(sdp.clone()),
+        }
+    }
+
+    pub fn format(&self) -> SdpFormat {
+        match *self {
+            MemberSdp::Text(_) => SdpFormat::Text,
+            MemberSdp::Json(_) => SdpFormat::Json,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct MemberPost {
-    pub sdp: String,
+    pub sdp: MemberSdp,
+    /// Format of the SDP answer, the same as the offer's if not set
+    #[serde(default)]
+    pub format: Option<SdpFormat>,
 }
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Serialize, Deserialize)]
 pub struct MemberResponse {
     pub member_id: String,
-    pub sdp: String,
+    pub sdp: MemberSdp,
 }
 
 impl ToJson for ConferenceResponse {
@@ -40,15 +77,6 @@ impl ToJson for ConferenceResponse {
     }
 }
 
-impl ToJson for MemberResponse {
-    fn to_json(&self) -> Json {
-        let mut map = BTreeMap::new();
-        map.insert("member_id".to_string(), self.member_id.to_json());
-        map.insert("sdp".to_string(), self.sdp.to_json());
-        Json::Object(map)
-    }
-}
-
 pub struct HttpServer {
     convos: Conferences,
 }
@@ -118,33 +146,66 @@ fn post_member<'mw>(req: &mut Request<HttpServer>, mut res: Response<'mw, HttpSe
     }
 
     // Parse JSON
-    let member_post = req.json_as::<MemberPost>().unwrap();
-    
+    let mut body = String::new();
+    if let Err(x) = req.origin.read_to_string(&mut body) {
+        res.set(StatusCode::BadRequest);
+        return res.send(format!("Invalid member body: {}", x))
+    }
+    let member_post = match serde_json::from_str::<MemberPost>(&body) {
+        Ok(member_post) => member_post,
+        Err(x) => {
+            res.set(StatusCode::BadRequest);
+            return res.send(format!("Invalid member body: {}", x))
+        },
+    };
+
+    let format = member_post.format.unwrap_or(member_post.sdp.format());
+
+    // The SDP comes from the outside, reject it as a whole if malformed
+    let parsed_sdp = match member_post.sdp {
+        MemberSdp::Json(parsed_sdp) => parsed_sdp,
+        MemberSdp::Text(sdp) => match SessionDescription::parse(&sdp, ParseMode::Strict) {
+            Ok(parsed_sdp) => parsed_sdp,
+            Err(x) => {
+                debug!("Rejecting member SDP: {}", x);
+                res.set(StatusCode::BadRequest);
+                return res.send(format!("Invalid SDP: {}", x))
+            },
+        },
+    };
+
     // Create member and insert in convo
-    let sdp = SessionDescription::new();
-    let parsed_sdp = sdp.from_sdp(&member_post.sdp);
-    let member = Member::new(parsed_sdp.desc);
+    let member = Member::new(parsed_sdp);
 
     let memberid  = member.id.clone();
 
     // Add member / SDP to the convo, negotiating the SDPs
-    let sdp_answer = convo.add_member(member);
-
-    // Return any response errors, like the negotiating
-    // between the SDPs failing, or because the parse
-    // failed. 
+    let sdp_answer = match convo.add_member(member) {
+        Ok(sdp_answer) => sdp_answer,
+        Err(x) => {
+            // The offer is at fault, or else we ran out of ports for now, or
+            // aren't configured to serve media at all
+            res.set(match x {
+                SessionError::Negotiation(_) => StatusCode::BadRequest,
+                SessionError::Ports(_) => StatusCode::ServiceUnavailable,
+                SessionError::Candidates(_) => StatusCode::InternalServerError,
+            });
+            return res.send(format!("Failed to add member: {}", x.to_string()))
+        },
+    };
 
-    debug!("SDP Answer {}", sdp_answer.clone().unwrap().to_string());
+    debug!("SDP Answer {}", sdp_answer.to_string());
 
     // Compose response
     let response = MemberResponse {
         member_id: memberid,
-        sdp: sdp_answer.unwrap().to_string(),
+        sdp: MemberSdp::new(&sdp_answer, format),
     };
 
     res.headers_mut().set_raw("Access-Control-Allow-Origin", vec![b"*".to_vec()]);
- 
-    res.send(response.to_json())
+    res.set(MediaType::Json);
+
+    res.send(serde_json::to_string(&response).unwrap())
 }
 
 fn get_conference_member<'mw>(req: &mut Request<HttpServer>, mut res: Response<'mw, HttpServer>) -> MiddlewareResult<'mw, HttpServer> {
@@ -168,19 +229,51 @@ fn get_conference_member<'mw>(req: &mut Request<HttpServer>, mut res: Response<'
         return res.send(format!("Member {} not found in conference {}", &memberid, &convoid))
     }
 
+    // SDP as text unless asked for "?format=json"
+    let format = match req.query().get("format") {
+        Some("json") => SdpFormat::Json,
+        _ => SdpFormat::Text,
+    };
+
     let member = member.unwrap();
     // Compose response
     let response = MemberResponse {
         member_id: member.id.to_string(),
-        sdp: member.sdp.to_string(),
+        sdp: MemberSdp::new(&member.sdp, format),
     };
- 
-    res.send(response.to_json())
+
+    res.set(MediaType::Json);
+    res.send(serde_json::to_string(&response).unwrap())
+}
+
+fn delete_conference_member<'mw>(req: &mut Request<HttpServer>, mut res: Response<'mw, HttpServer>) -> MiddlewareResult<'mw, HttpServer> {
+    let handler = req.server_data();
+    let convos = &handler.convos;
+
+    let convoid = req.param("convoid").unwrap();
+
+    // Get the convo of id :convoid
+    let convo = convos.get_convo(convoid);
+    if !convo.is_some() {
+        res.set(StatusCode::NotFound);
+        return res.render("Conference {} not found", &convoid)
+    }
+
+    // Remove member, releasing its ports
+    let memberid = req.param("memberid").unwrap();
+    if convo.unwrap().remove_member(memberid).is_none() {
+        res.set(StatusCode::NotFound);
+        return res.send(format!("Member {} not found in conference {}", &memberid, &convoid))
+    }
+
+    res.headers_mut().set_raw("Access-Control-Allow-Origin", vec![b"*".to_vec()]);
+    res.set(StatusCode::NoContent);
+    res.send("")
 }
 
 fn enable_cors<'mw>(_req: &mut Request<HttpServer>, mut res: Response<'mw, HttpServer>) -> MiddlewareResult<'mw, HttpServer> {
     res.headers_mut().set_raw("Access-Control-Allow-Headers", vec![b"content-type".to_vec()]);
-    res.headers_mut().set_raw("Access-Control-Allow-Methods", vec![b"POST, OPTIONS".to_vec()]);
+    res.headers_mut().set_raw("Access-Control-Allow-Methods", vec![b"POST, DELETE, OPTIONS".to_vec()]);
     res.headers_mut().set_raw("Access-Control-Allow-Origin", vec![b"*".to_vec()]);
     res.send("")
 }
@@ -198,6 +291,7 @@ impl Handlers for HttpServer {
         server.get("/convo/:convoid", get_conference);
         server.post("/convo/:convoid/member", post_member);
         server.get("/convo/:convoid/member/:memberid", get_conference_member);
+        server.delete("/convo/:convoid/member/:memberid", delete_conference_member);
 
         server.utilize(enable_cors);
 
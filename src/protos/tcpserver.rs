@@ -5,7 +5,7 @@ use std::str;
 use std::str::from_utf8;
 use std::sync::Arc;
 
-use sdp::{SessionDescription, ParseResult};
+use sdp::{SessionDescription, ParseMode};
 use convo::member::{Member};
 use convo::convo::{Conferences};
 use super::Handlers;
@@ -18,8 +18,20 @@ impl Tcp {
     // We will receive the SDP through TCP
     fn handle_client(&self, mut stream: TcpStream) {
         let mut buf = [0; 1500];
-        let _ = stream.read(&mut buf);
-        let request = from_utf8(&buf).unwrap();
+        let size = match stream.read(&mut buf) {
+            Ok(size) => size,
+            Err(x) => {
+                error!("Problem occurred reading request {}", x);
+                return;
+            },
+        };
+        let request = match from_utf8(&buf[..size]) {
+            Ok(request) => request,
+            Err(_) => {
+                debug!("Request is not valid UTF-8");
+                return;
+            },
+        };
 
         debug!("Request on the wire: {:?}", request);
 
@@ -35,14 +47,14 @@ impl Tcp {
             let trimmed_line = line.trim();
             
             if trimmed_line.starts_with("conference_id") {
-                convo_id = &trimmed_line[16..];
+                convo_id = trimmed_line.get(16..).unwrap_or("");
                 convo_id = convo_id.trim();
                 debug!("Got conference_id {}", convo_id);
             } else if trimmed_line.starts_with("sdp") {
                 debug!("Got size {}", parsed_len);
-                raw_sdp = &request[parsed_len..];
+                raw_sdp = request.get(parsed_len..).unwrap_or("");
                 raw_sdp = raw_sdp.trim();
-                raw_sdp = &raw_sdp[4..];
+                raw_sdp = raw_sdp.get(4..).unwrap_or("");
                 break;
             }
 
@@ -51,19 +63,15 @@ impl Tcp {
         
         // 2. Parse the SDP into a proper structure
 
-        let sdp:SessionDescription = SessionDescription::new();
-        let parse_result:ParseResult = sdp.from_sdp(&raw_sdp);
-        println!("Parsed SDP: {:?}", parse_result);
-
-        // Check validity of the parsing
-        if parse_result.unparsed_lines.len() != 0 {
-            debug!("Unparsed lines is not empty, something funky");
-            return;
-        }
-        if parse_result.ignored_lines.len() != 0 {
-            debug!("Ignored lines is not empty, something funky");
-            return;
-        }
+        let parsed_sdp = match SessionDescription::parse(&raw_sdp, ParseMode::Strict) {
+            Ok(parsed_sdp) => parsed_sdp,
+            Err(x) => {
+                debug!("Invalid SDP, {}", x);
+                let _ = stream.write(format!("error: {}\n", x).as_bytes());
+                return;
+            },
+        };
+        println!("Parsed SDP: {:?}", parsed_sdp);
 
         // 3. Pass the SDP into a new conference OR
         //    negotiate with the current SDP that's bound
@@ -73,22 +81,24 @@ impl Tcp {
         let convo = self.convos.new_convo(convo_id);
         
         // Abstract the SDP around a member
-        let member = Member::new(parse_result.desc); 
+        let member = Member::new(parsed_sdp);
 
         {
-            let sdp_answer;
             debug!("convo set up...");
             //let mut convo_lock = convo.write().unwrap();
             debug!("convo set up2...");
             // Add member / SDP to the convo, negotiating the SDPs
-            sdp_answer = convo.add_member(member);
-
-            // Return any response errors, like the negotiating
-            // between the SDPs failing, or because the parse
-            // failed. 
+            let sdp_answer = match convo.add_member(member) {
+                Ok(sdp_answer) => sdp_answer,
+                Err(x) => {
+                    debug!("Failed to add member, {}", x.to_string());
+                    let _ = stream.write(format!("error: {}\n", x.to_string()).as_bytes());
+                    return;
+                },
+            };
 
-            debug!("SDP Answer {}", sdp_answer.clone().unwrap().to_string());
-            match stream.write(sdp_answer.clone().unwrap().to_string().as_bytes()) {
+            debug!("SDP Answer {}", sdp_answer.to_string());
+            match stream.write(sdp_answer.to_string().as_bytes()) {
                 Err(x) => {
                     error!("Problem occurred writing answer {}", x);
                 },
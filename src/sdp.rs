@@ -1,62 +1,400 @@
+use std::fmt;
+use std::cmp;
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::string::ToString;
 use std::net::IpAddr;
 
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
+use serde_json;
+
 use ice;
 
-#[derive(Clone, Debug)]
+/// Serializes a type as the string it has in an SDP, for the types whose
+/// JSON is best left as such (e.g. "UDP/TLS/RTP/SAVPF").
+macro_rules! serde_as_string {
+    ($t:ty) => {
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse::<$t>().map_err(|_| D::Error::custom(format!("invalid {} '{}'", stringify!($t), s)))
+            }
+        }
+    }
+}
+
+serde_as_string!(Address);
+serde_as_string!(TypedTime);
+serde_as_string!(BandwidthType);
+serde_as_string!(FingerprintValue);
+serde_as_string!(GroupSemantics);
+serde_as_string!(SsrcGroupSemantics);
+serde_as_string!(RtcpFbKind);
+serde_as_string!(MediaProto);
+
+/// Address of an "o=", "c=" or candidate line: an IP address, or a fully
+/// qualified domain name in its place (RFC 8866, section 5.7).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    Ip(IpAddr),
+    Fqdn(String),
+}
+
+impl Address {
+    pub fn ip(&self) -> Option<IpAddr> {
+        match *self {
+            Address::Ip(ip) => Some(ip),
+            Address::Fqdn(_) => None,
+        }
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        self.ip().map_or(false, |ip| ip.is_multicast())
+    }
+
+    /// Type of the address, IPv4 for names as they don't tell.
+    pub fn addr_type(&self) -> AddrType {
+        match *self {
+            Address::Ip(ref ip) => AddrType::from_ip(ip),
+            Address::Fqdn(_) => AddrType::IP4,
+        }
+    }
+}
+
+impl From<IpAddr> for Address {
+    fn from(ip: IpAddr) -> Address {
+        Address::Ip(ip)
+    }
+}
+
+impl ToString for Address {
+
+    fn to_string(&self) -> String {
+        match *self {
+            Address::Ip(ref ip) => ip.to_string(),
+            Address::Fqdn(ref name) => name.to_string(),
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = ();
+
+    /// Anything not an IP address has to be a host name (RFC 1123, section
+    /// 2.1), whose top-level label isn't numeric.
+    fn from_str(s: &str) -> Result<Address, ()> {
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Address::Ip(ip))
+        }
+
+        let labels = s.trim_end_matches('.').split('.').collect::<Vec<&str>>();
+        let valid = s.len() <= 253 && labels.iter().all(|label| {
+            !label.is_empty() && label.len() <= 63 &&
+                !label.starts_with('-') && !label.ends_with('-') &&
+                label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+        if !valid || labels.last().map_or(true, |tld| tld.chars().all(|c| c.is_ascii_digit())) {
+            return Err(())
+        }
+
+        Ok(Address::Fqdn(s.to_string()))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Origin {
     pub username: String,
     pub session_id: String,
     pub session_version: u64,
     pub net_type: NetType,
     pub addr_type: AddrType,
-    pub ip_address: IpAddr,
+    pub ip_address: Address,
+}
+
+impl Origin {
+    pub fn new<A: Into<Address>>(username: &str, session_id: &str, session_version: u64, address: A) -> Origin {
+        let address = address.into();
+        Origin {
+            username: username.to_string(),
+            session_id: session_id.to_string(),
+            session_version: session_version,
+            net_type: NetType::IN,
+            addr_type: address.addr_type(),
+            ip_address: address,
+        }
+    }
 }
 
 impl ToString for Origin {
 
     fn to_string(&self) -> String {
-        format!("o={} {} {} {} {} {}\n",
+        format!("o={} {} {} {} {} {}\r\n",
                 self.username,
                 self.session_id,
                 self.session_version,
                 self.net_type.to_string(),
                 self.addr_type.to_string(),
-                self.ip_address)
+                self.ip_address.to_string())
     }
 }
 
-#[derive(Clone, Debug)]
+/// Connection data. The TTL only applies to IPv4 multicast addresses, and
+/// the number of addresses to multicast ones (RFC 8866, section 5.7).
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Connection {
     pub net_type: NetType,
     pub addr_type: AddrType,
-    pub ip_address: IpAddr,
-    pub ttl: u8,
-    pub nr_addrs: u8,
+    pub ip_address: Address,
+    pub ttl: Option<u8>,
+    pub nr_addrs: Option<u8>,
+}
+
+impl Connection {
+    pub fn new<A: Into<Address>>(address: A) -> Connection {
+        let address = address.into();
+        Connection {
+            net_type: NetType::IN,
+            addr_type: address.addr_type(),
+            ip_address: address,
+            ttl: None,
+            nr_addrs: None,
+        }
+    }
 }
 
 impl ToString for Connection {
 
     fn to_string(&self) -> String {
-        format!("c={} {} {} {} {}\n",
+        let mut conn_addr = self.ip_address.to_string();
+        if let Address::Ip(IpAddr::V4(ip)) = self.ip_address {
+            if let (true, Some(ttl)) = (ip.is_multicast(), self.ttl) {
+                conn_addr = format!("{}/{}", conn_addr, ttl);
+            }
+        }
+        if let (true, Some(nr_addrs)) = (self.ip_address.is_multicast(), self.nr_addrs) {
+            conn_addr = format!("{}/{}", conn_addr, nr_addrs);
+        }
+
+        format!("c={} {} {}\r\n",
             self.net_type.to_string(),
             self.addr_type.to_string(),
-            self.ip_address,
-            self.ttl,
-            self.nr_addrs)
+            conn_addr)
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Timing {
     pub start_time: u64,
     pub stop_time: u64,
 }
 
-#[derive(Clone, Debug)]
+impl ToString for Timing {
+
+    fn to_string(&self) -> String {
+        format!("t={} {}\r\n", self.start_time, self.stop_time)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeUnit {
+    Days,
+    Hours,
+    Minutes,
+    Seconds,
+}
+
+/// Time in seconds, or in the unit given by the suffix (e.g. "7d", "-1h").
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedTime {
+    pub value: i64,
+    pub unit: Option<TimeUnit>,
+}
+
+impl TypedTime {
+    pub fn as_seconds(&self) -> i64 {
+        match self.unit {
+            Some(TimeUnit::Days) => self.value * 86400,
+            Some(TimeUnit::Hours) => self.value * 3600,
+            Some(TimeUnit::Minutes) => self.value * 60,
+            Some(TimeUnit::Seconds) | None => self.value,
+        }
+    }
+}
+
+impl ToString for TypedTime {
+
+    fn to_string(&self) -> String {
+        let unit = match self.unit {
+            Some(TimeUnit::Days) => "d",
+            Some(TimeUnit::Hours) => "h",
+            Some(TimeUnit::Minutes) => "m",
+            Some(TimeUnit::Seconds) => "s",
+            None => "",
+        };
+
+        format!("{}{}", self.value, unit)
+    }
+}
+
+impl FromStr for TypedTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TypedTime, String> {
+        let (value, unit) = match s.chars().last() {
+            Some('d') => (&s[..s.len()-1], Some(TimeUnit::Days)),
+            Some('h') => (&s[..s.len()-1], Some(TimeUnit::Hours)),
+            Some('m') => (&s[..s.len()-1], Some(TimeUnit::Minutes)),
+            Some('s') => (&s[..s.len()-1], Some(TimeUnit::Seconds)),
+            _ => (s, None),
+        };
+
+        Ok(TypedTime {
+            value: parse_field::<i64>(value, "typed time")?,
+            unit: unit,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RepeatTime {
+    pub interval: TypedTime,
+    pub duration: TypedTime,
+    pub offsets: Vec<TypedTime>,
+}
+
+impl ToString for RepeatTime {
+
+    fn to_string(&self) -> String {
+        let mut repeat = format!("r={} {}", self.interval.to_string(), self.duration.to_string());
+
+        for offset in self.offsets.iter() {
+            repeat = format!("{} {}", repeat, offset.to_string());
+        }
+
+        format!("{}\r\n", repeat)
+    }
+}
+
+/// A "t=" line and the "r=" lines that follow it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimeDescription {
+    pub timing: Timing,
+    pub repeat_times: Vec<RepeatTime>,
+}
+
+impl TimeDescription {
+    pub fn new(timing: Timing) -> TimeDescription {
+        TimeDescription {
+            timing: timing,
+            repeat_times: vec![],
+        }
+    }
+}
+
+impl ToString for TimeDescription {
+
+    fn to_string(&self) -> String {
+        let mut time_description = self.timing.to_string();
+
+        for repeat_time in self.repeat_times.iter() {
+            time_description = time_description + &repeat_time.to_string();
+        }
+
+        time_description
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimeZone {
+    pub adjustment_time: u64,
+    pub offset: TypedTime,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BandwidthType {
+    CT,
+    AS,
+    TIAS,
+    /// Experimental or unknown type, kept as it was received.
+    Other(String),
+}
+
+impl ToString for BandwidthType {
+
+    fn to_string(&self) -> String {
+        match *self {
+            BandwidthType::CT => "CT".to_string(),
+            BandwidthType::AS => "AS".to_string(),
+            BandwidthType::TIAS => "TIAS".to_string(),
+            BandwidthType::Other(ref x) => x.to_string(),
+        }
+    }
+}
+
+impl FromStr for BandwidthType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<BandwidthType, ()> {
+        match s {
+            "CT" => Ok(BandwidthType::CT),
+            "AS" => Ok(BandwidthType::AS),
+            "TIAS" => Ok(BandwidthType::TIAS),
+            "" => Err(()),
+            _ => Ok(BandwidthType::Other(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bandwidth {
+    pub bwtype: BandwidthType,
+    pub bandwidth: u64,
+}
+
+impl ToString for Bandwidth {
+
+    fn to_string(&self) -> String {
+        format!("b={}:{}\r\n", self.bwtype.to_string(), self.bandwidth)
+    }
+}
+
+/// Bits per second `bandwidths` limit to, the lowest of "b=TIAS" and of
+/// "b=AS" and "b=CT" in kilobits. Offered values past what fits are taken
+/// as unlimited.
+pub fn bitrate_of(bandwidths: &[Bandwidth]) -> Option<u64> {
+    bandwidths.iter().filter_map(|b| match b.bwtype {
+        BandwidthType::TIAS => Some(b.bandwidth),
+        BandwidthType::AS | BandwidthType::CT => Some(b.bandwidth.saturating_mul(1000)),
+        BandwidthType::Other(_) => None,
+    }).min()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncryptionKey {
+    pub method: String,
+    pub key: Option<String>,
+}
+
+impl ToString for EncryptionKey {
+
+    fn to_string(&self) -> String {
+        match self.key {
+            Some(ref key) => format!("k={}:{}\r\n", self.method, key),
+            None => format!("k={}\r\n", self.method),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PTimeValue {
-    value: u32,
+    pub value: u32,
 }
 
 impl PartialEq for PTimeValue {
@@ -65,38 +403,36 @@ impl PartialEq for PTimeValue {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RtpMapValue {
-    payload_type: u32,
-    encoding_name: String,
-    clock_rate: u32,
-    encoding_params: Option<u32>,
+    pub payload_type: u32,
+    pub encoding_name: String,
+    pub clock_rate: u32,
+    pub encoding_params: Option<u32>,
 }
 
 impl FromStr for RtpMapValue {
-    type Err = ();
+    type Err = String;
 
     fn from_str(rtpmap_value: &str) -> Result<Self, Self::Err> {
         let values = rtpmap_value.split(' ').collect::<Vec<&str>>();
         if values.len() != 2 {
-            debug!("Invalid value for rtpmap");
-            return Err(())
+            return Err("expected '<payload type> <encoding>/<rate>'".to_string())
         }
 
-        let payload_type = values[0].parse::<u32>().unwrap();
+        let payload_type = parse_field::<u32>(values[0], "payload type")?;
         let params = values[1].split('/').collect::<Vec<&str>>();
 
-        if params.len() < 2 {
-            debug!("Invalid encoding/rate for rtpmap");
-            return Err(())
+        if params.len() < 2 || params.len() > 3 {
+            return Err("expected '<encoding>/<rate>[/<params>]'".to_string())
         }
 
-        let encoding_name = FromStr::from_str(params[0]).unwrap();
-        let clock_rate = params[1].parse::<u32>().unwrap();
+        let encoding_name = params[0].to_string();
+        let clock_rate = parse_field::<u32>(params[1], "clock rate")?;
 
         let mut encoding_params = None;
         if params.len() == 3 {
-            encoding_params = params[2].parse::<u32>().ok();
+            encoding_params = Some(parse_field::<u32>(params[2], "encoding parameters")?);
         }
 
         Ok(RtpMapValue {
@@ -130,28 +466,66 @@ impl PartialEq for RtpMapValue {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Format parameters, as "<key>=<value>" pairs separated by ";". Parameters
+/// that don't follow that form, e.g. "0-15" of telephone-event, are kept as
+/// keys without a value.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FmtPValue {
-    format: u32,
-    format_params: String,
+    pub format: u32,
+    pub params: Vec<(String, Option<String>)>,
+}
+
+impl FmtPValue {
+    pub fn new(format: u32) -> FmtPValue {
+        FmtPValue {
+            format: format,
+            params: vec![],
+        }
+    }
+
+    pub fn parse_params(params: &str) -> Vec<(String, Option<String>)> {
+        params.split(';')
+            .map(|param| param.trim())
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let param = param.splitn(2, '=').collect::<Vec<&str>>();
+                (param[0].trim().to_string(), param.get(1).map(|v| v.trim().to_string()))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params.iter()
+            .filter(|&&(ref k, _)| k == key)
+            .filter_map(|&(_, ref v)| v.as_ref().map(|v| v.as_str()))
+            .next()
+    }
+
+    /// Sets the parameter `key`, in place if already there.
+    pub fn set(&mut self, key: &str, value: &str) {
+        for param in self.params.iter_mut() {
+            if param.0 == key {
+                param.1 = Some(value.to_string());
+                return;
+            }
+        }
+
+        self.params.push((key.to_string(), Some(value.to_string())));
+    }
 }
 
 impl FromStr for FmtPValue {
-    type Err = ();
+    type Err = String;
 
     fn from_str(fmtp_value: &str) -> Result<Self, Self::Err> {
-        let values = fmtp_value.split(' ').collect::<Vec<&str>>();
+        let values = fmtp_value.splitn(2, ' ').collect::<Vec<&str>>();
         if values.len() != 2 {
-            debug!("Invalid value for fmtp");
-            return Err(())
+            return Err("expected '<format> <format specific parameters>'".to_string())
         }
 
-        let format = values[0].parse::<u32>().unwrap();
-        let fmt_params = values[1].to_string();
-
         Ok(FmtPValue {
-            format: format,
-            format_params: fmt_params,
+            format: parse_field::<u32>(values[0], "format")?,
+            params: FmtPValue::parse_params(values[1]),
         })
     }
 }
@@ -159,7 +533,12 @@ impl FromStr for FmtPValue {
 impl ToString for FmtPValue {
 
     fn to_string(&self) -> String {
-        format!("{} {}", self.format, self.format_params)
+        let params = self.params.iter().map(|&(ref key, ref value)| match *value {
+            Some(ref value) => format!("{}={}", key, value),
+            None => key.to_string(),
+        }).collect::<Vec<String>>();
+
+        format!("{} {}", self.format, params.join(";"))
     }
 }
 
@@ -167,53 +546,51 @@ impl PartialEq for FmtPValue {
 
     fn eq(&self, other: &FmtPValue) -> bool {
         self.format == other.format &&
-            self.format_params == self.format_params
+            self.params == other.params
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CandidateValue {
     pub ice_candidate: ice::Candidate,
 }
 
 impl FromStr for CandidateValue {
-    type Err = ();
+    type Err = String;
 
     fn from_str(candidate_value: &str) -> Result<Self, Self::Err> {
         let values = candidate_value.split(' ').collect::<Vec<&str>>();
         if values.len() < 8 {
-            debug!("Invalid value for candidate");
-            return Err(())
+            return Err("expected at least 8 fields".to_string())
         }
 
         let foundation = values[0].to_string();
-        let component_id = values[1].parse::<u16>().unwrap();
-        let proto = values[2].parse::<ice::Proto>().unwrap();
-        let priority = values[3].parse::<u32>().unwrap();
-        let conn = FromStr::from_str(values[4]).unwrap();
-        let port = values[5].parse::<u16>().unwrap();
+        let component_id = parse_field::<u16>(values[1], "component id")?;
+        let proto = values[2].parse::<ice::Proto>()
+            .map_err(|_| format!("unknown transport '{}'", values[2]))?;
+        let priority = parse_field::<u32>(values[3], "priority")?;
+        let conn = parse_field::<Address>(values[4], "connection address")?;
+        let port = parse_field::<u16>(values[5], "port")?;
         let typ = values[6].to_string();
         if typ != "typ" {
-            debug!("Invalid value for candidate, no 'typ' found");
-            return Err(())
+            return Err("no 'typ' found".to_string())
         }
-        let candidate_type = values[7].parse::<ice::CandidateType>().unwrap();
+        let candidate_type = values[7].parse::<ice::CandidateType>()
+            .map_err(|_| format!("unknown candidate type '{}'", values[7]))?;
 
+        // What follows the type are name and value pairs
         let mut rel_addr = None;
         let mut rel_port = None;
-        if values.len() >= 12 {
-            let raddr = values[8].to_string();
-            if raddr != "raddr" {
-                debug!("No 'raddr' found");
-            } else {
-                rel_addr = Some(FromStr::from_str(values[9]).unwrap());
+        let mut extensions = vec![];
+        for pair in values[8..].chunks(2) {
+            if pair.len() != 2 {
+                return Err(format!("no value for '{}'", pair[0]))
             }
 
-            let rport = values[10].to_string();
-            if rport != "rport" {
-                debug!("No 'rport' found");
-            } else {
-                rel_port = Some(values[11].parse::<u16>().unwrap());
+            match pair[0] {
+                "raddr" => rel_addr = Some(parse_field::<Address>(pair[1], "related address")?),
+                "rport" => rel_port = Some(parse_field::<u16>(pair[1], "related port")?),
+                name => extensions.push((name.to_string(), pair[1].to_string())),
             }
         }
 
@@ -228,6 +605,7 @@ impl FromStr for CandidateValue {
                 candidate_type: candidate_type,
                 rel_addr: rel_addr,
                 rel_port: rel_port,
+                extensions: extensions,
             }
         })
     }
@@ -237,12 +615,19 @@ impl ToString for CandidateValue {
 
     fn to_string(&self) -> String {
 
-        let value = format!("{} {} {} {} {} {} typ {}", self.ice_candidate.foundation, self.ice_candidate.component_id.unwrap(), self.ice_candidate.proto.to_string(), self.ice_candidate.priority, self.ice_candidate.conn.to_string(), self.ice_candidate.port, self.ice_candidate.candidate_type.to_string());
+        let mut value = format!("{} {} {} {} {} {} typ {}", self.ice_candidate.foundation, self.ice_candidate.component_id.unwrap(), self.ice_candidate.proto.to_string(), self.ice_candidate.priority, self.ice_candidate.conn.to_string(), self.ice_candidate.port, self.ice_candidate.candidate_type.to_string());
 
-        match self.ice_candidate.rel_addr {
-            Some(rel_addr) => { format!("{} raddr {:?} rport {:?}", value, rel_addr, self.ice_candidate.rel_port) },
-            None => { value },
+        if let Some(ref rel_addr) = self.ice_candidate.rel_addr {
+            value = format!("{} raddr {}", value, rel_addr.to_string());
+        }
+        if let Some(rel_port) = self.ice_candidate.rel_port {
+            value = format!("{} rport {}", value, rel_port);
+        }
+        for &(ref name, ref ext_value) in self.ice_candidate.extensions.iter() {
+            value = format!("{} {} {}", value, name, ext_value);
         }
+
+        value
     }
 }
 
@@ -254,9 +639,9 @@ impl PartialEq for CandidateValue {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IceUfragValue {
-    value: String,
+    pub value: String,
 }
 
 impl PartialEq for IceUfragValue {
@@ -265,9 +650,9 @@ impl PartialEq for IceUfragValue {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IcePwdValue {
-    value: String,
+    pub value: String,
 }
 
 impl PartialEq for IcePwdValue {
@@ -276,126 +661,1080 @@ impl PartialEq for IcePwdValue {
     }
 }
 
+/// Hash function of a certificate fingerprint, as of RFC 8122.
 #[derive(Clone, Debug, PartialEq)]
-pub enum Attr {
-    SendRecv,
-    SendOnly,
-    RecvOnly,
-    Inactive,
-    PTime(PTimeValue),
-    RtpMap(RtpMapValue),
-    FmtP(FmtPValue),
-    Candidate(CandidateValue),
-    IceUfrag(IceUfragValue),
-    IcePwd(IcePwdValue),
-    IceMismatch,
-    IceLite,
+pub enum HashFunction {
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Md5,
+    Md2,
+    Other(String),
 }
 
-impl ToString for Attr {
+impl ToString for HashFunction {
 
     fn to_string(&self) -> String {
-
-        let name;
-        let mut value: Option<String> = None;
         match *self {
-            Attr::RecvOnly => {
-                name = "recvonly".to_string();
-            },
-            Attr::SendOnly => {
-                name = "sendonly".to_string();
-            },
-            Attr::SendRecv => {
-                name = "sendrecv".to_string();
-            },
-            Attr::Inactive => {
-                name = "inactive".to_string();
-            },
-            Attr::PTime(PTimeValue{value: x}) => {
-                name = "ptime".to_string();
-                value = Some(x.to_string());
-            },
-            Attr::RtpMap(ref x) => {
-                name = "rtpmap".to_string();
-                value = Some(x.to_string());
-            },
-            Attr::FmtP(ref x) => {
-                name = "fmtp".to_string();
-                value = Some(x.to_string());
-            },
-            Attr::Candidate(ref x) => {
-                name = "candidate".to_string();
-                value = Some(x.to_string());
-            },
-            Attr::IceUfrag(IceUfragValue{value: ref x}) => {
-                name = "ice-ufrag".to_string();
-                value = Some(x.to_string());
-            },
-            Attr::IcePwd(IcePwdValue{value: ref x}) => {
-                name = "ice-pwd".to_string();
-                value = Some(x.to_string());
-            },
-            Attr::IceMismatch => {
-                name = "ice-mismatch".to_string();
-            },
-            Attr::IceLite => {
-                name = "ice-lite".to_string();
-            },
+            HashFunction::Sha1 => "sha-1".to_string(),
+            HashFunction::Sha224 => "sha-224".to_string(),
+            HashFunction::Sha256 => "sha-256".to_string(),
+            HashFunction::Sha384 => "sha-384".to_string(),
+            HashFunction::Sha512 => "sha-512".to_string(),
+            HashFunction::Md5 => "md5".to_string(),
+            HashFunction::Md2 => "md2".to_string(),
+            HashFunction::Other(ref x) => x.to_string(),
         }
+    }
+}
 
-        match value {
-            Some(x) => format!("a={}:{}\n", name, x),
-            None => format!("a={}\n", name)
+impl FromStr for HashFunction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<HashFunction, ()> {
+        // Hash function names are case-insensitive
+        match s.to_lowercase().as_str() {
+            "sha-1" => Ok(HashFunction::Sha1),
+            "sha-224" => Ok(HashFunction::Sha224),
+            "sha-256" => Ok(HashFunction::Sha256),
+            "sha-384" => Ok(HashFunction::Sha384),
+            "sha-512" => Ok(HashFunction::Sha512),
+            "md5" => Ok(HashFunction::Md5),
+            "md2" => Ok(HashFunction::Md2),
+            "" => Err(()),
+            _ => Ok(HashFunction::Other(s.to_string())),
         }
     }
 }
 
-trait AttrFromStr {
-    fn from_str(attr_type: &str, attr_value: Option<&str>) -> Result<Attr, ()>;
+#[derive(Clone, Debug, PartialEq)]
+pub struct FingerprintValue {
+    pub hash_fn: HashFunction,
+    pub digest: Vec<u8>,
 }
 
-impl AttrFromStr for Attr {
+impl FromStr for FingerprintValue {
+    type Err = String;
 
-    fn from_str(attr_type: &str, attr_value: Option<&str>) -> Result<Attr, ()> {
-        match attr_type {
-            "recvonly"  => Ok(Attr::RecvOnly),
-            "sendonly"  => Ok(Attr::SendOnly),
-            "sendrecv"  => Ok(Attr::SendRecv),
-            "inactive"  => Ok(Attr::Inactive),
-            "ptime"     => {
-                Ok(Attr::PTime(PTimeValue{
-                    value: attr_value.unwrap().parse::<u32>().unwrap()
-                }))
-            },
-            "rtpmap"    => {
-                Ok(Attr::RtpMap(
-                    attr_value.unwrap().parse::<RtpMapValue>().unwrap()
-                ))
-            },
-            "fmtp"      => {
-                Ok(Attr::FmtP(
-                    attr_value.unwrap().parse::<FmtPValue>().unwrap()
-                ))
-            },
-            "candidate" => {
-                Ok(Attr::Candidate(
-                    attr_value.unwrap().parse::<CandidateValue>().unwrap()
-                ))
-            },
-            "ice-ufrag" => {
-                Ok(Attr::IceUfrag(IceUfragValue{
-                    value: attr_value.unwrap().to_string(),
-                }))
-            },
-            "ice-pwd" => {
-                Ok(Attr::IcePwd(IcePwdValue{
-                    value: attr_value.unwrap().to_string(),
-                }))
+    fn from_str(fingerprint_value: &str) -> Result<Self, Self::Err> {
+        let values = fingerprint_value.split(' ').collect::<Vec<&str>>();
+        if values.len() != 2 {
+            return Err("expected '<hash function> <fingerprint>'".to_string())
+        }
+
+        let hash_fn = parse_field::<HashFunction>(values[0], "hash function")?;
+
+        let mut digest = vec![];
+        for byte in values[1].split(':') {
+            if byte.len() != 2 {
+                return Err(format!("invalid fingerprint byte '{}'", byte))
+            }
+            digest.push(u8::from_str_radix(byte, 16)
+                .map_err(|_| format!("invalid fingerprint byte '{}'", byte))?);
+        }
+
+        Ok(FingerprintValue {
+            hash_fn: hash_fn,
+            digest: digest,
+        })
+    }
+}
+
+impl ToString for FingerprintValue {
+
+    fn to_string(&self) -> String {
+        let digest = self.digest.iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<String>>();
+
+        format!("{} {}", self.hash_fn.to_string(), digest.join(":"))
+    }
+}
+
+/// Port, and optionally address, the offerer expects RTCP on (RFC 3605).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RtcpValue {
+    pub port: u16,
+    pub conn: Option<Connection>,
+}
+
+impl PartialEq for RtcpValue {
+    fn eq(&self, other: &RtcpValue) -> bool {
+        self.port == other.port &&
+            self.conn.as_ref().map(|c| &c.ip_address) == other.conn.as_ref().map(|c| &c.ip_address)
+    }
+}
+
+impl FromStr for RtcpValue {
+    type Err = String;
+
+    fn from_str(rtcp_value: &str) -> Result<Self, Self::Err> {
+        let values = rtcp_value.splitn(2, ' ').collect::<Vec<&str>>();
+
+        let mut conn = None;
+        if values.len() == 2 {
+            conn = Some(parse_connection(values[1])?);
+        }
+
+        Ok(RtcpValue {
+            port: parse_field::<u16>(values[0], "port")?,
+            conn: conn,
+        })
+    }
+}
+
+impl ToString for RtcpValue {
+
+    fn to_string(&self) -> String {
+        match self.conn {
+            Some(ref conn) => {
+                format!("{} {} {} {}", self.port, conn.net_type.to_string(),
+                        conn.addr_type.to_string(), conn.ip_address.to_string())
+            },
+            None => self.port.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GroupSemantics {
+    /// Lip synchronization (RFC 5888)
+    LS,
+    /// Flow identification (RFC 5888)
+    FID,
+    /// Media multiplexed over a single transport (RFC 8843)
+    Bundle,
+    Other(String),
+}
+
+impl ToString for GroupSemantics {
+
+    fn to_string(&self) -> String {
+        match *self {
+            GroupSemantics::LS => "LS".to_string(),
+            GroupSemantics::FID => "FID".to_string(),
+            GroupSemantics::Bundle => "BUNDLE".to_string(),
+            GroupSemantics::Other(ref x) => x.to_string(),
+        }
+    }
+}
+
+impl FromStr for GroupSemantics {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<GroupSemantics, ()> {
+        match s {
+            "LS" => Ok(GroupSemantics::LS),
+            "FID" => Ok(GroupSemantics::FID),
+            "BUNDLE" => Ok(GroupSemantics::Bundle),
+            "" => Err(()),
+            _ => Ok(GroupSemantics::Other(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GroupValue {
+    pub semantics: GroupSemantics,
+    /// Identification tags ("a=mid") of the media in the group.
+    pub mids: Vec<String>,
+}
+
+impl FromStr for GroupValue {
+    type Err = String;
+
+    fn from_str(group_value: &str) -> Result<Self, Self::Err> {
+        let values = group_value.split(' ').collect::<Vec<&str>>();
+
+        Ok(GroupValue {
+            semantics: parse_field::<GroupSemantics>(values[0], "semantics")?,
+            mids: values[1..].iter().map(|s| s.to_string()).collect(),
+        })
+    }
+}
+
+impl ToString for GroupValue {
+
+    fn to_string(&self) -> String {
+        let mut value = self.semantics.to_string();
+
+        for mid in self.mids.iter() {
+            value = format!("{} {}", value, mid);
+        }
+
+        value
+    }
+}
+
+/// Attribute of a media source, as of RFC 5576 "a=ssrc".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SsrcValue {
+    pub ssrc: u32,
+    /// Name of the source attribute, e.g. "cname" or "msid".
+    pub attribute: String,
+    pub value: Option<String>,
+}
+
+impl FromStr for SsrcValue {
+    type Err = String;
+
+    fn from_str(ssrc_value: &str) -> Result<Self, Self::Err> {
+        let values = ssrc_value.splitn(2, ' ').collect::<Vec<&str>>();
+        if values.len() != 2 {
+            return Err("missing source attribute".to_string());
+        }
+
+        let attribute = values[1].splitn(2, ':').collect::<Vec<&str>>();
+
+        Ok(SsrcValue {
+            ssrc: parse_field::<u32>(values[0], "ssrc")?,
+            attribute: attribute[0].to_string(),
+            value: attribute.get(1).map(|s| s.to_string()),
+        })
+    }
+}
+
+impl ToString for SsrcValue {
+
+    fn to_string(&self) -> String {
+        match self.value {
+            Some(ref value) => format!("{} {}:{}", self.ssrc, self.attribute, value),
+            None => format!("{} {}", self.ssrc, self.attribute),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SsrcGroupSemantics {
+    /// Retransmission flow (RFC 4588)
+    FID,
+    /// Simulcast layers
+    SIM,
+    Other(String),
+}
+
+impl ToString for SsrcGroupSemantics {
+
+    fn to_string(&self) -> String {
+        match *self {
+            SsrcGroupSemantics::FID => "FID".to_string(),
+            SsrcGroupSemantics::SIM => "SIM".to_string(),
+            SsrcGroupSemantics::Other(ref x) => x.to_string(),
+        }
+    }
+}
+
+impl FromStr for SsrcGroupSemantics {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<SsrcGroupSemantics, ()> {
+        match s {
+            "FID" => Ok(SsrcGroupSemantics::FID),
+            "SIM" => Ok(SsrcGroupSemantics::SIM),
+            "" => Err(()),
+            _ => Ok(SsrcGroupSemantics::Other(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SsrcGroupValue {
+    pub semantics: SsrcGroupSemantics,
+    pub ssrcs: Vec<u32>,
+}
+
+impl FromStr for SsrcGroupValue {
+    type Err = String;
+
+    fn from_str(group_value: &str) -> Result<Self, Self::Err> {
+        let values = group_value.split(' ').collect::<Vec<&str>>();
+
+        let mut ssrcs = vec![];
+        for ssrc in values[1..].iter() {
+            ssrcs.push(parse_field::<u32>(ssrc, "ssrc")?);
+        }
+
+        Ok(SsrcGroupValue {
+            semantics: parse_field::<SsrcGroupSemantics>(values[0], "semantics")?,
+            ssrcs: ssrcs,
+        })
+    }
+}
+
+impl ToString for SsrcGroupValue {
+
+    fn to_string(&self) -> String {
+        let mut value = self.semantics.to_string();
+
+        for ssrc in self.ssrcs.iter() {
+            value = format!("{} {}", value, ssrc);
+        }
+
+        value
+    }
+}
+
+/// Media stream and track a media description belongs to (RFC 8830).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MsidValue {
+    pub stream_id: String,
+    pub track_id: Option<String>,
+}
+
+impl FromStr for MsidValue {
+    type Err = String;
+
+    fn from_str(msid_value: &str) -> Result<Self, Self::Err> {
+        let values = msid_value.trim().splitn(2, ' ').collect::<Vec<&str>>();
+        if values[0].is_empty() {
+            return Err("missing stream id".to_string());
+        }
+
+        Ok(MsidValue {
+            stream_id: values[0].to_string(),
+            track_id: values.get(1).map(|s| s.to_string()),
+        })
+    }
+}
+
+impl ToString for MsidValue {
+
+    fn to_string(&self) -> String {
+        match self.track_id {
+            Some(ref track_id) => format!("{} {}", self.stream_id, track_id),
+            None => self.stream_id.to_string(),
+        }
+    }
+}
+
+/// Type of RTCP feedback message (RFC 4585, RFC 5104).
+#[derive(Clone, Debug, PartialEq)]
+pub enum RtcpFbKind {
+    /// Generic NACK
+    Nack,
+    /// Picture Loss Indication
+    NackPli,
+    /// Full Intra Request
+    CcmFir,
+    /// Receiver Estimated Max Bitrate
+    GoogRemb,
+    /// Transport-wide congestion control
+    TransportCc,
+    Other(String),
+}
+
+impl ToString for RtcpFbKind {
+
+    fn to_string(&self) -> String {
+        match *self {
+            RtcpFbKind::Nack => "nack".to_string(),
+            RtcpFbKind::NackPli => "nack pli".to_string(),
+            RtcpFbKind::CcmFir => "ccm fir".to_string(),
+            RtcpFbKind::GoogRemb => "goog-remb".to_string(),
+            RtcpFbKind::TransportCc => "transport-cc".to_string(),
+            RtcpFbKind::Other(ref x) => x.to_string(),
+        }
+    }
+}
+
+impl FromStr for RtcpFbKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<RtcpFbKind, ()> {
+        match s {
+            "nack" => Ok(RtcpFbKind::Nack),
+            "nack pli" => Ok(RtcpFbKind::NackPli),
+            "ccm fir" => Ok(RtcpFbKind::CcmFir),
+            "goog-remb" => Ok(RtcpFbKind::GoogRemb),
+            "transport-cc" => Ok(RtcpFbKind::TransportCc),
+            "" => Err(()),
+            _ => Ok(RtcpFbKind::Other(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RtcpFbValue {
+    /// Payload type the feedback applies to, `None` for all ("*").
+    pub payload_type: Option<u32>,
+    pub kind: RtcpFbKind,
+}
+
+impl FromStr for RtcpFbValue {
+    type Err = String;
+
+    fn from_str(rtcp_fb_value: &str) -> Result<Self, Self::Err> {
+        let values = rtcp_fb_value.splitn(2, ' ').collect::<Vec<&str>>();
+        if values.len() != 2 {
+            return Err("missing feedback type".to_string());
+        }
+
+        let payload_type = match values[0] {
+            "*" => None,
+            pt => Some(parse_field::<u32>(pt, "payload type")?),
+        };
+
+        Ok(RtcpFbValue {
+            payload_type: payload_type,
+            kind: parse_field::<RtcpFbKind>(values[1].trim(), "feedback type")?,
+        })
+    }
+}
+
+impl ToString for RtcpFbValue {
+
+    fn to_string(&self) -> String {
+        match self.payload_type {
+            Some(pt) => format!("{} {}", pt, self.kind.to_string()),
+            None => format!("* {}", self.kind.to_string()),
+        }
+    }
+}
+
+/// Direction of a stream or of an RTP header extension.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    SendRecv,
+    SendOnly,
+    RecvOnly,
+    Inactive,
+}
+
+impl Direction {
+    pub fn from_attr(attr: &Attr) -> Option<Direction> {
+        match *attr {
+            Attr::SendRecv => Some(Direction::SendRecv),
+            Attr::SendOnly => Some(Direction::SendOnly),
+            Attr::RecvOnly => Some(Direction::RecvOnly),
+            Attr::Inactive => Some(Direction::Inactive),
+            _ => None,
+        }
+    }
+
+    pub fn to_attr(&self) -> Attr {
+        match *self {
+            Direction::SendRecv => Attr::SendRecv,
+            Direction::SendOnly => Attr::SendOnly,
+            Direction::RecvOnly => Attr::RecvOnly,
+            Direction::Inactive => Attr::Inactive,
+        }
+    }
+
+    pub fn sends(&self) -> bool {
+        *self == Direction::SendRecv || *self == Direction::SendOnly
+    }
+
+    pub fn receives(&self) -> bool {
+        *self == Direction::SendRecv || *self == Direction::RecvOnly
+    }
+
+    /// Direction the answerer uses, from its own point of view.
+    pub fn reverse(&self) -> Direction {
+        match *self {
+            Direction::SendOnly => Direction::RecvOnly,
+            Direction::RecvOnly => Direction::SendOnly,
+            ref x => x.clone(),
+        }
+    }
+}
+
+impl ToString for Direction {
+
+    fn to_string(&self) -> String {
+        match *self {
+            Direction::SendRecv => "sendrecv".to_string(),
+            Direction::SendOnly => "sendonly".to_string(),
+            Direction::RecvOnly => "recvonly".to_string(),
+            Direction::Inactive => "inactive".to_string(),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Direction, ()> {
+        match s {
+            "sendrecv" => Ok(Direction::SendRecv),
+            "sendonly" => Ok(Direction::SendOnly),
+            "recvonly" => Ok(Direction::RecvOnly),
+            "inactive" => Ok(Direction::Inactive),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Audio level of the packet's samples (RFC 6464)
+pub const AUDIO_LEVEL_URI: &'static str = "urn:ietf:params:rtp-hdrext:ssrc-audio-level";
+/// Absolute sender time, for bandwidth estimation
+pub const ABS_SEND_TIME_URI: &'static str = "http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time";
+/// Transport-wide sequence number, for transport-cc feedback
+pub const TRANSPORT_CC_URI: &'static str = "http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01";
+/// Media identification, for BUNDLE demultiplexing (RFC 8843)
+pub const SDES_MID_URI: &'static str = "urn:ietf:params:rtp-hdrext:sdes:mid";
+/// RTP stream identifier, telling simulcast layers apart (RFC 8852)
+pub const RTP_STREAM_ID_URI: &'static str = "urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id";
+
+/// RTP header extensions hibrido supports, only these are answered.
+pub const SUPPORTED_EXTENSIONS: &'static [&'static str] = &[
+    AUDIO_LEVEL_URI,
+    ABS_SEND_TIME_URI,
+    TRANSPORT_CC_URI,
+    SDES_MID_URI,
+    RTP_STREAM_ID_URI,
+];
+
+fn parse_extmap(extmap_value: &str) -> Result<Attr, String> {
+    let values = extmap_value.splitn(3, ' ').collect::<Vec<&str>>();
+    if values.len() < 2 {
+        return Err("missing extension URI".to_string());
+    }
+
+    let id = values[0].splitn(2, '/').collect::<Vec<&str>>();
+    let mut direction = None;
+    if id.len() == 2 {
+        direction = Some(parse_field::<Direction>(id[1], "direction")?);
+    }
+
+    Ok(Attr::ExtMap {
+        id: parse_field::<u16>(id[0], "extension id")?,
+        direction: direction,
+        uri: values[1].to_string(),
+        attrs: values.get(2).map(|s| s.to_string()),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RidDirection {
+    Send,
+    Recv,
+}
+
+impl RidDirection {
+    pub fn reverse(&self) -> RidDirection {
+        match *self {
+            RidDirection::Send => RidDirection::Recv,
+            RidDirection::Recv => RidDirection::Send,
+        }
+    }
+}
+
+impl ToString for RidDirection {
+
+    fn to_string(&self) -> String {
+        match *self {
+            RidDirection::Send => "send".to_string(),
+            RidDirection::Recv => "recv".to_string(),
+        }
+    }
+}
+
+impl FromStr for RidDirection {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<RidDirection, ()> {
+        match s {
+            "send" => Ok(RidDirection::Send),
+            "recv" => Ok(RidDirection::Recv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// RTP stream identifier, with the restrictions applying to the stream
+/// (RFC 8851).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RidValue {
+    pub id: String,
+    pub direction: RidDirection,
+    /// Payload types the stream may use, all of the media if empty.
+    pub payload_types: Vec<u32>,
+    /// Restrictions such as "max-width", in order of appearance.
+    pub restrictions: Vec<(String, Option<String>)>,
+}
+
+impl RidValue {
+    /// Value of the restriction named `name`, e.g. "max-width".
+    pub fn restriction(&self, name: &str) -> Option<&str> {
+        self.restrictions.iter()
+            .filter(|&&(ref n, _)| n == name)
+            .filter_map(|&(_, ref value)| value.as_ref().map(|v| v.as_str()))
+            .next()
+    }
+}
+
+impl FromStr for RidValue {
+    type Err = String;
+
+    fn from_str(rid_value: &str) -> Result<Self, Self::Err> {
+        let values = rid_value.splitn(3, ' ').collect::<Vec<&str>>();
+        if values.len() < 2 {
+            return Err("missing rid direction".to_string());
+        }
+
+        let mut payload_types = vec![];
+        let mut restrictions = vec![];
+        if let Some(params) = values.get(2) {
+            for param in params.split(';').filter(|p| !p.is_empty()) {
+                let param = param.splitn(2, '=').collect::<Vec<&str>>();
+                match (param[0], param.get(1)) {
+                    ("pt", Some(pts)) => {
+                        for pt in pts.split(',') {
+                            payload_types.push(parse_field::<u32>(pt, "payload type")?);
+                        }
+                    },
+                    (name, value) => {
+                        restrictions.push((name.to_string(), value.map(|v| v.to_string())));
+                    },
+                }
+            }
+        }
+
+        Ok(RidValue {
+            id: values[0].to_string(),
+            direction: parse_field::<RidDirection>(values[1], "direction")?,
+            payload_types: payload_types,
+            restrictions: restrictions,
+        })
+    }
+}
+
+impl ToString for RidValue {
+
+    fn to_string(&self) -> String {
+        let mut params = vec![];
+        if !self.payload_types.is_empty() {
+            let pts = self.payload_types.iter().map(|pt| pt.to_string()).collect::<Vec<String>>();
+            params.push(format!("pt={}", pts.join(",")));
+        }
+        for &(ref name, ref value) in self.restrictions.iter() {
+            match *value {
+                Some(ref value) => params.push(format!("{}={}", name, value)),
+                None => params.push(name.to_string()),
+            }
+        }
+
+        let value = format!("{} {}", self.id, self.direction.to_string());
+        if params.is_empty() {
+            return value
+        }
+
+        format!("{} {}", value, params.join(";"))
+    }
+}
+
+/// A RID in a simulcast stream list, "~" marking it as paused.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimulcastRid {
+    pub id: String,
+    pub paused: bool,
+}
+
+impl ToString for SimulcastRid {
+
+    fn to_string(&self) -> String {
+        if self.paused {
+            return format!("~{}", self.id)
+        }
+
+        self.id.to_string()
+    }
+}
+
+/// Simulcast streams, each one with its alternative RIDs (RFC 8853).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimulcastValue {
+    pub send: Vec<Vec<SimulcastRid>>,
+    pub recv: Vec<Vec<SimulcastRid>>,
+}
+
+fn parse_simulcast_streams(streams: &str) -> Result<Vec<Vec<SimulcastRid>>, String> {
+    let mut result = vec![];
+    for stream in streams.split(';') {
+        let mut alternatives = vec![];
+        for rid in stream.split(',') {
+            let paused = rid.starts_with('~');
+            let id = if paused { &rid[1..] } else { rid };
+            if id.is_empty() {
+                return Err(format!("invalid simulcast stream '{}'", stream));
+            }
+
+            alternatives.push(SimulcastRid {
+                id: id.to_string(),
+                paused: paused,
+            });
+        }
+        result.push(alternatives);
+    }
+
+    Ok(result)
+}
+
+fn simulcast_streams_to_string(streams: &[Vec<SimulcastRid>]) -> String {
+    streams.iter()
+        .map(|alternatives| alternatives.iter().map(|rid| rid.to_string()).collect::<Vec<String>>().join(","))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+impl FromStr for SimulcastValue {
+    type Err = String;
+
+    fn from_str(simulcast_value: &str) -> Result<Self, Self::Err> {
+        let values = simulcast_value.split(' ').collect::<Vec<&str>>();
+        if values.len() != 2 && values.len() != 4 {
+            return Err("malformed simulcast value".to_string());
+        }
+
+        let mut simulcast = SimulcastValue {
+            send: vec![],
+            recv: vec![],
+        };
+        for direction in values.chunks(2) {
+            let streams = parse_simulcast_streams(direction[1])?;
+            match parse_field::<RidDirection>(direction[0], "direction")? {
+                RidDirection::Send => simulcast.send = streams,
+                RidDirection::Recv => simulcast.recv = streams,
+            }
+        }
+
+        Ok(simulcast)
+    }
+}
+
+impl ToString for SimulcastValue {
+
+    fn to_string(&self) -> String {
+        let mut values = vec![];
+        if !self.send.is_empty() {
+            values.push(format!("send {}", simulcast_streams_to_string(&self.send)));
+        }
+        if !self.recv.is_empty() {
+            values.push(format!("recv {}", simulcast_streams_to_string(&self.recv)));
+        }
+
+        values.join(" ")
+    }
+}
+
+/// DTLS role of an endpoint, as of RFC 4145 and RFC 5763.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SetupValue {
+    Active,
+    Passive,
+    ActPass,
+    HoldConn,
+}
+
+impl ToString for SetupValue {
+
+    fn to_string(&self) -> String {
+        match *self {
+            SetupValue::Active => "active".to_string(),
+            SetupValue::Passive => "passive".to_string(),
+            SetupValue::ActPass => "actpass".to_string(),
+            SetupValue::HoldConn => "holdconn".to_string(),
+        }
+    }
+}
+
+impl FromStr for SetupValue {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<SetupValue, ()> {
+        match s {
+            "active" => Ok(SetupValue::Active),
+            "passive" => Ok(SetupValue::Passive),
+            "actpass" => Ok(SetupValue::ActPass),
+            "holdconn" => Ok(SetupValue::HoldConn),
+            _ => Err(()),
+        }
+    }
+}
+
+impl SetupValue {
+    /// Role to answer with, given the offered one (RFC 5763, section 5).
+    /// The answerer takes the active role whenever it has the choice. An
+    /// offer without "a=setup" is taken as "actpass" (RFC 8842).
+    pub fn answer_to(offer: Option<&SetupValue>) -> SetupValue {
+        match offer {
+            Some(&SetupValue::Active) => SetupValue::Passive,
+            Some(&SetupValue::Passive) => SetupValue::Active,
+            Some(&SetupValue::HoldConn) => SetupValue::HoldConn,
+            Some(&SetupValue::ActPass) | None => SetupValue::Active,
+        }
+    }
+}
+
+/// An "a=" line. In JSON, an object with the attribute name as "type" and
+/// its parsed value, if any, as "value".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "kebab-case")]
+pub enum Attr {
+    #[serde(rename = "sendrecv")]
+    SendRecv,
+    #[serde(rename = "sendonly")]
+    SendOnly,
+    #[serde(rename = "recvonly")]
+    RecvOnly,
+    Inactive,
+    #[serde(rename = "ptime")]
+    PTime(PTimeValue),
+    #[serde(rename = "rtpmap")]
+    RtpMap(RtpMapValue),
+    #[serde(rename = "fmtp")]
+    FmtP(FmtPValue),
+    Candidate(CandidateValue),
+    IceUfrag(IceUfragValue),
+    IcePwd(IcePwdValue),
+    IceMismatch,
+    IceLite,
+    Fingerprint(FingerprintValue),
+    Setup(SetupValue),
+    Group(GroupValue),
+    Mid(String),
+    RtcpMux,
+    Rtcp(RtcpValue),
+    Ssrc(SsrcValue),
+    SsrcGroup(SsrcGroupValue),
+    Msid(MsidValue),
+    RtcpFb(RtcpFbValue),
+    /// RTP header extension (RFC 8285)
+    #[serde(rename = "extmap")]
+    ExtMap {
+        id: u16,
+        direction: Option<Direction>,
+        uri: String,
+        attrs: Option<String>,
+    },
+    Rid(RidValue),
+    Simulcast(SimulcastValue),
+    /// Attribute not understood by hibrido, kept as received.
+    Other { name: String, value: Option<String> },
+}
+
+impl ToString for Attr {
+
+    fn to_string(&self) -> String {
+
+        let name;
+        let mut value: Option<String> = None;
+        match *self {
+            Attr::RecvOnly => {
+                name = "recvonly".to_string();
+            },
+            Attr::SendOnly => {
+                name = "sendonly".to_string();
+            },
+            Attr::SendRecv => {
+                name = "sendrecv".to_string();
+            },
+            Attr::Inactive => {
+                name = "inactive".to_string();
+            },
+            Attr::PTime(PTimeValue{value: x}) => {
+                name = "ptime".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::RtpMap(ref x) => {
+                name = "rtpmap".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::FmtP(ref x) => {
+                name = "fmtp".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Candidate(ref x) => {
+                name = "candidate".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::IceUfrag(IceUfragValue{value: ref x}) => {
+                name = "ice-ufrag".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::IcePwd(IcePwdValue{value: ref x}) => {
+                name = "ice-pwd".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::IceMismatch => {
+                name = "ice-mismatch".to_string();
+            },
+            Attr::IceLite => {
+                name = "ice-lite".to_string();
+            },
+            Attr::Fingerprint(ref x) => {
+                name = "fingerprint".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Setup(ref x) => {
+                name = "setup".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Group(ref x) => {
+                name = "group".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Mid(ref x) => {
+                name = "mid".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::RtcpMux => {
+                name = "rtcp-mux".to_string();
+            },
+            Attr::Rtcp(ref x) => {
+                name = "rtcp".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Ssrc(ref x) => {
+                name = "ssrc".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::SsrcGroup(ref x) => {
+                name = "ssrc-group".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Msid(ref x) => {
+                name = "msid".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::RtcpFb(ref x) => {
+                name = "rtcp-fb".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::ExtMap { id, ref direction, ref uri, ref attrs } => {
+                name = "extmap".to_string();
+                let mut extmap = id.to_string();
+                if let Some(ref direction) = *direction {
+                    extmap = format!("{}/{}", extmap, direction.to_string());
+                }
+                extmap = format!("{} {}", extmap, uri);
+                if let Some(ref attrs) = *attrs {
+                    extmap = format!("{} {}", extmap, attrs);
+                }
+                value = Some(extmap);
             },
-            "ice-mismatch"  => Ok(Attr::IceMismatch),
-            "ice-lite"  => Ok(Attr::IceLite),
-            _           => Err(()),
+            Attr::Rid(ref x) => {
+                name = "rid".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Simulcast(ref x) => {
+                name = "simulcast".to_string();
+                value = Some(x.to_string());
+            },
+            Attr::Other { name: ref x, value: ref y } => {
+                name = x.to_string();
+                value = y.clone();
+            },
+        }
+
+        match value {
+            Some(x) => format!("a={}:{}\r\n", name, x),
+            None => format!("a={}\r\n", name)
+        }
+    }
+}
+
+trait AttrFromStr {
+    fn from_str(attr_type: &str, attr_value: Option<&str>) -> Result<Attr, String>;
+}
+
+/// Returns the value of an attribute that requires one.
+fn require_value<'a>(attr_value: Option<&'a str>) -> Result<&'a str, String> {
+    match attr_value {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err("missing attribute value".to_string()),
+    }
+}
+
+impl AttrFromStr for Attr {
+
+    /// Parses an attribute. Unknown attributes are kept as `Attr::Other`,
+    /// so they can be passed through or stripped on purpose.
+    fn from_str(attr_type: &str, attr_value: Option<&str>) -> Result<Attr, String> {
+        if attr_type.is_empty() || attr_type.contains(' ') {
+            return Err(format!("invalid attribute name '{}'", attr_type));
         }
+
+        let attr = match attr_type {
+            "recvonly"  => Attr::RecvOnly,
+            "sendonly"  => Attr::SendOnly,
+            "sendrecv"  => Attr::SendRecv,
+            "inactive"  => Attr::Inactive,
+            "ptime"     => {
+                Attr::PTime(PTimeValue{
+                    value: parse_field::<u32>(require_value(attr_value)?, "ptime")?
+                })
+            },
+            "rtpmap"    => {
+                Attr::RtpMap(require_value(attr_value)?.parse::<RtpMapValue>()?)
+            },
+            "fmtp"      => {
+                Attr::FmtP(require_value(attr_value)?.parse::<FmtPValue>()?)
+            },
+            "candidate" => {
+                Attr::Candidate(require_value(attr_value)?.parse::<CandidateValue>()?)
+            },
+            "ice-ufrag" => {
+                Attr::IceUfrag(IceUfragValue{
+                    value: require_value(attr_value)?.to_string(),
+                })
+            },
+            "ice-pwd" => {
+                Attr::IcePwd(IcePwdValue{
+                    value: require_value(attr_value)?.to_string(),
+                })
+            },
+            "ice-mismatch"  => Attr::IceMismatch,
+            "ice-lite"  => Attr::IceLite,
+            "fingerprint" => {
+                Attr::Fingerprint(require_value(attr_value)?.parse::<FingerprintValue>()?)
+            },
+            "setup" => {
+                Attr::Setup(parse_field::<SetupValue>(require_value(attr_value)?, "setup")?)
+            },
+            "group" => {
+                Attr::Group(require_value(attr_value)?.parse::<GroupValue>()?)
+            },
+            "mid" => {
+                Attr::Mid(require_value(attr_value)?.to_string())
+            },
+            "rtcp-mux" => Attr::RtcpMux,
+            "rtcp" => {
+                Attr::Rtcp(require_value(attr_value)?.parse::<RtcpValue>()?)
+            },
+            "ssrc" => {
+                Attr::Ssrc(require_value(attr_value)?.parse::<SsrcValue>()?)
+            },
+            "ssrc-group" => {
+                Attr::SsrcGroup(require_value(attr_value)?.parse::<SsrcGroupValue>()?)
+            },
+            "msid" => {
+                Attr::Msid(require_value(attr_value)?.parse::<MsidValue>()?)
+            },
+            "rtcp-fb" => {
+                Attr::RtcpFb(require_value(attr_value)?.parse::<RtcpFbValue>()?)
+            },
+            "extmap" => parse_extmap(require_value(attr_value)?)?,
+            "rid" => {
+                Attr::Rid(require_value(attr_value)?.parse::<RidValue>()?)
+            },
+            "simulcast" => {
+                Attr::Simulcast(require_value(attr_value)?.parse::<SimulcastValue>()?)
+            },
+            _           => {
+                Attr::Other {
+                    name: attr_type.to_string(),
+                    value: attr_value.map(|v| v.to_string()),
+                }
+            },
+        };
+
+        Ok(attr)
     }
 }
 
@@ -406,35 +1745,31 @@ pub struct ConnectionData {
     pub num_addresses: Option<u8>
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SessionDescription {
     pub ver: Option<i32>, 
     pub origin: Option<Origin>,
     pub name: Option<String>,
     pub info: Option<String>,
     pub uri: Option<String>,
-    //email:
-    //phone:
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
     pub conn: Option<Connection>,
-    //bandwidth: 
-    pub timing: Option<Timing>,
-    //time_zones:
-    //encrypt_key:
+    pub bandwidths: Vec<Bandwidth>,
+    pub times: Vec<TimeDescription>,
+    pub time_zones: Vec<TimeZone>,
+    pub encrypt_key: Option<EncryptionKey>,
     pub attrs: Vec<Attr>,
     pub media: Vec<MediaDescription>,
 }
 
-//struct TimeDescription {
-//    timing:
-//    repeat_times:
-//}
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MediaDescription {
     pub media: Media,
-    //title:
-    //bandwidth:
-    //encrypt_key:
+    pub title: Option<String>,
+    pub conns: Vec<Connection>,
+    pub bandwidths: Vec<Bandwidth>,
+    pub encrypt_key: Option<EncryptionKey>,
     pub attrs: Vec<Attr>
 }
 
@@ -442,16 +1777,37 @@ pub struct MediaDescription {
 impl ToString for MediaDescription {
 
     fn to_string(&self) -> String {
-        let mut media_description = format!("m={} {} {}",
+        let mut media_description = format!("m={} {}",
             self.media.media.to_string(),
-            self.media.port,
-            self.media.proto.to_string());
+            self.media.port);
+
+        if let Some(num_ports) = self.media.num_ports {
+            media_description = format!("{}/{}", media_description, num_ports);
+        }
+
+        media_description = format!("{} {}", media_description, self.media.proto.to_string());
 
         for i in 0..self.media.fmt.len() {
             media_description = format!("{} {}", media_description, self.media.fmt[i]);
         }
 
-        media_description = format!("{}\n", media_description);
+        media_description = format!("{}\r\n", media_description);
+
+        if let Some(ref title) = self.title {
+            media_description = media_description + &format!("i={}\r\n", title);
+        }
+
+        for conn in self.conns.iter() {
+            media_description = media_description + &conn.to_string();
+        }
+
+        for bandwidth in self.bandwidths.iter() {
+            media_description = media_description + &bandwidth.to_string();
+        }
+
+        if let Some(ref encrypt_key) = self.encrypt_key {
+            media_description = media_description + &encrypt_key.to_string();
+        }
 
         for i in 0..self.attrs.len() {
             let media_attrs = format!("{}",
@@ -465,9 +1821,159 @@ impl ToString for MediaDescription {
 }
 
 impl MediaDescription {
+    /// Whether RTP and RTCP share the same port (RFC 5761).
+    pub fn rtcp_mux(&self) -> bool {
+        self.attrs.contains(&Attr::RtcpMux)
+    }
+
+    /// Port, and address if not that of the media, of its RTCP, from
+    /// "a=rtcp" (RFC 3605).
+    pub fn rtcp(&self) -> Option<&RtcpValue> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::Rtcp(ref x) => Some(x),
+            _ => None,
+        }).next()
+    }
+
+    /// Identification tag of the media, from "a=mid".
+    pub fn mid(&self) -> Option<&str> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::Mid(ref x) => Some(x.as_str()),
+            _ => None,
+        }).next()
+    }
+
+    /// RTP header extensions of the media, by ID.
+    pub fn extmaps(&self) -> HashMap<u16, String> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::ExtMap { id, ref uri, .. } => Some((id, uri.to_string())),
+            _ => None,
+        }).collect()
+    }
+
+    /// Parameters of the format `payload_type`, from "a=fmtp".
+    pub fn fmtp(&self, payload_type: u32) -> Option<&FmtPValue> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::FmtP(ref x) if x.format == payload_type => Some(x),
+            _ => None,
+        }).next()
+    }
+
+    /// RID of identifier `id`, declared with "a=rid".
+    pub fn rid(&self, id: &str) -> Option<&RidValue> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::Rid(ref x) if x.id == id => Some(x),
+            _ => None,
+        }).next()
+    }
+
+    pub fn simulcast(&self) -> Option<&SimulcastValue> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::Simulcast(ref x) => Some(x),
+            _ => None,
+        }).next()
+    }
+
+    /// Simulcast layers of the media, in the order of "a=simulcast". For
+    /// each stream, the first alternative with an "a=rid" is the layer.
+    pub fn simulcast_layers(&self) -> Vec<RidValue> {
+        let simulcast = match self.simulcast() {
+            Some(simulcast) => simulcast,
+            None => return vec![],
+        };
+
+        simulcast.send.iter().chain(simulcast.recv.iter())
+            .filter_map(|alternatives| {
+                alternatives.iter().filter_map(|rid| self.rid(&rid.id)).next()
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Direction set with "a=sendrecv", "a=sendonly", "a=recvonly" or
+    /// "a=inactive" on the media, if any.
+    pub fn direction(&self) -> Option<Direction> {
+        self.attrs.iter().filter_map(Direction::from_attr).next()
+    }
+
+    /// Whether the direction of the media allows sending on it.
+    pub fn is_sending(&self) -> bool {
+        self.direction().map_or(true, |d| d.sends())
+    }
+
+    /// Bits per second the media is limited to, if any "b=" line sets it.
+    pub fn bitrate(&self) -> Option<u64> {
+        bitrate_of(&self.bandwidths)
+    }
+
+    /// Whether the media is disabled, with port 0 (RFC 3264, section 5.1).
+    /// Media offered as "a=bundle-only" is not (RFC 8843, section 6).
+    pub fn is_rejected(&self) -> bool {
+        self.media.port == 0 && !self.attrs.iter().any(|attr| match *attr {
+            Attr::Other { ref name, .. } => name == "bundle-only",
+            _ => false,
+        })
+    }
+
+    /// SSRCs declared on the media, in order of appearance, along with the
+    /// track each belongs to. The "msid" source attribute takes precedence
+    /// over the media-level "a=msid".
+    pub fn sources(&self) -> Vec<(u32, Option<MsidValue>)> {
+        let media_msid = self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::Msid(ref x) => Some(x.clone()),
+            _ => None,
+        }).next();
+
+        let mut ssrcs = vec![];
+        for attr in self.attrs.iter() {
+            match *attr {
+                Attr::Ssrc(ref x) => ssrcs.push(x.ssrc),
+                Attr::SsrcGroup(ref x) => ssrcs.extend(x.ssrcs.iter().cloned()),
+                _ => {},
+            }
+        }
+
+        let mut sources: Vec<(u32, Option<MsidValue>)> = vec![];
+        for ssrc in ssrcs {
+            if sources.iter().any(|&(s, _)| s == ssrc) {
+                continue;
+            }
+
+            let ssrc_msid = self.attrs.iter().filter_map(|attr| match *attr {
+                Attr::Ssrc(SsrcValue { ssrc: s, ref attribute, value: Some(ref value) })
+                    if s == ssrc && attribute == "msid" => value.parse::<MsidValue>().ok(),
+                _ => None,
+            }).next();
+
+            sources.push((ssrc, ssrc_msid.or(media_msid.clone())));
+        }
+
+        sources
+    }
+
+    /// Declares a source sent on the media, with its canonical name and the
+    /// track it belongs to.
+    pub fn add_source(&mut self, ssrc: u32, cname: &str, msid: &MsidValue) {
+        self.attrs.push(Attr::Msid(msid.clone()));
+        self.attrs.push(Attr::Ssrc(SsrcValue {
+            ssrc: ssrc,
+            attribute: "cname".to_string(),
+            value: Some(cname.to_string()),
+        }));
+        self.attrs.push(Attr::Ssrc(SsrcValue {
+            ssrc: ssrc,
+            attribute: "msid".to_string(),
+            value: Some(msid.to_string()),
+        }));
+    }
+
     pub fn new(media: Media) -> MediaDescription {
         MediaDescription {
             media: media,
+            title: None,
+            conns: vec![],
+            bandwidths: vec![],
+            encrypt_key: None,
             attrs: vec![],
         }
     }
@@ -478,6 +1984,8 @@ pub struct ParseResult {
     pub desc: SessionDescription,
     pub ignored_lines: Vec<SdpLine>,
     pub unparsed_lines: Vec<String>,
+    /// Why each of the `unparsed_lines` was rejected, in the same order.
+    pub errors: Vec<SdpError>,
 }
 
 impl ParseResult {
@@ -486,6 +1994,70 @@ impl ParseResult {
             desc: SessionDescription::new(),
             ignored_lines: vec![],
             unparsed_lines: vec![],
+            errors: vec![],
+        }
+    }
+}
+
+/// How strictly a description is checked while being parsed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseMode {
+    /// The first malformed, unknown or misplaced line fails the parsing, as
+    /// do missing mandatory lines.
+    Strict,
+    /// Offending lines are skipped and recorded in the `ParseResult`.
+    Lenient,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SdpErrorKind {
+    /// Line is not of the form `<type>=<value>`.
+    Malformed,
+    /// Type letter is not defined by RFC 8866.
+    UnknownType,
+    /// Line is valid, but not allowed where it appears.
+    Unexpected,
+    /// Mandatory line is missing from the description.
+    Missing,
+    /// Value of the line could not be parsed.
+    InvalidValue(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdpError {
+    /// Line number, starting at 1. Zero if the error is not tied to a line,
+    /// like a missing mandatory line.
+    pub line: usize,
+    /// Type letter of the offending line, if any.
+    pub line_type: Option<char>,
+    pub kind: SdpErrorKind,
+}
+
+impl SdpError {
+    fn new(line: usize, line_type: Option<char>, kind: SdpErrorKind) -> SdpError {
+        SdpError {
+            line: line,
+            line_type: line_type,
+            kind: kind,
+        }
+    }
+}
+
+impl fmt::Display for SdpError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            SdpErrorKind::Malformed => "malformed line".to_string(),
+            SdpErrorKind::UnknownType => "unknown line type".to_string(),
+            SdpErrorKind::Unexpected => "line not allowed here".to_string(),
+            SdpErrorKind::Missing => "mandatory line missing".to_string(),
+            SdpErrorKind::InvalidValue(ref x) => format!("invalid value, {}", x),
+        };
+
+        match self.line_type {
+            Some(t) if self.line > 0 => write!(f, "line {} ({}=): {}", self.line, t, reason),
+            Some(t) => write!(f, "{}=: {}", t, reason),
+            None => write!(f, "line {}: {}", self.line, reason),
         }
     }
 }
@@ -496,13 +2068,20 @@ pub enum SdpLine {
     Origin(Origin),
     Description(String),
     Information(String),
+    Uri(String),
+    Email(String),
+    Phone(String),
     Connection(Connection),
+    Bandwidth(Bandwidth),
     Timing(Timing),
+    RepeatTime(RepeatTime),
+    TimeZones(Vec<TimeZone>),
+    EncryptionKey(EncryptionKey),
     Attr(Attr),
     Media(Media),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum AddrType {
     IP4,
     IP6,
@@ -518,6 +2097,15 @@ impl ToString for AddrType {
     }
 }
 
+impl AddrType {
+    pub fn from_ip(ip_address: &IpAddr) -> AddrType {
+        match *ip_address {
+            IpAddr::V4(_) => AddrType::IP4,
+            IpAddr::V6(_) => AddrType::IP6,
+        }
+    }
+}
+
 impl FromStr for AddrType {
     type Err = ();
 
@@ -530,7 +2118,7 @@ impl FromStr for AddrType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum NetType {
     IN,
 }
@@ -563,6 +2151,9 @@ pub enum MediaProto {
     RtpSavp,
     RtpSavpf,
     UdpTlsRtpSavpf,
+    /// Data channels (RFC 8841).
+    UdpDtlsSctp,
+    DtlsSctp,
 }
 
 impl ToString for MediaProto {
@@ -575,6 +2166,8 @@ impl ToString for MediaProto {
             MediaProto::RtpSavp => "RTP/SAVP".to_string(),
             MediaProto::RtpSavpf => "RTP/SAVPF".to_string(),
             MediaProto::UdpTlsRtpSavpf => "UDP/TLS/RTP/SAVPF".to_string(),
+            MediaProto::UdpDtlsSctp => "UDP/DTLS/SCTP".to_string(),
+            MediaProto::DtlsSctp => "DTLS/SCTP".to_string(),
         }
     } 
 }
@@ -590,20 +2183,43 @@ impl FromStr for MediaProto {
             "RTP/SAVP" => Ok(MediaProto::RtpSavp),
             "RTP/SAVPF" => Ok(MediaProto::RtpSavpf),
             "UDP/TLS/RTP/SAVPF" => Ok(MediaProto::UdpTlsRtpSavpf),
+            "UDP/DTLS/SCTP" => Ok(MediaProto::UdpDtlsSctp),
+            "DTLS/SCTP" => Ok(MediaProto::DtlsSctp),
             _ => Err(()),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+impl MediaProto {
+    /// Whether the media is carried over RTP.
+    pub fn is_rtp(&self) -> bool {
+        match *self {
+            MediaProto::Udp | MediaProto::UdpDtlsSctp | MediaProto::DtlsSctp => false,
+            _ => true,
+        }
+    }
+
+    /// Whether the profile allows for early RTCP feedback (RFC 4585).
+    pub fn has_feedback(&self) -> bool {
+        match *self {
+            MediaProto::RtpAvpf | MediaProto::RtpSavpf | MediaProto::UdpTlsRtpSavpf => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Media {
     pub media: MediaType,
     pub port: u16,
+    /// Number of ports, from the "<port>/<number of ports>" form.
+    pub num_ports: Option<u16>,
     pub proto: MediaProto,
     pub fmt: Vec<String>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MediaType {
     AUDIO,
     VIDEO,
@@ -649,167 +2265,488 @@ impl SessionDescription {
             name: None,
             info: None,
             uri: None,
+            emails: vec![],
+            phones: vec![],
             conn: None,
-            timing: None,
+            bandwidths: vec![],
+            times: vec![],
+            time_zones: vec![],
+            encrypt_key: None,
             attrs: vec![],
             media: vec![],
         }
     }
 
+    /// Parses an SDP leniently, never failing. Lines that could not be
+    /// parsed are kept in `unparsed_lines`, along with the reason in
+    /// `errors`.
     pub fn from_sdp(&self, sdp: &str) -> ParseResult {
-        let mut res = ParseResult::new();
-        let sdm: Option<MediaDescription> = None;
-        let mut first_media = false;
-
-        for mut line in sdp.lines() {
-            line = line.trim();
-            debug!("line: {}", line);
-            if let Some(parsed) = parse_line(line) {
-                match sdm {
-                    None => {
-                        debug!("sdm is None");
-                        match parsed {
-                            SdpLine::ProtocolVersion(v) => { res.desc.ver = Some(v); },
-                            SdpLine::Origin(o) => { res.desc.origin = Some(o); },
-                            SdpLine::Description(s) => { res.desc.name = Some(s); },
-                            SdpLine::Information(i) => { res.desc.info = Some(i); },
-                            SdpLine::Connection(c) => { res.desc.conn = Some(c); },
-                            SdpLine::Timing(t) => { res.desc.timing = Some(t); },
-                            SdpLine::Attr(a) => {
-                                if first_media {
-                                    let size = res.desc.media.len()-1;
-                                    res.desc.media[size].attrs.push(a);
-                                } else {
-                                    res.desc.attrs.push(a);
-                                }
-                            },
-                            SdpLine::Media(m) => {
-                                res.desc.media.push(MediaDescription::new(m));
-                                first_media = true;
-                            },
-                        }
-                    }, Some(_) => {
-                       debug!("sdm has \"ref media\"");
-                       match parsed {
-                            SdpLine::ProtocolVersion(_) => { res.ignored_lines.push(parsed.clone()); },
-                            SdpLine::Origin(_) => { res.ignored_lines.push(parsed.clone()); },
-
-                            SdpLine::Description(_) => { res.ignored_lines.push(parsed.clone()); },
-                            SdpLine::Information(_) => { res.ignored_lines.push(parsed.clone()); },
-                            SdpLine::Connection(_) => { res.ignored_lines.push(parsed.clone()); },
-                            SdpLine::Timing(_) => { res.ignored_lines.push(parsed.clone()); },
-                            SdpLine::Attr(_) => { res.ignored_lines.push(parsed.clone()); },
-                            SdpLine::Media(_) => {
-                                res.ignored_lines.push(parsed.clone());
-                            },
-                        }
-                    }
+        match parse_sdp(sdp, ParseMode::Lenient) {
+            Ok(res) => res,
+            // Lenient parsing doesn't fail
+            Err(_) => ParseResult::new(),
+        }
+    }
+
+    /// ICE credentials of the media of index `media_idx`. Media-level
+    /// attributes take precedence over session-level ones.
+    pub fn ice_credentials(&self, media_idx: usize) -> Option<ice::Credentials> {
+        let media_attrs = self.media.get(media_idx).map(|m| &m.attrs[..]).unwrap_or(&[]);
+
+        let ufrag = media_attrs.iter().chain(self.attrs.iter()).filter_map(|attr| match *attr {
+            Attr::IceUfrag(ref x) => Some(x.value.clone()),
+            _ => None,
+        }).next();
+        let pwd = media_attrs.iter().chain(self.attrs.iter()).filter_map(|attr| match *attr {
+            Attr::IcePwd(ref x) => Some(x.value.clone()),
+            _ => None,
+        }).next();
+
+        match (ufrag, pwd) {
+            (Some(ufrag), Some(pwd)) => Some(ice::Credentials { ufrag: ufrag, pwd: pwd }),
+            _ => None,
+        }
+    }
+
+    /// Direction of the media of index `media_idx`. A session-level direction
+    /// applies to media without one, "a=sendrecv" being the default.
+    pub fn media_direction(&self, media_idx: usize) -> Direction {
+        self.media.get(media_idx)
+            .and_then(|media| media.direction())
+            .or(self.attrs.iter().filter_map(Direction::from_attr).next())
+            .unwrap_or(Direction::SendRecv)
+    }
+
+    /// Bits per second the media of index `media_idx` is limited to, the
+    /// lowest of its own limit and the session's.
+    pub fn media_bitrate(&self, media_idx: usize) -> Option<u64> {
+        let media = self.media.get(media_idx).and_then(|media| media.bitrate());
+
+        match (media, bitrate_of(&self.bandwidths)) {
+            (Some(media), Some(session)) => Some(cmp::min(media, session)),
+            (media, session) => media.or(session),
+        }
+    }
+
+    /// Media identification tags of each BUNDLE group, the first being the
+    /// tag of the media whose transport is shared.
+    pub fn bundle_groups(&self) -> Vec<Vec<String>> {
+        self.attrs.iter().filter_map(|attr| match *attr {
+            Attr::Group(GroupValue { semantics: GroupSemantics::Bundle, ref mids }) => Some(mids.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    /// For each media description, the index of the one whose transport it
+    /// uses. That is its own index, unless it is bundled with others.
+    pub fn transport_owners(&self) -> Vec<usize> {
+        let groups = self.bundle_groups();
+
+        self.media.iter().enumerate().map(|(i, media)| {
+            let group = match media.mid() {
+                Some(mid) => groups.iter().find(|g| g.iter().any(|m| m == mid)),
+                None => None,
+            };
+
+            group.and_then(|g| self.media.iter().position(|m| m.mid() == Some(g[0].as_str())))
+                 .unwrap_or(i)
+        }).collect()
+    }
+
+    /// Parses an SDP with the given `mode`, returning the first error found
+    /// when parsing strictly.
+    pub fn parse(sdp: &str, mode: ParseMode) -> Result<SessionDescription, SdpError> {
+        parse_sdp(sdp, mode).map(|res| res.desc)
+    }
+
+    /// JSON representation of the description. Lines keep their order, and
+    /// converting it back with `from_json` gives the same description.
+    pub fn to_json(&self) -> String {
+        // Nothing in a description fails to serialize
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<SessionDescription, String> {
+        serde_json::from_str(json).map_err(|e| format!("invalid SDP JSON: {}", e))
+    }
+}
+
+impl FromStr for SessionDescription {
+    type Err = SdpError;
+
+    fn from_str(sdp: &str) -> Result<SessionDescription, SdpError> {
+        SessionDescription::parse(sdp, ParseMode::Strict)
+    }
+}
+
+/// Builds a session description in code. It starts with the lines every
+/// description must have, "v=0", "s=-" and "t=0 0", and an origin.
+pub struct SessionDescriptionBuilder {
+    desc: SessionDescription,
+}
+
+impl SessionDescriptionBuilder {
+    pub fn new(origin: Origin) -> SessionDescriptionBuilder {
+        let mut desc = SessionDescription::new();
+        desc.ver = Some(0);
+        desc.origin = Some(origin);
+        desc.name = Some("-".to_string());
+        desc.times.push(TimeDescription::new(Timing {
+            start_time: 0,
+            stop_time: 0,
+        }));
+
+        SessionDescriptionBuilder {
+            desc: desc,
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> SessionDescriptionBuilder {
+        self.desc.name = Some(name.to_string());
+        self
+    }
+
+    pub fn info(mut self, info: &str) -> SessionDescriptionBuilder {
+        self.desc.info = Some(info.to_string());
+        self
+    }
+
+    pub fn connection(mut self, conn: Connection) -> SessionDescriptionBuilder {
+        self.desc.conn = Some(conn);
+        self
+    }
+
+    pub fn bandwidth(mut self, bwtype: BandwidthType, bandwidth: u64) -> SessionDescriptionBuilder {
+        self.desc.bandwidths.push(Bandwidth {
+            bwtype: bwtype,
+            bandwidth: bandwidth,
+        });
+        self
+    }
+
+    /// Replaces the "t=0 0" the description starts with.
+    pub fn timing(mut self, start_time: u64, stop_time: u64) -> SessionDescriptionBuilder {
+        self.desc.times = vec![TimeDescription::new(Timing {
+            start_time: start_time,
+            stop_time: stop_time,
+        })];
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> SessionDescriptionBuilder {
+        self.desc.attrs.push(attr);
+        self
+    }
+
+    pub fn media(mut self, media: MediaDescription) -> SessionDescriptionBuilder {
+        self.desc.media.push(media);
+        self
+    }
+
+    pub fn build(self) -> SessionDescription {
+        self.desc
+    }
+}
+
+/// Builds a media description in code, formats being added along with
+/// their "a=rtpmap".
+pub struct MediaDescriptionBuilder {
+    media: MediaDescription,
+}
+
+impl MediaDescriptionBuilder {
+    pub fn new(media_type: MediaType, port: u16, proto: MediaProto) -> MediaDescriptionBuilder {
+        MediaDescriptionBuilder {
+            media: MediaDescription::new(Media {
+                media: media_type,
+                port: port,
+                num_ports: None,
+                proto: proto,
+                fmt: vec![],
+            }),
+        }
+    }
+
+    /// Adds a format without "a=rtpmap", e.g. a static payload type.
+    pub fn format(mut self, fmt: &str) -> MediaDescriptionBuilder {
+        self.media.media.fmt.push(fmt.to_string());
+        self
+    }
+
+    pub fn rtpmap(mut self, payload_type: u32, encoding_name: &str, clock_rate: u32, encoding_params: Option<u32>) -> MediaDescriptionBuilder {
+        self.media.media.fmt.push(payload_type.to_string());
+        self.media.attrs.push(Attr::RtpMap(RtpMapValue {
+            payload_type: payload_type,
+            encoding_name: encoding_name.to_string(),
+            clock_rate: clock_rate,
+            encoding_params: encoding_params,
+        }));
+        self
+    }
+
+    pub fn fmtp(mut self, format: u32, params: &str) -> MediaDescriptionBuilder {
+        self.media.attrs.push(Attr::FmtP(FmtPValue {
+            format: format,
+            params: FmtPValue::parse_params(params),
+        }));
+        self
+    }
+
+    pub fn connection(mut self, conn: Connection) -> MediaDescriptionBuilder {
+        self.media.conns.push(conn);
+        self
+    }
+
+    pub fn bandwidth(mut self, bwtype: BandwidthType, bandwidth: u64) -> MediaDescriptionBuilder {
+        self.media.bandwidths.push(Bandwidth {
+            bwtype: bwtype,
+            bandwidth: bandwidth,
+        });
+        self
+    }
+
+    pub fn mid(mut self, mid: &str) -> MediaDescriptionBuilder {
+        self.media.attrs.push(Attr::Mid(mid.to_string()));
+        self
+    }
+
+    pub fn direction(mut self, direction: Direction) -> MediaDescriptionBuilder {
+        self.media.attrs.push(direction.to_attr());
+        self
+    }
+
+    pub fn attr(mut self, attr: Attr) -> MediaDescriptionBuilder {
+        self.media.attrs.push(attr);
+        self
+    }
+
+    pub fn build(self) -> MediaDescription {
+        self.media
+    }
+}
+
+fn parse_sdp(sdp: &str, mode: ParseMode) -> Result<ParseResult, SdpError> {
+    let mut res = ParseResult::new();
+    let mut first_media = false;
+
+    for (i, mut line) in sdp.lines().enumerate() {
+        line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        debug!("line: {}", line);
+        let parsed = match parse_line(i + 1, line) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                debug!("invalid: {} ({})", line, e);
+                if mode == ParseMode::Strict {
+                    return Err(e);
                 }
-            } else {
-                debug!("invalid: {}", line);
                 res.unparsed_lines.push(line.to_string());
+                res.errors.push(e);
+                continue;
+            },
+        };
+
+        let unexpected = if first_media {
+            let size = res.desc.media.len()-1;
+            let media = &mut res.desc.media[size];
+            match parsed {
+                SdpLine::Information(i) => { media.title = Some(i); None },
+                SdpLine::Connection(c) => { media.conns.push(c); None },
+                SdpLine::Bandwidth(b) => { media.bandwidths.push(b); None },
+                SdpLine::EncryptionKey(k) => { media.encrypt_key = Some(k); None },
+                SdpLine::Attr(a) => { media.attrs.push(a); None },
+                SdpLine::Media(m) => {
+                    res.desc.media.push(MediaDescription::new(m));
+                    None
+                },
+                // Session-level lines can't follow a media description
+                _ => Some(parsed),
+            }
+        } else {
+            match parsed {
+                SdpLine::ProtocolVersion(v) => { res.desc.ver = Some(v); None },
+                SdpLine::Origin(o) => { res.desc.origin = Some(o); None },
+                SdpLine::Description(s) => { res.desc.name = Some(s); None },
+                SdpLine::Information(i) => { res.desc.info = Some(i); None },
+                SdpLine::Uri(u) => { res.desc.uri = Some(u); None },
+                SdpLine::Email(e) => { res.desc.emails.push(e); None },
+                SdpLine::Phone(p) => { res.desc.phones.push(p); None },
+                SdpLine::Connection(c) => { res.desc.conn = Some(c); None },
+                SdpLine::Bandwidth(b) => { res.desc.bandwidths.push(b); None },
+                SdpLine::Timing(t) => {
+                    res.desc.times.push(TimeDescription::new(t));
+                    None
+                },
+                SdpLine::RepeatTime(r) => {
+                    // Repeat times apply to the "t=" line just before
+                    match res.desc.times.last_mut() {
+                        Some(time) => { time.repeat_times.push(r); None },
+                        None => Some(SdpLine::RepeatTime(r)),
+                    }
+                },
+                SdpLine::TimeZones(z) => { res.desc.time_zones = z; None },
+                SdpLine::EncryptionKey(k) => { res.desc.encrypt_key = Some(k); None },
+                SdpLine::Attr(a) => { res.desc.attrs.push(a); None },
+                SdpLine::Media(m) => {
+                    res.desc.media.push(MediaDescription::new(m));
+                    first_media = true;
+                    None
+                },
+            }
+        };
+
+        if let Some(parsed) = unexpected {
+            if mode == ParseMode::Strict {
+                return Err(SdpError::new(i + 1, line.chars().next(), SdpErrorKind::Unexpected));
             }
+            res.ignored_lines.push(parsed);
         }
+    }
+
+    if mode == ParseMode::Strict {
+        check_mandatory_lines(&res.desc)?;
+    }
+
+    Ok(res)
+}
+
+/// Checks that the lines the serializer relies on are present.
+fn check_mandatory_lines(desc: &SessionDescription) -> Result<(), SdpError> {
+    let missing = if desc.ver.is_none() {
+        Some('v')
+    } else if desc.origin.is_none() {
+        Some('o')
+    } else if desc.name.is_none() {
+        Some('s')
+    } else if desc.times.is_empty() {
+        Some('t')
+    } else if desc.conn.is_none() && desc.media.iter().any(|m| m.conns.is_empty()) {
+        // Either at the session level, or in every media description
+        Some('c')
+    } else {
+        None
+    };
 
-        res
+    match missing {
+        Some(t) => Err(SdpError::new(0, Some(t), SdpErrorKind::Missing)),
+        None => Ok(()),
     }
 }
 
-fn parse_line(line: &str) -> Option<SdpLine> {
+/// Parses a single line, `line_nr` being its position in the description.
+fn parse_line(line_nr: usize, line: &str) -> Result<SdpLine, SdpError> {
     let parts = line.splitn(2, '=').collect::<Vec<&str>>();
-    if parts.len() != 2 {
-        return None;
+    if parts.len() != 2 || parts[0].chars().count() != 1 {
+        return Err(SdpError::new(line_nr, None, SdpErrorKind::Malformed));
     }
 
     let line_type = parts[0];
     let line_val = parts[1];
+    let type_char = line_type.chars().next();
 
-    match line_type {
+    let invalid = |reason: String| {
+        SdpError::new(line_nr, type_char, SdpErrorKind::InvalidValue(reason))
+    };
+
+    let parsed = match line_type {
         "v" => {
-            if let Ok(v) = FromStr::from_str(line_val) {
-                debug!("v => {}", v);
-                Some(SdpLine::ProtocolVersion(v))
-            } else {
-                debug!("v is None");
-                None
-            }
+            SdpLine::ProtocolVersion(parse_field::<i32>(line_val, "version").map_err(&invalid)?)
         },
         "o" => {
-            if let Some(o) = parse_origin(line_val) {
-                Some(SdpLine::Origin(o))
-            } else {
-                debug!("o is None");
-                None
-            }
+            SdpLine::Origin(parse_origin(line_val).map_err(&invalid)?)
         },
         "s" => {
-            if let Some(s) = parse_session_name(line_val) {
-                Some(SdpLine::Description(s))
-            } else {
-                debug!("Session name not valid");
-                None
-            }
+            SdpLine::Description(parse_session_name(line_val).map_err(&invalid)?)
         },
         "i" => {
-            if let Some(i) = parse_information(line_val) {
-                Some(SdpLine::Information(i))
-            } else {
-                debug!("Information not valid");
-                None
-            }
+            SdpLine::Information(parse_information(line_val).map_err(&invalid)?)
+        },
+        "u" => {
+            SdpLine::Uri(parse_information(line_val).map_err(&invalid)?)
+        },
+        "e" => {
+            SdpLine::Email(parse_information(line_val).map_err(&invalid)?)
+        },
+        "p" => {
+            SdpLine::Phone(parse_information(line_val).map_err(&invalid)?)
         },
-        // TODO: support optional URI, "uri"
-        // TODO: support optional email address and phone number, "e" and "p"
         "c" => {
-            if let Some(c) = parse_connection(line_val) {
-                Some(SdpLine::Connection(c))
-            } else {
-                debug!("Connection not valid");
-                None
-            }
+            SdpLine::Connection(parse_connection(line_val).map_err(&invalid)?)
+        },
+        "b" => {
+            SdpLine::Bandwidth(parse_bandwidth(line_val).map_err(&invalid)?)
         },
         "t" => {
-            if let Some(t) = parse_timing(line_val) {
-                Some(SdpLine::Timing(t))
-            } else {
-                debug!("Timing not valid");
-                None
-            }
+            SdpLine::Timing(parse_timing(line_val).map_err(&invalid)?)
+        },
+        "r" => {
+            SdpLine::RepeatTime(parse_repeat_time(line_val).map_err(&invalid)?)
+        },
+        "z" => {
+            SdpLine::TimeZones(parse_time_zones(line_val).map_err(&invalid)?)
+        },
+        "k" => {
+            SdpLine::EncryptionKey(parse_encryption_key(line_val).map_err(&invalid)?)
         },
         "a" => {
-            if let Some(a) = parse_attr(line_val) {
-                Some(SdpLine::Attr(a))
-            } else {
-                debug!("Attribute not valid");
-                None
-            }        
+            SdpLine::Attr(parse_attr(line_val).map_err(&invalid)?)
         },
         "m" => {
-            if let Some(m) = parse_media(line_val) {
-                Some(SdpLine::Media(m))
-            } else {
-                debug!("Media not valid");
-                None
-            }        
+            SdpLine::Media(parse_media(line_val).map_err(&invalid)?)
         },
-         _ => None
-    }
+         _ => return Err(SdpError::new(line_nr, type_char, SdpErrorKind::UnknownType)),
+    };
 
+    Ok(parsed)
 }
 
 impl ToString for SessionDescription {
 
     fn to_string(&self) -> String {
-        let mut session_description = format!("v={}\n{}s={}\n{}",
-                 self.ver.unwrap(),
-                 self.origin.clone().unwrap().to_string(),
-                 self.name.clone().unwrap(),
-                 //self.info.clone().unwrap(),
-                 self.conn.clone().unwrap().to_string());
+        let mut session_description = String::new();
 
-        session_description = session_description + &format!("t={} {}\n",
-                 self.timing.clone().unwrap().start_time,
-                 self.timing.clone().unwrap().stop_time);
+        if let Some(ver) = self.ver {
+            session_description = session_description + &format!("v={}\r\n", ver);
+        }
+        if let Some(ref origin) = self.origin {
+            session_description = session_description + &origin.to_string();
+        }
+        if let Some(ref name) = self.name {
+            session_description = session_description + &format!("s={}\r\n", name);
+        }
+        if let Some(ref info) = self.info {
+            session_description = session_description + &format!("i={}\r\n", info);
+        }
+        if let Some(ref uri) = self.uri {
+            session_description = session_description + &format!("u={}\r\n", uri);
+        }
+        for email in self.emails.iter() {
+            session_description = session_description + &format!("e={}\r\n", email);
+        }
+        for phone in self.phones.iter() {
+            session_description = session_description + &format!("p={}\r\n", phone);
+        }
+        if let Some(ref conn) = self.conn {
+            session_description = session_description + &conn.to_string();
+        }
+        for bandwidth in self.bandwidths.iter() {
+            session_description = session_description + &bandwidth.to_string();
+        }
+        for time in self.times.iter() {
+            session_description = session_description + &time.to_string();
+        }
+        if self.time_zones.len() > 0 {
+            let zones = self.time_zones.iter()
+                .map(|z| format!("{} {}", z.adjustment_time, z.offset.to_string()))
+                .collect::<Vec<String>>();
+            session_description = session_description + &format!("z={}\r\n", zones.join(" "));
+        }
+        if let Some(ref encrypt_key) = self.encrypt_key {
+            session_description = session_description + &encrypt_key.to_string();
+        }
 
         for i in 0..self.attrs.len() {
             session_description = session_description + &self.attrs[i].to_string();
@@ -823,30 +2760,40 @@ impl ToString for SessionDescription {
     } 
 }
 
-fn parse_origin(text: &str) -> Option<Origin> {
-    let parts = text.split(' ').collect::<Vec<&str>>();
-    if parts.len() != 6 {
-        return None;
-    }
+/// Parses `value` into `T`, describing it as `what` on failure.
+fn parse_field<T: FromStr>(value: &str, what: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid {} '{}'", what, value))
+}
 
-    let session_version = FromStr::from_str(parts[2]);
-    let ip_addr = FromStr::from_str(parts[5]);
+/// Parses an address of type `addr_type`, which IP addresses have to match.
+fn parse_address(addr_type: &AddrType, text: &str) -> Result<Address, String> {
+    let address = parse_field::<Address>(text, "address")?;
+    match address {
+        Address::Ip(ref ip) if AddrType::from_ip(ip) != *addr_type => {
+            Err(format!("address '{}' is not of type {}", text, addr_type.to_string()))
+        },
+        _ => Ok(address),
+    }
+}
 
-    if session_version.is_err() || ip_addr.is_err() {
-        return None
+fn parse_origin(text: &str) -> Result<Origin, String> {
+    let parts = text.split(' ').collect::<Vec<&str>>();
+    if parts.len() != 6 {
+        return Err("expected 6 fields".to_string());
     }
 
-    Some(Origin {
+    let addr_type = parse_field::<AddrType>(parts[4], "address type")?;
+    Ok(Origin {
         username: parts[0].to_string(),
         session_id: parts[1].to_string(),
-        session_version: session_version.unwrap(),
-        net_type: parts[3].parse::<NetType>().unwrap(),
-        addr_type: parts[4].parse::<AddrType>().unwrap(),
-        ip_address: ip_addr.unwrap(),
+        session_version: parse_field::<u64>(parts[2], "session version")?,
+        net_type: parse_field::<NetType>(parts[3], "network type")?,
+        ip_address: parse_address(&addr_type, parts[5])?,
+        addr_type: addr_type,
     })
 }
 
-fn parse_session_name(text: &str) -> Option<String> {
+fn parse_session_name(text: &str) -> Result<String, String> {
 
     // TODO(tiagolam) Validate strings according to the spec
 
@@ -854,10 +2801,10 @@ fn parse_session_name(text: &str) -> Option<String> {
 
     debug!("session_name: {}", session_name);
 
-    Some(session_name)
+    Ok(session_name)
 }
 
-fn parse_information(text: &str) -> Option<String> {
+fn parse_information(text: &str) -> Result<String, String> {
 
     // TODO(tiagolam) Validate strings according to the spec
 
@@ -865,342 +2812,166 @@ fn parse_information(text: &str) -> Option<String> {
 
     debug!("information: {}", information);
 
-    Some(information)
+    Ok(information)
 }
 
-fn parse_connection(text: &str) -> Option<Connection> {
+fn parse_connection(text: &str) -> Result<Connection, String> {
     let parts = text.split(' ').collect::<Vec<&str>>();
     if parts.len() != 3 {
-        return None;
+        return Err("expected 3 fields".to_string());
     }
 
     let conn_addr = parts[2].to_string();
     let conn_addr = conn_addr.split('/').collect::<Vec<&str>>();
 
-    let ip_addr = FromStr::from_str(conn_addr[0]);
-
-    let mut is_mulcast_or_ipv6:bool = false;
-
-    match ip_addr {
-        Ok(value) => match value {
-            IpAddr::V4(x) => {
-                is_mulcast_or_ipv6 = x.is_multicast();
-                debug!("Ipv4 address")
-            }
-            IpAddr::V6(_) => {
-                is_mulcast_or_ipv6 = true;
-                debug!("Ipv6 address")
+    let addr_type = parse_field::<AddrType>(parts[1], "address type")?;
+    let address = parse_address(&addr_type, conn_addr[0])?;
+
+    // IPv4 multicast addresses carry a TTL, and multicast ones may carry a
+    // number of addresses. Unicast ones, and names, carry neither.
+    let mut ttl = None;
+    let mut nr_addrs = None;
+    match (address.ip(), conn_addr.len()) {
+        (_, 1) if !address.is_multicast() => {},
+        (Some(IpAddr::V4(_)), 1) => {
+            return Err("missing ttl of multicast address".to_string());
+        },
+        (Some(IpAddr::V4(_)), 2) | (Some(IpAddr::V4(_)), 3) if address.is_multicast() => {
+            ttl = Some(parse_field::<u8>(conn_addr[1], "ttl")?);
+            if conn_addr.len() == 3 {
+                nr_addrs = Some(parse_field::<u8>(conn_addr[2], "number of addresses")?);
             }
         },
-        Err(_) => return None,
-    }
-
-    let mut ttl:u8 = 0;
-    let mut nr_addrs:u8 = 0;
-    if is_mulcast_or_ipv6 && conn_addr.len() > 1 {
-        debug!("Malformed connection address");
-        return None;
-    } else if conn_addr.len() == 3 {
-        ttl = conn_addr[1].parse::<u8>().unwrap();
-        nr_addrs = conn_addr[2].parse::<u8>().unwrap();
-    } else if conn_addr.len() == 2 {
-        ttl = conn_addr[1].parse::<u8>().unwrap();
-    }
-
-    Some(Connection {
-        // TODO(tlam) Deal with errors instead of using unwrap
-        net_type: parts[0].parse::<NetType>().unwrap(),
-        addr_type: parts[1].parse::<AddrType>().unwrap(),
-        ip_address: ip_addr.unwrap(),
+        (Some(IpAddr::V6(_)), 1) => {},
+        (Some(IpAddr::V6(_)), 2) if address.is_multicast() => {
+            nr_addrs = Some(parse_field::<u8>(conn_addr[1], "number of addresses")?);
+        },
+        _ => return Err("malformed connection address".to_string()),
+    }
+
+    Ok(Connection {
+        net_type: parse_field::<NetType>(parts[0], "network type")?,
+        addr_type: addr_type,
+        ip_address: address,
         ttl: ttl,
         nr_addrs: nr_addrs,
     })
 }
 
-fn parse_timing(text: &str) -> Option<Timing> {
+fn parse_timing(text: &str) -> Result<Timing, String> {
     let parts = text.split(' ').collect::<Vec<&str>>();
     if parts.len() != 2 {
-        return None;
+        return Err("expected 2 fields".to_string());
     }
 
     // TODO(tlam) Verify this is NTP timestamps
-    let start_time:u64 = parts[0].parse::<u64>().unwrap();
-    let stop_time:u64 = parts[1].parse::<u64>().unwrap();
+    let start_time:u64 = parse_field::<u64>(parts[0], "start time")?;
+    let stop_time:u64 = parse_field::<u64>(parts[1], "stop time")?;
 
-    Some(Timing {
+    Ok(Timing {
         start_time: start_time,
         stop_time: stop_time,
     })
 }
 
-fn parse_attr(text: &str) -> Option<Attr> {
-    let parts = text.split(':').collect::<Vec<&str>>();
-
-    let result;
-    match parts.len() {
-        1 => {
-            result = Attr::from_str(parts[0], None)
-        },
-        2 => {
-            result = Attr::from_str(parts[0], Some(parts[1]))
-        },
-        _ => {
-            debug!("Invalid attribute");
-            result = Err(())
-        },
+fn parse_bandwidth(text: &str) -> Result<Bandwidth, String> {
+    let parts = text.splitn(2, ':').collect::<Vec<&str>>();
+    if parts.len() != 2 {
+        return Err("expected '<bwtype>:<bandwidth>'".to_string());
     }
 
-    result.ok()
+    Ok(Bandwidth {
+        bwtype: parse_field::<BandwidthType>(parts[0], "bandwidth type")?,
+        bandwidth: parse_field::<u64>(parts[1], "bandwidth")?,
+    })
 }
 
-fn parse_media(text: &str) -> Option<Media> {
+fn parse_repeat_time(text: &str) -> Result<RepeatTime, String> {
     let parts = text.split(' ').collect::<Vec<&str>>();
-    if parts.len() < 4 {
-        return None;
+    if parts.len() < 3 {
+        return Err("expected at least 3 fields".to_string());
     }
 
-    let fmt:Vec<String> = parts[3..].iter().map(|s| s.to_string()).collect();
-
-    if fmt.len() == 0 {
-        return None;
+    let mut offsets = vec![];
+    for offset in parts[2..].iter() {
+        offsets.push(offset.parse::<TypedTime>()?);
     }
 
-    debug!("Fmt items {:?}", fmt);
-
-    Some(Media {
-        media: parts[0].parse::<MediaType>().unwrap(),
-        port: parts[1].parse::<u16>().unwrap(),
-        proto: parts[2].parse::<MediaProto>().unwrap(),
-        fmt: fmt,
+    Ok(RepeatTime {
+        interval: parts[0].parse::<TypedTime>()?,
+        duration: parts[1].parse::<TypedTime>()?,
+        offsets: offsets,
     })
 }
 
-fn negotiate_media_stream(orig_media: MediaDescription, offer_media: &mut MediaDescription) -> bool {
-
-    if orig_media.media.media != offer_media.media.media {
-        debug!("Different media types [orig: {}, offer: {}", orig_media.media.media.to_string(), offer_media.media.media.to_string());
-        return false
+fn parse_time_zones(text: &str) -> Result<Vec<TimeZone>, String> {
+    let parts = text.split(' ').collect::<Vec<&str>>();
+    if parts.len() % 2 != 0 {
+        return Err("expected pairs of '<adjustment time> <offset>'".to_string());
     }
 
-    if offer_media.media.proto != MediaProto::RtpAvp {
-        debug!("Media proto {} not supported", offer_media.media.proto.to_string());
-        return false
+    let mut time_zones = vec![];
+    for pair in parts.chunks(2) {
+        time_zones.push(TimeZone {
+            adjustment_time: parse_field::<u64>(pair[0], "adjustment time")?,
+            offset: pair[1].parse::<TypedTime>()?,
+        });
     }
 
-    let found_match = true;
-    let offer_fmt =  offer_media.media.fmt.clone();
-    let result = offer_fmt.into_iter().filter(|x| orig_media.media.fmt.contains(x)).collect::<Vec<_>>();
-    //offer_media.media.fmt.retain(|x| !orig_media.media.fmt.contains(x));
+    Ok(time_zones)
+}
 
-    if result.len() == 0 {
-        debug!("Media {} and {} are not a match", orig_media.to_string(), offer_media.to_string());
-        return false
+fn parse_encryption_key(text: &str) -> Result<EncryptionKey, String> {
+    let parts = text.splitn(2, ':').collect::<Vec<&str>>();
+    if parts[0].is_empty() {
+        return Err("missing method".to_string());
     }
 
-    debug!("Media {} and {} are a match: {}", orig_media.to_string(), offer_media.to_string(), result.len());
-
-    offer_media.media.fmt = result;
-
-    let mut filtered_attrs = vec![];
-    let offer_fmts = offer_media.media.fmt.clone();
-    for fmt in offer_fmts {
-        let value = fmt.parse::<u32>().unwrap();
-
-        let mut orig_attrs = orig_media.attrs.clone();
-        for orig_attr in orig_attrs.drain(0..) {
-            match orig_attr {
-                Attr::RtpMap(RtpMapValue{payload_type: x, ..}) => {
-                    if value != x {
-                        debug!("Different media fmt [orig: {}, offer: {}]", value, x);
-                        continue;
-                    }
+    Ok(EncryptionKey {
+        method: parts[0].to_string(),
+        key: parts.get(1).map(|k| k.to_string()),
+    })
+}
 
-                    filtered_attrs.push(orig_attr);
-                },
-                Attr::Candidate(_) => {
-                    continue;
-                },
+fn parse_attr(text: &str) -> Result<Attr, String> {
+    // Values may contain ':' themselves, e.g. IPv6 candidates
+    let parts = text.splitn(2, ':').collect::<Vec<&str>>();
 
-                _ => {},
-            }
-        }
+    match parts.len() {
+        1 => {
+            Attr::from_str(parts[0], None)
+        },
+        _ => {
+            Attr::from_str(parts[0], Some(parts[1]))
+        },
     }
+}
 
-    let offer_attrs = filtered_attrs.clone();
-    let result = offer_attrs.into_iter().filter(|x| orig_media.attrs.contains(x)).collect::<Vec<_>>();
-
-    if result.len() == 0 {
-        debug!("attrs {:?} and {:?} are not a match", filtered_attrs, orig_media.attrs);
-        return false
+fn parse_media(text: &str) -> Result<Media, String> {
+    let parts = text.split(' ').collect::<Vec<&str>>();
+    if parts.len() < 4 {
+        return Err("expected at least 4 fields".to_string());
     }
 
-    debug!("Media is a match: {}", result[0].to_string());
+    let fmt:Vec<String> = parts[3..].iter().map(|s| s.to_string()).collect();
 
-    let mut offer_media_attrs = offer_media.attrs.clone();
-    offer_media.attrs = result;
-    // For each of the attributes present on the offer, negotiate, and put the
-    // result on the answer
+    debug!("Fmt items {:?}", fmt);
 
-    for offer_attr in &mut offer_media_attrs {
-        match *offer_attr {
-            Attr::SendOnly => {
-                offer_media.attrs.push(Attr::RecvOnly)
-            },
-            Attr::RecvOnly => {
-                offer_media.attrs.push(Attr::SendOnly)
-            },
-            Attr::SendRecv => {
-                offer_media.attrs.push(Attr::SendRecv)
-            },
-            Attr::Inactive => {
-                offer_media.attrs.push(Attr::Inactive)
-            },
-            Attr::PTime(ref x) => {
-                offer_media.attrs.push(Attr::PTime(x.clone()))
-            },
-            Attr::IceUfrag(_) => {
-                // Generate ufrag and pass
-                offer_media.attrs.push(Attr::IceUfrag(IceUfragValue {
-                    value: "Oyef7uvBlwafI3hT".to_string()
-                }));
-
-                offer_media.attrs.push(Attr::IcePwd(IcePwdValue {
-                    value: "T0teqPLNQQOf+5W+ls+P2p16".to_string()
-                }));
-            },
-            _ => {},
-        }
+    let port = parts[1].split('/').collect::<Vec<&str>>();
+    if port.len() > 2 {
+        return Err("malformed port".to_string());
     }
 
-    return found_match
-}
-
-fn set_media_stream(offer_media: &mut MediaDescription) {
-    let mut offer_media_attrs = offer_media.attrs.clone();
-    let mut final_attrs = vec![];
-    // For each of the attributes present on the offer, negotiate, and put the
-    // result on the answer
-    for offer_attr in &mut offer_media_attrs {
-        match *offer_attr {
-            Attr::SendOnly => {
-                final_attrs.push(Attr::RecvOnly)
-            },
-            Attr::RecvOnly => {
-                final_attrs.push(Attr::SendOnly)
-            },
-            Attr::SendRecv => {
-                final_attrs.push(Attr::SendRecv)
-            },
-            Attr::Inactive => {
-                final_attrs.push(Attr::Inactive)
-            },
-            Attr::PTime(ref x) => {
-                final_attrs.push(Attr::PTime(x.clone()))
-            },
-            Attr::IceUfrag(_) => {
-                // Generate ufrag and pass
-                final_attrs.push(Attr::IceUfrag(IceUfragValue {
-                    value: "Oyef7uvBlwafI3hT".to_string()
-                }));
-
-                final_attrs.push(Attr::IcePwd(IcePwdValue {
-                    value: "T0teqPLNQQOf+5W+ls+P2p16".to_string()
-                }));
-            }
-            Attr::RtpMap(ref x) => {
-
-                final_attrs.push(Attr::RtpMap(x.clone()));
-            },
-            Attr::FmtP(ref x) => {
-
-                final_attrs.push(Attr::FmtP(x.clone()));
-            },
-            _ => {},
-        }
+    let mut num_ports = None;
+    if port.len() == 2 {
+        num_ports = Some(parse_field::<u16>(port[1], "number of ports")?);
     }
-    offer_media.media.port = 9;
-
-    offer_media.attrs = final_attrs;
-}
-
 
-pub fn negotiate_with(sdp_orig: Option<&SessionDescription>, sdp_offer: &SessionDescription) -> SessionDescription {
-
-    // TODO Negotiation based on RFC#3264
-
-    // Construct a new SDP based on the negotiation
-    if sdp_orig.is_some() {
-        let mut sdp_answer = sdp_offer.clone();
-
-        debug!("Here1");
-        for answer_media in &mut sdp_answer.media {
-            let mut found_match = false;
-            for orig_media in sdp_orig.unwrap().media.iter() {
-                found_match = negotiate_media_stream(orig_media.clone(), answer_media);
-                if found_match {
-                    break
-                }
-            }
-
-            if !found_match {
-                answer_media.media.port = 9;
-            }
-        }
-
-        // Add "ice-lite" attribute
-        sdp_answer.attrs.push(Attr::IceLite);
-
-        debug!("Here2");
-        let mut filtered_attrs = vec![];
-        for answer_attr in sdp_answer.media[0].attrs.drain(0..) {
-            match answer_attr {
-                Attr::Candidate(_) => {
-                    continue;
-                },
-                Attr::IceUfrag(_) => {
-                    // Generate ufrag and pass
-                    filtered_attrs.push(Attr::IceUfrag(IceUfragValue {
-                        value: "Oyef7uvBlwafI3hT".to_string()
-                    }));
-                },
-                Attr::IcePwd(_) => {
-                    filtered_attrs.push(Attr::IcePwd(IcePwdValue {
-                        value: "T0teqPLNQQOf+5W+ls+P2p16".to_string()
-                    }));
-                },
-                _ => {
-                    filtered_attrs.push(answer_attr);
-                },
-            }
-        }
-        sdp_answer.media[0].attrs = filtered_attrs;
-
-        return sdp_answer
-    } else {
-        let mut sdp_answer = sdp_offer.clone();
-
-        for answer_media in &mut sdp_answer.media {
-            set_media_stream(answer_media);
-        }
-
-        // Add "ice-lite" attribute
-        sdp_answer.attrs.push(Attr::IceLite);
-
-        // TODO(tlam): Hack that only gets the candidate of the first m=
-        for answer_attr in &mut sdp_answer.media[0].attrs {
-            match *answer_attr {
-                Attr::Candidate(ref x) => {
-                    // Set connection
-                    let conn = sdp_answer.conn.as_mut().unwrap();
-                    conn.ip_address = x.ice_candidate.conn;
-
-                    break;
-                },
-                _ => {},
-            }
-
-        }
-
-        return sdp_answer
-    }
+    Ok(Media {
+        media: parse_field::<MediaType>(parts[0], "media type")?,
+        port: parse_field::<u16>(port[0], "port")?,
+        num_ports: num_ports,
+        proto: parse_field::<MediaProto>(parts[2], "protocol")?,
+        fmt: fmt,
+    })
 }
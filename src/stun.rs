@@ -0,0 +1,337 @@
+//! STUN messages (RFC 5389), as far as ICE connectivity checks need them.
+
+extern crate hmac;
+extern crate sha1;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use self::hmac::{Hmac, Mac};
+use self::sha1::Sha1;
+
+pub const MAGIC_COOKIE: u32 = 0x2112a442;
+
+pub const BINDING_REQUEST: u16 = 0x0001;
+pub const BINDING_SUCCESS: u16 = 0x0101;
+pub const BINDING_ERROR: u16 = 0x0111;
+
+pub const ATTR_USERNAME: u16 = 0x0006;
+pub const ATTR_MESSAGE_INTEGRITY: u16 = 0x0008;
+pub const ATTR_ERROR_CODE: u16 = 0x0009;
+pub const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+pub const ATTR_PRIORITY: u16 = 0x0024;
+pub const ATTR_USE_CANDIDATE: u16 = 0x0025;
+pub const ATTR_FINGERPRINT: u16 = 0x8028;
+pub const ATTR_ICE_CONTROLLED: u16 = 0x8029;
+pub const ATTR_ICE_CONTROLLING: u16 = 0x802a;
+
+const HEADER_LEN: usize = 20;
+const FINGERPRINT_XOR: u32 = 0x5354554e;
+
+/// Whether `packet` is a STUN message rather than RTP or DTLS, sharing the
+/// same port (RFC 7983, section 7).
+pub fn is_stun(packet: &[u8]) -> bool {
+    packet.len() >= HEADER_LEN && packet[0] < 2 && read_u32(packet, 4) == MAGIC_COOKIE
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub attr_type: u16,
+    pub value: Vec<u8>,
+    /// Where the attribute starts in the message it was parsed from.
+    offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub msg_type: u16,
+    pub transaction_id: [u8; 12],
+    pub attrs: Vec<Attribute>,
+}
+
+impl Message {
+    pub fn new(msg_type: u16, transaction_id: [u8; 12]) -> Message {
+        Message {
+            msg_type: msg_type,
+            transaction_id: transaction_id,
+            attrs: vec![],
+        }
+    }
+
+    pub fn parse(packet: &[u8]) -> Result<Message, String> {
+        if !is_stun(packet) {
+            return Err("not a STUN message".to_string())
+        }
+
+        let length = read_u16(packet, 2) as usize;
+        if length % 4 != 0 || HEADER_LEN + length != packet.len() {
+            return Err(format!("length {} doesn't match the {} bytes received", length, packet.len()))
+        }
+
+        let mut transaction_id = [0; 12];
+        transaction_id.copy_from_slice(&packet[8..HEADER_LEN]);
+
+        let mut attrs = vec![];
+        let mut offset = HEADER_LEN;
+        while offset < packet.len() {
+            if offset + 4 > packet.len() {
+                return Err("truncated attribute".to_string())
+            }
+
+            let attr_type = read_u16(packet, offset);
+            let attr_len = read_u16(packet, offset + 2) as usize;
+            let value_end = offset + 4 + attr_len;
+            if value_end > packet.len() {
+                return Err(format!("attribute {:#06x} overflows the message", attr_type))
+            }
+
+            attrs.push(Attribute {
+                attr_type: attr_type,
+                value: packet[offset + 4..value_end].to_vec(),
+                offset: offset,
+            });
+
+            // Values are padded to 4 bytes
+            offset = value_end + (4 - attr_len % 4) % 4;
+        }
+
+        Ok(Message {
+            msg_type: read_u16(packet, 0),
+            transaction_id: transaction_id,
+            attrs: attrs,
+        })
+    }
+
+    pub fn get(&self, attr_type: u16) -> Option<&[u8]> {
+        self.attrs.iter().find(|a| a.attr_type == attr_type).map(|a| a.value.as_slice())
+    }
+
+    pub fn has(&self, attr_type: u16) -> bool {
+        self.get(attr_type).is_some()
+    }
+
+    pub fn add(&mut self, attr_type: u16, value: Vec<u8>) {
+        self.attrs.push(Attribute {
+            attr_type: attr_type,
+            value: value,
+            offset: 0,
+        });
+    }
+
+    pub fn username(&self) -> Option<String> {
+        self.get(ATTR_USERNAME).and_then(|u| String::from_utf8(u.to_vec()).ok())
+    }
+
+    pub fn priority(&self) -> Option<u32> {
+        self.get(ATTR_PRIORITY).filter(|p| p.len() == 4).map(|p| read_u32(p, 0))
+    }
+
+    pub fn add_priority(&mut self, priority: u32) {
+        let mut value = vec![];
+        push_u32(&mut value, priority);
+        self.add(ATTR_PRIORITY, value);
+    }
+
+    /// Tie-breaker of an ICE-CONTROLLING or ICE-CONTROLLED attribute.
+    pub fn tie_breaker(&self, attr_type: u16) -> Option<u64> {
+        self.get(attr_type).filter(|v| v.len() == 8).map(|v| (read_u32(v, 0) as u64) << 32 | read_u32(v, 4) as u64)
+    }
+
+    pub fn add_tie_breaker(&mut self, attr_type: u16, tie_breaker: u64) {
+        let mut value = vec![];
+        push_u32(&mut value, (tie_breaker >> 32) as u32);
+        push_u32(&mut value, tie_breaker as u32);
+        self.add(attr_type, value);
+    }
+
+    pub fn xor_mapped_address(&self) -> Option<SocketAddr> {
+        self.get(ATTR_XOR_MAPPED_ADDRESS).and_then(|v| decode_xor_address(v, &self.transaction_id))
+    }
+
+    pub fn add_xor_mapped_address(&mut self, addr: SocketAddr) {
+        let value = encode_xor_address(addr, &self.transaction_id);
+        self.add(ATTR_XOR_MAPPED_ADDRESS, value);
+    }
+
+    /// Adds an ERROR-CODE, e.g. 401 "Unauthorized" (RFC 5389, section 15.6).
+    pub fn add_error_code(&mut self, code: u16, reason: &str) {
+        let mut value = vec![0, 0, (code / 100) as u8, (code % 100) as u8];
+        value.extend_from_slice(reason.as_bytes());
+        self.add(ATTR_ERROR_CODE, value);
+    }
+
+    pub fn error_code(&self) -> Option<u16> {
+        self.get(ATTR_ERROR_CODE).filter(|v| v.len() >= 4).map(|v| (v[2] & 0x07) as u16 * 100 + v[3] as u16)
+    }
+
+    /// The message on the wire, with a MESSAGE-INTEGRITY keyed with `key`
+    /// if set, and a FINGERPRINT if `fingerprint`.
+    pub fn encode(&self, key: Option<&[u8]>, fingerprint: bool) -> Vec<u8> {
+        let mut packet = vec![];
+        push_u16(&mut packet, self.msg_type);
+        push_u16(&mut packet, 0);
+        push_u32(&mut packet, MAGIC_COOKIE);
+        packet.extend_from_slice(&self.transaction_id);
+
+        for attr in self.attrs.iter() {
+            push_attr(&mut packet, attr.attr_type, &attr.value);
+        }
+
+        if let Some(key) = key {
+            // The length covers the MESSAGE-INTEGRITY being computed
+            set_length(&mut packet, 24);
+            let mut hmac = hmac_sha1(key);
+            hmac.input(&packet);
+            push_attr(&mut packet, ATTR_MESSAGE_INTEGRITY, &hmac.result().code());
+        }
+
+        if fingerprint {
+            set_length(&mut packet, 8);
+            let crc = crc32(&packet) ^ FINGERPRINT_XOR;
+            let mut value = vec![];
+            push_u32(&mut value, crc);
+            push_attr(&mut packet, ATTR_FINGERPRINT, &value);
+        }
+
+        set_length(&mut packet, 0);
+
+        packet
+    }
+}
+
+/// Checks the MESSAGE-INTEGRITY of `packet`, parsed into `message`, against
+/// `key` (RFC 5389, section 15.4). Attributes after it are not covered.
+pub fn check_integrity(packet: &[u8], message: &Message, key: &[u8]) -> bool {
+    let attr = match message.attrs.iter().find(|a| a.attr_type == ATTR_MESSAGE_INTEGRITY) {
+        Some(attr) if attr.value.len() == 20 => attr,
+        _ => return false,
+    };
+
+    let mut covered = packet[..attr.offset].to_vec();
+    let length = (attr.offset + 24 - HEADER_LEN) as u16;
+    covered[2] = (length >> 8) as u8;
+    covered[3] = length as u8;
+
+    // Compared in constant time, not to tell forgers how much is right
+    let mut hmac = hmac_sha1(key);
+    hmac.input(&covered);
+    hmac.verify(&attr.value).is_ok()
+}
+
+/// Checks the FINGERPRINT of `packet`, which has to be its last attribute
+/// (RFC 5389, section 15.5).
+pub fn check_fingerprint(packet: &[u8], message: &Message) -> bool {
+    match message.attrs.last() {
+        Some(attr) if attr.attr_type == ATTR_FINGERPRINT && attr.value.len() == 4 => {
+            crc32(&packet[..attr.offset]) ^ FINGERPRINT_XOR == read_u32(&attr.value, 0)
+        },
+        _ => false,
+    }
+}
+
+/// Key of the short-term credential mechanism, the password itself
+/// (RFC 5389, section 15.4).
+pub fn short_term_key(pwd: &str) -> Vec<u8> {
+    pwd.as_bytes().to_vec()
+}
+
+fn encode_xor_address(addr: SocketAddr, transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut value = vec![0];
+    let port = addr.port() ^ (MAGIC_COOKIE >> 16) as u16;
+
+    let mut mask = vec![];
+    push_u32(&mut mask, MAGIC_COOKIE);
+    mask.extend_from_slice(transaction_id);
+
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            value.push(0x01);
+            push_u16(&mut value, port);
+            value.extend(ip.octets().iter().zip(mask.iter()).map(|(a, m)| a ^ m));
+        },
+        IpAddr::V6(ip) => {
+            value.push(0x02);
+            push_u16(&mut value, port);
+            value.extend(ip.octets().iter().zip(mask.iter()).map(|(a, m)| a ^ m));
+        },
+    }
+
+    value
+}
+
+fn decode_xor_address(value: &[u8], transaction_id: &[u8; 12]) -> Option<SocketAddr> {
+    if value.len() < 4 {
+        return None
+    }
+
+    let port = read_u16(value, 2) ^ (MAGIC_COOKIE >> 16) as u16;
+
+    let mut mask = vec![];
+    push_u32(&mut mask, MAGIC_COOKIE);
+    mask.extend_from_slice(transaction_id);
+    let octets = value[4..].iter().zip(mask.iter()).map(|(a, m)| a ^ m).collect::<Vec<u8>>();
+
+    let ip = match (value[1], octets.len()) {
+        (0x01, 4) => IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])),
+        (0x02, 16) => {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&octets);
+            IpAddr::V6(Ipv6Addr::from(ip))
+        },
+        _ => return None,
+    };
+
+    Some(SocketAddr::new(ip, port))
+}
+
+fn push_attr(packet: &mut Vec<u8>, attr_type: u16, value: &[u8]) {
+    push_u16(packet, attr_type);
+    push_u16(packet, value.len() as u16);
+    packet.extend_from_slice(value);
+    for _ in 0..(4 - value.len() % 4) % 4 {
+        packet.push(0);
+    }
+}
+
+/// Sets the length of the message so far, plus `extra` bytes to come.
+fn set_length(packet: &mut Vec<u8>, extra: usize) {
+    let length = (packet.len() - HEADER_LEN + extra) as u16;
+    packet[2] = (length >> 8) as u8;
+    packet[3] = length as u8;
+}
+
+fn push_u16(packet: &mut Vec<u8>, value: u16) {
+    packet.push((value >> 8) as u8);
+    packet.push(value as u8);
+}
+
+fn push_u32(packet: &mut Vec<u8>, value: u32) {
+    push_u16(packet, (value >> 16) as u16);
+    push_u16(packet, value as u16);
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    (data[offset] as u16) << 8 | data[offset + 1] as u16
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    (read_u16(data, offset) as u32) << 16 | read_u16(data, offset + 2) as u32
+}
+
+/// CRC-32 of ITU-T V.42, as FINGERPRINT uses.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// HMAC-SHA1 (RFC 2104), which MESSAGE-INTEGRITY carries.
+fn hmac_sha1(key: &[u8]) -> Hmac<Sha1> {
+    // HMAC takes keys of any length
+    Hmac::<Sha1>::new_varkey(key).unwrap()
+}
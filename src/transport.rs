@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ice;
+use stun;
+
+/// How often the workers look for the transport being closed.
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// Size of the largest UDP datagram.
+const MAX_PACKET_SIZE: usize = 65536;
+
+/// Sockets of the components of a stream, as bound for its candidates: one
+/// per family, media to a peer going out on that of its family.
+/// STUN received on them goes to the ICE agent, which answers checks and
+/// learns the peer's, while the media is relayed to and from the media
+/// session over loopback: rir reads the sockets it is given itself.
+pub struct Transport {
+    stream_id: String,
+    sockets: HashMap<u16, Vec<Arc<UdpSocket>>>,
+    // Where media of each component is sent, as picked by ICE
+    peers: Arc<Mutex<HashMap<u16, SocketAddr>>>,
+    // Sockets relaying RTP and RTCP, and the media session's they talk to
+    relays: Vec<(Arc<UdpSocket>, SocketAddr)>,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Sockets the media session is to read and write its RTP and RTCP on,
+/// relayed by the transport.
+pub struct MediaSockets {
+    pub rtp: UdpSocket,
+    pub rtcp: UdpSocket,
+}
+
+impl Transport {
+    /// Starts relaying the media of `stream_id` on the sockets of its
+    /// components, `rtcp_conns` being empty when RTCP is multiplexed with
+    /// RTP.
+    pub fn open(stream_id: &str, ice: Arc<Mutex<ice::Agent>>, rtp_conns: Vec<UdpSocket>, rtcp_conns: Vec<UdpSocket>) -> Result<(Transport, MediaSockets), String> {
+        if rtp_conns.is_empty() {
+            return Err(format!("no RTP socket for stream {}", stream_id))
+        }
+
+        let media = MediaSockets {
+            rtp: bind_loopback()?,
+            rtcp: bind_loopback()?,
+        };
+        let rtp_relay = Arc::new(bind_loopback()?);
+        let rtcp_relay = Arc::new(bind_loopback()?);
+
+        let mut sockets = HashMap::new();
+        sockets.insert(ice::RTP_COMPONENT_ID, rtp_conns.into_iter().map(Arc::new).collect::<Vec<_>>());
+        if !rtcp_conns.is_empty() {
+            sockets.insert(ice::RTCP_COMPONENT_ID, rtcp_conns.into_iter().map(Arc::new).collect());
+        }
+        // Workers wake up now and then to find out whether to stop
+        for socket in sockets.values().flat_map(|sockets| sockets.iter()).chain([&rtp_relay, &rtcp_relay].iter().cloned()) {
+            socket.set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL_MS))).map_err(|e| e.to_string())?;
+        }
+
+        let mut transport = Transport {
+            stream_id: stream_id.to_string(),
+            sockets: sockets,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            relays: vec![],
+            stop: Arc::new(AtomicBool::new(false)),
+            workers: vec![],
+        };
+
+        let media_addrs = (local_addr(&media.rtp)?, local_addr(&media.rtcp)?);
+        transport.relays = vec![(rtp_relay.clone(), media_addrs.0), (rtcp_relay.clone(), media_addrs.1)];
+        let rtcp_component_id = if transport.sockets.contains_key(&ice::RTCP_COMPONENT_ID) {
+            ice::RTCP_COMPONENT_ID
+        } else {
+            ice::RTP_COMPONENT_ID
+        };
+
+        // Packets received from the peer, RTCP possibly multiplexed on the
+        // RTP component
+        let muxed = rtcp_component_id == ice::RTP_COMPONENT_ID;
+        let component_sockets = transport.sockets.iter()
+            .flat_map(|(&component_id, sockets)| sockets.iter().map(move |socket| (component_id, socket.clone())))
+            .collect::<Vec<_>>();
+        for (component_id, socket) in component_sockets {
+            let (rtp_relay, rtcp_relay) = (rtp_relay.clone(), rtcp_relay.clone());
+            let stream_id = transport.stream_id.clone();
+            let ice = ice.clone();
+
+            transport.spawn(socket.clone(), move |packet, source| {
+                if stun::is_stun(packet) {
+                    let local_port = socket.local_addr().map(|addr| addr.port()).unwrap_or(0);
+                    let response = ice.lock().unwrap().handle_stun(&stream_id, &component_id, local_port, packet, source);
+                    if let Some(response) = response {
+                        if let Err(e) = socket.send_to(&response, source) {
+                            debug!("Failed to answer check from {}: {}", source, e);
+                        }
+                    }
+                    return
+                }
+
+                if component_id == ice::RTCP_COMPONENT_ID || (muxed && is_rtcp(packet)) {
+                    let _ = rtcp_relay.send_to(packet, media_addrs.1);
+                } else {
+                    let _ = rtp_relay.send_to(packet, media_addrs.0);
+                }
+            });
+        }
+
+        // Packets sent by the media session
+        for &(component_id, ref relay, media_addr) in [(ice::RTP_COMPONENT_ID, rtp_relay.clone(), media_addrs.0), (rtcp_component_id, rtcp_relay.clone(), media_addrs.1)].iter() {
+            let sockets = transport.sockets[&component_id].clone();
+            let peers = transport.peers.clone();
+
+            transport.spawn(relay.clone(), move |packet, source| {
+                if source != media_addr {
+                    return
+                }
+
+                let peer = peers.lock().unwrap().get(&component_id).cloned();
+                if let Some(peer) = peer {
+                    match reaching(&sockets, peer) {
+                        Some(socket) => if let Err(e) = socket.send_to(packet, peer) {
+                            debug!("Failed to send media to {}: {}", peer, e);
+                        },
+                        None => debug!("No socket reaching {}", peer),
+                    }
+                }
+            });
+        }
+
+        Ok((transport, media))
+    }
+
+    /// Addresses the media session sends its RTP and RTCP to.
+    pub fn relay_addrs(&self) -> (SocketAddr, SocketAddr) {
+        let addr = |i: usize| self.relays[i].0.local_addr().unwrap();
+
+        (addr(0), addr(1))
+    }
+
+    /// Sends the media of a component to `peer` from now on.
+    pub fn set_peer(&self, component_id: u16, peer: SocketAddr) {
+        let component_id = self.route(component_id);
+
+        debug!("Sending component {} of stream {} to {}", component_id, self.stream_id, peer);
+        self.peers.lock().unwrap().insert(component_id, peer);
+    }
+
+    /// Sends a check of the agent on the socket of its component.
+    pub fn send(&self, transmit: &ice::Transmit) {
+        let destination = transmit.destination;
+
+        match reaching(&self.sockets[&self.route(transmit.component_id)], destination) {
+            Some(socket) => if let Err(e) = socket.send_to(&transmit.packet, destination) {
+                debug!("Failed to send check to {}: {}", destination, e);
+            },
+            None => debug!("No socket reaching {}", destination),
+        }
+    }
+
+    /// Component whose sockets carry `component_id`, which may be
+    /// multiplexed on the RTP one.
+    fn route(&self, component_id: u16) -> u16 {
+        if self.sockets.contains_key(&component_id) { component_id } else { ice::RTP_COMPONENT_ID }
+    }
+
+    /// Stops relaying, closing the sockets once the workers are done. An
+    /// empty datagram wakes up whoever reads the media session.
+    pub fn close(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        for (relay, media_addr) in self.relays.drain(..) {
+            let _ = relay.send_to(&[], media_addr);
+        }
+        self.sockets.clear();
+    }
+
+    /// Runs `f` on each packet received on `socket`, until closed.
+    fn spawn<F>(&mut self, socket: Arc<UdpSocket>, f: F) where F: Fn(&[u8], SocketAddr) + Send + 'static {
+        let stop = self.stop.clone();
+
+        self.workers.push(thread::spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+
+            while !stop.load(Ordering::SeqCst) {
+                match socket.recv_from(&mut buf) {
+                    Ok((size, source)) => f(&buf[..size], source),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {},
+                    Err(e) => debug!("Failed to receive on {:?}: {}", socket.local_addr(), e),
+                }
+            }
+        }));
+    }
+}
+
+impl Drop for Transport {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Whether a packet received on a multiplexed transport is RTCP: RTCP packet
+/// types 192-223 show as payload types 64-95 (RFC 5761, section 4).
+pub fn is_rtcp(packet: &[u8]) -> bool {
+    packet.len() >= 2 && packet[1] >= 192 && packet[1] <= 223
+}
+
+/// Sends the checks of a full agent as it hands them out, paced by Ta, on
+/// the transports of its streams.
+pub struct Checker {
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Checker {
+    pub fn start(ice: Arc<Mutex<ice::Agent>>, transports: Arc<RwLock<HashMap<String, Transport>>>) -> Checker {
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let stop = stop.clone();
+
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    let (transmit, ta) = {
+                        let mut ice = ice.lock().unwrap();
+                        (ice.poll(Instant::now()), ice.ta())
+                    };
+
+                    match transmit {
+                        Some(transmit) => match transports.read().unwrap().get(&transmit.stream_id) {
+                            Some(transport) => transport.send(&transmit),
+                            None => debug!("No transport for the check of stream {}", transmit.stream_id),
+                        },
+                        None => thread::sleep(ta),
+                    }
+                }
+            })
+        };
+
+        Checker {
+            stop: stop,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl Drop for Checker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Socket, amongst those of a component, of the family of `addr`.
+fn reaching(sockets: &[Arc<UdpSocket>], addr: SocketAddr) -> Option<&Arc<UdpSocket>> {
+    sockets.iter().find(|socket| socket.local_addr().map(|local| local.is_ipv6() == addr.is_ipv6()).unwrap_or(false))
+}
+
+fn bind_loopback() -> Result<UdpSocket, String> {
+    UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0)).map_err(|e| e.to_string())
+}
+
+fn local_addr(socket: &UdpSocket) -> Result<SocketAddr, String> {
+    socket.local_addr().map_err(|e| e.to_string())
+}
@@ -0,0 +1,694 @@
+extern crate hibrido;
+
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hibrido::sdp::{Address, CandidateValue};
+use hibrido::ice;
+use hibrido::mdns;
+use hibrido::stun;
+
+fn candidate(value: &str) -> ice::Candidate {
+    value.parse::<CandidateValue>().unwrap().ice_candidate
+}
+
+#[test]
+fn test_resolve() {
+    // Addresses are kept as they are
+    let host = candidate("1 1 udp 2122260223 2001:db8::2 46244 typ host");
+    let resolved = host.resolve();
+    assert_eq!(resolved.len(), 1);
+    assert_eq!(resolved[0].socket_addr(), Some("[2001:db8::2]:46244".parse().unwrap()));
+
+    // Names become one candidate per address
+    let named = candidate("1 1 udp 2122260223 localhost 46244 typ host");
+    let resolved = named.resolve();
+    assert!(!resolved.is_empty());
+    assert!(resolved.iter().all(|c| c.conn.ip().map_or(false, |ip| ip.is_loopback()) && c.port == 46244));
+
+    // Unless they can't be found
+    assert!(candidate("1 1 udp 2122260223 host.invalid 46244 typ host").resolve().is_empty());
+    assert!(candidate("1 1 udp 2122260223 4b30a4a7-d5f3-46c0-b457-4d7d9b0b3a19.local 46244 typ host").resolve().is_empty());
+}
+
+#[test]
+fn test_dns_candidate() {
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    let stream_id = agent.add_stream(1);
+
+    // Names are left to the caller to look up
+    agent.add_offer_candidate(&stream_id, &1, candidate("1 1 udp 2122260223 localhost 46244 typ host"));
+    assert!(agent.get_offer_candidates(&stream_id, &1).unwrap().is_empty());
+
+    let mut lookups = agent.take_dns_lookups();
+    assert_eq!(lookups.len(), 1);
+    assert!(agent.take_dns_lookups().is_empty());
+    let (lookup_stream, component_id, named) = lookups.remove(0);
+    assert_eq!((lookup_stream.as_str(), component_id), (stream_id.as_str(), 1));
+
+    // Which offers the addresses found
+    let resolved = named.resolve();
+    agent.add_resolved_candidates(&stream_id, &1, &named, resolved.clone());
+    let offered = agent.get_offer_candidates(&stream_id, &1).unwrap();
+    assert_eq!(offered.len(), resolved.len());
+    assert!(offered.iter().all(|c| c.conn.ip().map_or(false, |ip| ip.is_loopback())));
+}
+
+#[test]
+fn test_address_family() {
+    let v4 = Address::Ip("192.0.2.1".parse::<IpAddr>().unwrap());
+    let v6 = Address::Ip("2001:db8::1".parse::<IpAddr>().unwrap());
+    let name = Address::Fqdn("host.example.com".to_string());
+
+    assert!(ice::is_same_family(&v4, &v4));
+    assert!(ice::is_same_family(&v6, &v6));
+    assert!(!ice::is_same_family(&v4, &v6));
+    assert!(!ice::is_same_family(&v4, &name));
+
+    // IPv6 sockets see IPv4 peers through mapped addresses
+    let addr = "192.0.2.1:5000".parse::<SocketAddr>().unwrap();
+    let mapped = ice::map_addr(addr);
+    assert_eq!(mapped, "[::ffff:192.0.2.1]:5000".parse().unwrap());
+    assert_eq!(ice::unmap_addr(mapped), addr);
+
+    let v6_addr = "[2001:db8::1]:5000".parse::<SocketAddr>().unwrap();
+    assert_eq!(ice::map_addr(v6_addr), v6_addr);
+    assert_eq!(ice::unmap_addr(v6_addr), v6_addr);
+}
+
+const MDNS_NAME: &'static str = "4b30a4a7-d5f3-46c0-b457-4d7d9b0b3a19.local";
+
+struct NoHandler;
+
+impl ice::Handler for NoHandler {
+    fn handle_callback(&mut self, _stream_id: &str, _candidate: ice::Candidate) {}
+}
+
+fn encode_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+/// Response with an AAAA record of another name, then an A record of
+/// `name`, compressed against the first.
+fn mdns_response(name: &str, ip: [u8; 4]) -> Vec<u8> {
+    let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+    encode_name(&mut packet, "other.local");
+    packet.extend_from_slice(&[0, 28, 0x80, 1, 0, 0, 0, 120, 0, 16]);
+    packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    // The ".local" of the first name is at offset 18
+    let label = name.split('.').next().unwrap();
+    packet.push(label.len() as u8);
+    packet.extend_from_slice(label.as_bytes());
+    packet.extend_from_slice(&[0xc0, 18]);
+    packet.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4]);
+    packet.extend_from_slice(&ip);
+
+    packet
+}
+
+/// Responder on loopback, answering the first query it gets.
+fn mdns_responder(ip: [u8; 4]) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 1500];
+        let (size, from) = socket.recv_from(&mut buffer).unwrap();
+        // Queries carry no answers
+        assert!(mdns::parse_response(&buffer[..size], MDNS_NAME).is_none());
+        socket.send_to(&mdns_response(MDNS_NAME, ip), from).unwrap();
+    });
+
+    addr
+}
+
+#[test]
+fn test_mdns_response() {
+    let response = mdns_response(MDNS_NAME, [192, 168, 1, 23]);
+    assert_eq!(mdns::parse_response(&response, MDNS_NAME), Some("192.168.1.23".parse().unwrap()));
+    assert_eq!(mdns::parse_response(&response, &MDNS_NAME.to_uppercase()), Some("192.168.1.23".parse().unwrap()));
+    assert_eq!(mdns::parse_response(&response, "other.local"), Some("fe80::1".parse().unwrap()));
+    assert_eq!(mdns::parse_response(&response, "missing.local"), None);
+    assert_eq!(mdns::parse_response(&response[..response.len() - 2], MDNS_NAME), None);
+
+    assert!(mdns::is_mdns_name(MDNS_NAME));
+    assert!(mdns::is_mdns_name("host.LOCAL."));
+    assert!(!mdns::is_mdns_name("host.example.com"));
+}
+
+#[test]
+fn test_mdns_candidate() {
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_mdns_querier(mdns::Querier::with_group(mdns_responder([192, 168, 1, 23]), Duration::from_secs(2)));
+    let stream_id = agent.add_stream(1);
+
+    let value = format!("1 1 udp 2122194687 {} 46244 typ host", MDNS_NAME);
+    agent.add_offer_candidate(&stream_id, &1, candidate(&value));
+    agent.add_offer_candidate(&stream_id, &1, candidate(&value.replace(MDNS_NAME, &MDNS_NAME.to_uppercase())));
+    assert!(agent.get_offer_candidates(&stream_id, &1).unwrap().is_empty());
+
+    // The name is looked up once, by the caller
+    let (querier, names) = agent.take_mdns_lookups();
+    assert_eq!(names, vec![MDNS_NAME.to_string()]);
+    assert!(agent.take_mdns_lookups().1.is_empty());
+    agent.add_mdns_address(&names[0], querier.resolve(&names[0]));
+
+    let offered = agent.get_offer_candidates(&stream_id, &1).unwrap();
+    assert_eq!(offered.len(), 1);
+    assert_eq!(offered[0].socket_addr(), Some("192.168.1.23:46244".parse().unwrap()));
+
+    // And its address known from then on
+    let other_stream = agent.add_stream(1);
+    agent.add_offer_candidate(&other_stream, &1, candidate(&value));
+    assert_eq!(agent.get_offer_candidates(&other_stream, &1).unwrap().len(), 1);
+    assert!(agent.take_mdns_lookups().1.is_empty());
+}
+
+#[test]
+fn test_mdns_candidate_prflx() {
+    // No one answers, the candidate is learnt from the peer's checks
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_mdns_querier(mdns::Querier::with_group(silent.local_addr().unwrap(), Duration::from_millis(50)));
+    let stream_id = agent.add_stream(1);
+
+    let value = format!("1 1 udp 2122194687 {} 46244 typ host", MDNS_NAME);
+    agent.add_offer_candidate(&stream_id, &1, candidate(&value));
+    assert!(agent.get_offer_candidates(&stream_id, &1).unwrap().is_empty());
+    let (querier, names) = agent.take_mdns_lookups();
+    agent.add_mdns_address(&names[0], querier.resolve(&names[0]));
+    assert!(agent.get_offer_candidates(&stream_id, &1).unwrap().is_empty());
+
+    agent.add_pair_candidate(&stream_id, &1, 6000, "192.168.1.23:46244".parse().unwrap());
+
+    let offered = agent.get_offer_candidates(&stream_id, &1).unwrap();
+    assert_eq!(offered.len(), 1);
+    assert_eq!(offered[0].socket_addr(), Some("192.168.1.23:46244".parse().unwrap()));
+    assert_eq!(offered[0].candidate_type.to_string(), "prflx");
+    assert_eq!(offered[0].priority, 2122194687);
+}
+
+// Sample request of RFC 5769, section 2.1
+const SAMPLE_REQUEST: &'static str = "\
+    000100582112a442b7e7a701bc34d686fa87dfae802200105354554e207465737420636c69656e74\
+    002400046e0001ff80290008932ff9b151263b36000600096576746a3a68367659202020\
+    000800149aeaa70cbfd8cb56781ef2b5b2d3f249c1b571a280280004e57a3bcf";
+const SAMPLE_PWD: &'static str = "VOkJxbRl1RmTxUk/WvJxBt";
+
+// Sample IPv4 response of RFC 5769, section 2.2
+const SAMPLE_RESPONSE: &'static str = "\
+    0101003c2112a442b7e7a701bc34d686fa87dfae8022000b7465737420766563746f7220\
+    002000080001a147e112a643000800142b91f599fd9e90c38c7489f92af9ba53f06be7d7\
+    80280004c07d4c96";
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    let hex: String = hex.split_whitespace().collect();
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn test_stun_sample_request() {
+    let packet = from_hex(SAMPLE_REQUEST);
+    assert!(stun::is_stun(&packet));
+
+    let request = stun::Message::parse(&packet).unwrap();
+    assert_eq!(request.msg_type, stun::BINDING_REQUEST);
+    assert_eq!(request.username(), Some("evtj:h6vY".to_string()));
+    assert_eq!(request.priority(), Some(0x6e0001ff));
+    assert!(stun::check_fingerprint(&packet, &request));
+    assert!(stun::check_integrity(&packet, &request, &stun::short_term_key(SAMPLE_PWD)));
+    assert!(!stun::check_integrity(&packet, &request, &stun::short_term_key("wrong")));
+
+    // Any change breaks the fingerprint
+    let mut tampered = packet.clone();
+    tampered[30] ^= 1;
+    assert!(!stun::check_fingerprint(&tampered, &stun::Message::parse(&tampered).unwrap()));
+}
+
+#[test]
+fn test_stun_sample_response() {
+    let packet = from_hex(SAMPLE_RESPONSE);
+    let response = stun::Message::parse(&packet).unwrap();
+    assert_eq!(response.msg_type, stun::BINDING_SUCCESS);
+    assert_eq!(response.xor_mapped_address(), Some("192.0.2.1:32853".parse().unwrap()));
+    assert!(stun::check_fingerprint(&packet, &response));
+    assert!(stun::check_integrity(&packet, &response, &stun::short_term_key(SAMPLE_PWD)));
+}
+
+#[test]
+fn test_stun_round_trip() {
+    let mut message = stun::Message::new(stun::BINDING_SUCCESS, [7; 12]);
+    message.add_xor_mapped_address("[2001:db8::1]:32853".parse().unwrap());
+    let packet = message.encode(Some(b"key"), true);
+
+    let parsed = stun::Message::parse(&packet).unwrap();
+    assert_eq!(parsed.transaction_id, [7; 12]);
+    assert_eq!(parsed.xor_mapped_address(), Some("[2001:db8::1]:32853".parse().unwrap()));
+    assert!(stun::check_integrity(&packet, &parsed, b"key"));
+    assert!(stun::check_fingerprint(&packet, &parsed));
+
+    // The last byte of MESSAGE-INTEGRITY, before FINGERPRINT, counts too
+    let mut forged = packet.clone();
+    let last = forged.len() - 9;
+    forged[last] ^= 1;
+    assert!(!stun::check_integrity(&forged, &stun::Message::parse(&forged).unwrap(), b"key"));
+
+    assert!(stun::Message::parse(&packet[..packet.len() - 1]).is_err());
+    assert!(!stun::is_stun(&[0x80, 0x00, 0x00, 0x00]));
+}
+
+fn binding_request(username: &str, pwd: &str, use_candidate: bool) -> Vec<u8> {
+    let mut request = stun::Message::new(stun::BINDING_REQUEST, [1; 12]);
+    request.add(stun::ATTR_USERNAME, username.as_bytes().to_vec());
+    request.add(stun::ATTR_PRIORITY, vec![0x6e, 0x00, 0x01, 0xff]);
+    request.add(stun::ATTR_ICE_CONTROLLING, vec![0; 8]);
+    if use_candidate {
+        request.add(stun::ATTR_USE_CANDIDATE, vec![]);
+    }
+
+    request.encode(Some(&stun::short_term_key(pwd)), true)
+}
+
+#[test]
+fn test_stun_responder() {
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_remote_credentials(ice::Credentials { ufrag: "peer".to_string(), pwd: "peerpassword".to_string() });
+    let stream_id = agent.add_stream(1);
+    let local = agent.local_credentials().clone();
+    let username = format!("{}:peer", local.ufrag);
+    let source: SocketAddr = "192.0.2.1:32853".parse().unwrap();
+
+    // Authenticated, answered with the source address and learnt as prflx
+    let request = binding_request(&username, &local.pwd, false);
+    let packet = agent.handle_stun(&stream_id, &1, 6000, &request, source).unwrap();
+    let response = stun::Message::parse(&packet).unwrap();
+    assert_eq!(response.msg_type, stun::BINDING_SUCCESS);
+    assert_eq!(response.transaction_id, [1; 12]);
+    assert_eq!(response.xor_mapped_address(), Some(source));
+    assert!(stun::check_integrity(&packet, &response, &stun::short_term_key(&local.pwd)));
+    assert!(stun::check_fingerprint(&packet, &response));
+
+    let offered = agent.get_offer_candidates(&stream_id, &1).unwrap();
+    assert_eq!(offered.len(), 1);
+    assert_eq!(offered[0].socket_addr(), Some(source));
+    assert_eq!(offered[0].candidate_type.to_string(), "prflx");
+    assert_eq!(offered[0].priority, 0x6e0001ff);
+
+    // Wrong password or username
+    for &(username, pwd) in [(username.as_str(), "wrongpassword"), ("other:peer", local.pwd.as_str()),
+                              (&format!("{}:other", local.ufrag), local.pwd.as_str())].iter() {
+        let request = binding_request(username, pwd, true);
+        let response = stun::Message::parse(&agent.handle_stun(&stream_id, &1, 6000, &request, source).unwrap()).unwrap();
+        assert_eq!(response.msg_type, stun::BINDING_ERROR);
+        assert_eq!(response.error_code(), Some(401));
+    }
+
+    // No credentials at all
+    let mut request = stun::Message::new(stun::BINDING_REQUEST, [2; 12]);
+    request.add(stun::ATTR_PRIORITY, vec![0, 0, 0, 1]);
+    let packet = agent.handle_stun(&stream_id, &1, 6000, &request.encode(None, true), source).unwrap();
+    assert_eq!(stun::Message::parse(&packet).unwrap().error_code(), Some(400));
+
+    // Nor are checks authenticated before the peer's credentials are known
+    let mut unknown_peer = ice::Agent::new(Box::new(NoHandler));
+    let other_stream = unknown_peer.add_stream(1);
+    let other_local = unknown_peer.local_credentials().clone();
+    let request = binding_request(&format!("{}:peer", other_local.ufrag), &other_local.pwd, true);
+    let packet = unknown_peer.handle_stun(&other_stream, &1, 6000, &request, source).unwrap();
+    assert_eq!(stun::Message::parse(&packet).unwrap().error_code(), Some(401));
+    assert!(unknown_peer.get_offer_candidates(&other_stream, &1).map_or(true, |c| c.is_empty()));
+
+    // Bad fingerprints and non-STUN packets are dropped
+    let mut request = binding_request(&username, &local.pwd, true);
+    let len = request.len();
+    request[len - 1] ^= 1;
+    assert!(agent.handle_stun(&stream_id, &1, 6000, &request, source).is_none());
+    assert!(agent.handle_stun(&stream_id, &1, 6000, &[0x80, 0x60, 0, 1], source).is_none());
+}
+
+struct NominationHandler(::std::sync::mpsc::Sender<ice::Candidate>);
+
+impl ice::Handler for NominationHandler {
+    fn handle_callback(&mut self, _stream_id: &str, candidate: ice::Candidate) {
+        self.0.send(candidate).unwrap();
+    }
+}
+
+#[test]
+fn test_stun_nomination() {
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_remote_credentials(ice::Credentials { ufrag: "peer".to_string(), pwd: "peerpassword".to_string() });
+    let stream_id = agent.add_stream(1);
+    agent.add_offer_candidate(&stream_id, &1, candidate("1 1 udp 2122260223 192.0.2.1 32853 typ host"));
+    agent.gather_candidates(&stream_id, &1).unwrap();
+    agent.start(Box::new(NominationHandler(tx)));
+    let port = agent.get_stream_candidates(&stream_id, &1).unwrap()[0].port;
+
+    let local = agent.local_credentials().clone();
+    let username = format!("{}:peer", local.ufrag);
+    let source: SocketAddr = "192.0.2.1:32853".parse().unwrap();
+
+    // Checks without USE-CANDIDATE, or unauthenticated, don't nominate
+    let request = binding_request(&username, &local.pwd, false);
+    agent.handle_stun(&stream_id, &1, port, &request, source).unwrap();
+    let request = binding_request(&username, "wrongpassword", true);
+    agent.handle_stun(&stream_id, &1, port, &request, source).unwrap();
+    assert!(rx.try_recv().is_err());
+
+    let request = binding_request(&username, &local.pwd, true);
+    agent.handle_stun(&stream_id, &1, port, &request, source).unwrap();
+    assert_eq!(rx.try_recv().unwrap().socket_addr(), Some(source));
+}
+
+#[test]
+fn test_pair_priority() {
+    assert_eq!(ice::pair_priority(100, 100), (100 << 32) + 200);
+    // The controlling agent's candidate breaks ties
+    assert_eq!(ice::pair_priority(200, 100), (100 << 32) + 400 + 1);
+    assert_eq!(ice::pair_priority(100, 200), (100 << 32) + 400);
+    assert!(ice::pair_priority(2130706431, 1694498815) > ice::pair_priority(1694498815, 16777215));
+}
+
+/// Full agent with a single component stream, and its host candidates.
+fn full_agent(role: ice::Role) -> (ice::Agent, String, ::std::sync::mpsc::Receiver<ice::Candidate>) {
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_lite(false);
+    agent.set_role(role);
+    let stream_id = agent.add_stream(1);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+    agent.start(Box::new(NominationHandler(tx)));
+
+    (agent, stream_id, rx)
+}
+
+/// Offers the candidates and credentials of each agent to the other.
+fn exchange(a: &mut ice::Agent, a_stream: &str, b: &mut ice::Agent, b_stream: &str) {
+    a.set_remote_credentials(b.local_credentials().clone());
+    b.set_remote_credentials(a.local_credentials().clone());
+
+    for candidate in b.get_stream_candidates(b_stream, &1).unwrap().clone() {
+        a.add_offer_candidate(a_stream, &1, candidate);
+    }
+    for candidate in a.get_stream_candidates(a_stream, &1).unwrap().clone() {
+        b.add_offer_candidate(b_stream, &1, candidate);
+    }
+}
+
+/// Sends the next check of `from`, if any, to `to`, and its response back.
+fn deliver_check(from: &mut ice::Agent, to: &mut ice::Agent, to_stream: &str, now: Instant) {
+    if let Some(transmit) = from.poll(now) {
+        let response = to.handle_stun(to_stream, &1, transmit.destination.port(), &transmit.packet, transmit.local_addr);
+        if let Some(response) = response {
+            assert!(from.handle_stun(&transmit.stream_id, &1, transmit.local_addr.port(), &response, transmit.destination).is_none());
+        }
+    }
+}
+
+/// Runs the checks of both agents, delivering each packet to the other one,
+/// until both have selected a pair.
+fn run_checks(a: &mut ice::Agent, a_stream: &str, b: &mut ice::Agent, b_stream: &str) {
+    let mut now = Instant::now();
+    for _ in 0..1000 {
+        now += Duration::from_millis(10);
+
+        deliver_check(a, b, b_stream, now);
+        deliver_check(b, a, a_stream, now);
+
+        if a.get_selected_pair(a_stream, &1).is_some() && b.get_selected_pair(b_stream, &1).is_some() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_check_list() {
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_lite(false);
+    agent.set_role(ice::Role::Controlling);
+    let stream_id = agent.add_stream(1);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+    let local = agent.get_stream_candidates(&stream_id, &1).unwrap()[0].clone();
+    let ip = local.conn.ip().unwrap();
+
+    agent.add_offer_candidate(&stream_id, &1, candidate(&format!("1 1 udp 1694498815 {} 5000 typ srflx raddr {} rport 5000", ip, ip)));
+    agent.add_offer_candidate(&stream_id, &1, candidate(&format!("2 1 udp 2130706431 {} 5001 typ host", ip)));
+    agent.add_offer_candidate(&stream_id, &1, candidate(&format!("2 1 udp 2130706175 {} 5002 typ host", ip)));
+    // Redundant, of the same address
+    agent.add_offer_candidate(&stream_id, &1, candidate(&format!("2 1 udp 2130706431 {} 5001 typ host", ip)));
+    // TCP and the other family are not paired
+    agent.add_offer_candidate(&stream_id, &1, candidate(&format!("3 1 tcp 2105524479 {} 9 typ host tcptype active", ip)));
+    let other = if ip.is_ipv4() { "2001:db8::1" } else { "192.0.2.1" };
+    agent.add_offer_candidate(&stream_id, &1, candidate(&format!("4 1 udp 2130706431 {} 5003 typ host", other)));
+
+    let pairs = agent.get_check_list(&stream_id, &1).unwrap();
+    let ports: Vec<u16> = pairs.iter().filter(|p| p.local_candidate().socket_addr() == local.socket_addr()).map(|p| p.peer_candidate().port).collect();
+    assert_eq!(ports, vec![5001, 5002, 5000]);
+    assert!(pairs.windows(2).all(|p| p[0].priority() >= p[1].priority()));
+
+    // One pair of each foundation waits, the others are frozen
+    let states: Vec<ice::PairState> = pairs.iter().filter(|p| p.local_candidate().socket_addr() == local.socket_addr()).map(|p| p.state()).collect();
+    assert_eq!(states, vec![ice::PairState::Waiting, ice::PairState::Frozen, ice::PairState::Waiting]);
+
+    // Priorities follow the role
+    let priorities: Vec<u64> = pairs.iter().map(|p| p.priority()).collect();
+    agent.set_role(ice::Role::Controlled);
+    let pairs = agent.get_check_list(&stream_id, &1).unwrap();
+    assert!(pairs.iter().map(|p| p.priority()).collect::<Vec<u64>>() != priorities);
+}
+
+#[test]
+fn test_connectivity_checks() {
+    let (mut a, a_stream, a_rx) = full_agent(ice::Role::Controlling);
+    let (mut b, b_stream, b_rx) = full_agent(ice::Role::Controlled);
+    exchange(&mut a, &a_stream, &mut b, &b_stream);
+
+    run_checks(&mut a, &a_stream, &mut b, &b_stream);
+
+    let a_pair = a.get_selected_pair(&a_stream, &1).unwrap();
+    let b_pair = b.get_selected_pair(&b_stream, &1).unwrap();
+    assert_eq!(a_pair.peer_candidate().socket_addr(), b_pair.local_candidate().socket_addr());
+    assert_eq!(b_pair.peer_candidate().socket_addr(), a_pair.local_candidate().socket_addr());
+    assert_eq!(a_rx.try_recv().unwrap().socket_addr(), a_pair.peer_candidate().socket_addr());
+    assert_eq!(b_rx.try_recv().unwrap().socket_addr(), b_pair.peer_candidate().socket_addr());
+
+    // Nothing's left to check
+    assert!(a.poll(Instant::now() + Duration::from_secs(60)).is_none());
+}
+
+#[test]
+fn test_checks_paced() {
+    let (mut a, a_stream, _a_rx) = full_agent(ice::Role::Controlling);
+    let (mut b, b_stream, _b_rx) = full_agent(ice::Role::Controlled);
+    exchange(&mut a, &a_stream, &mut b, &b_stream);
+    a.set_ta(Duration::from_millis(20));
+
+    let now = Instant::now();
+    assert!(a.poll(now).is_some());
+    assert!(a.poll(now + Duration::from_millis(10)).is_none());
+    assert_eq!(a.get_check_list(&a_stream, &1).unwrap()[0].state(), ice::PairState::InProgress);
+
+    // Lite agents don't check, nor do agents without the peer's credentials
+    assert!(b.poll(now).is_some());
+    b.set_lite(true);
+    assert!(b.poll(now + Duration::from_secs(1)).is_none());
+    assert!(full_agent(ice::Role::Controlling).0.poll(now).is_none());
+}
+
+#[test]
+fn test_full_with_lite() {
+    let (mut a, a_stream, a_rx) = full_agent(ice::Role::Controlling);
+    let (tx, b_rx) = ::std::sync::mpsc::channel();
+    let mut b = ice::Agent::new(Box::new(NominationHandler(tx)));
+    let b_stream = b.add_stream(1);
+    b.gather_candidates(&b_stream, &1).unwrap();
+    exchange(&mut a, &a_stream, &mut b, &b_stream);
+    assert!(b.get_check_list(&b_stream, &1).is_none());
+
+    run_checks(&mut a, &a_stream, &mut b, &b_stream);
+
+    assert!(a.get_selected_pair(&a_stream, &1).is_some());
+    assert!(a_rx.try_recv().is_ok());
+    assert!(b_rx.try_recv().is_ok());
+    assert_eq!(b.role(), ice::Role::Controlled);
+}
+
+#[test]
+fn test_role_conflict() {
+    for &role in [ice::Role::Controlling, ice::Role::Controlled].iter() {
+        let (mut a, a_stream, _a_rx) = full_agent(role);
+        let (mut b, b_stream, _b_rx) = full_agent(role);
+        exchange(&mut a, &a_stream, &mut b, &b_stream);
+
+        run_checks(&mut a, &a_stream, &mut b, &b_stream);
+
+        assert!(a.role() != b.role());
+        assert!(a.get_selected_pair(&a_stream, &1).is_some());
+        assert!(b.get_selected_pair(&b_stream, &1).is_some());
+    }
+}
+
+#[test]
+fn test_retransmissions() {
+    let (mut a, a_stream, _a_rx) = full_agent(ice::Role::Controlling);
+    let ip = a.get_stream_candidates(&a_stream, &1).unwrap()[0].conn.ip().unwrap();
+    a.set_remote_credentials(ice::Credentials { ufrag: "peer".to_string(), pwd: "peerpassword".to_string() });
+    a.add_offer_candidate(&a_stream, &1, candidate(&format!("1 1 udp 2130706431 {} 9 typ host", ip)));
+
+    // No one answers: the check is retransmitted with backoff, then fails
+    let start = Instant::now();
+    let mut sent = vec![];
+    for i in 0..20000 {
+        let now = start + Duration::from_millis(10 * i);
+        if let Some(transmit) = a.poll(now) {
+            let request = stun::Message::parse(&transmit.packet).unwrap();
+            assert_eq!(request.username(), Some(format!("peer:{}", a.local_credentials().ufrag)));
+            assert!(request.has(stun::ATTR_ICE_CONTROLLING));
+            assert!(stun::check_integrity(&transmit.packet, &request, &stun::short_term_key("peerpassword")));
+            sent.push((now, request.transaction_id));
+        }
+    }
+
+    assert_eq!(sent.len(), 7);
+    assert!(sent.iter().all(|s| s.1 == sent[0].1));
+    assert_eq!(sent[1].0 - sent[0].0, Duration::from_millis(500));
+    assert_eq!(sent[2].0 - sent[1].0, Duration::from_millis(1000));
+    assert_eq!(a.get_check_list(&a_stream, &1).unwrap()[0].state(), ice::PairState::Failed);
+}
+
+#[test]
+fn test_candidate_priority() {
+    use hibrido::ice::CandidateType;
+
+    assert_eq!(ice::candidate_priority(&CandidateType::Host, 65535, 1), 2130706431);
+    assert_eq!(ice::candidate_priority(&CandidateType::Host, 65535, 2), 2130706430);
+    assert_eq!(ice::candidate_priority(&CandidateType::Srflx, 65535, 1), 1694498815);
+    assert_eq!(ice::candidate_priority(&CandidateType::Prflx, 65535, 1), 1862270975);
+    assert_eq!(ice::candidate_priority(&CandidateType::Relay, 65535, 1), 16777215);
+
+    // Earlier interfaces, and IPv6 within an interface, are preferred
+    let v4: IpAddr = "192.0.2.1".parse().unwrap();
+    let v6: IpAddr = "2001:db8::1".parse().unwrap();
+    assert_eq!(ice::local_preference(&v6, 0), 65535);
+    assert!(ice::local_preference(&v6, 0) > ice::local_preference(&v4, 0));
+    assert!(ice::local_preference(&v4, 0) > ice::local_preference(&v6, 1));
+    assert!(ice::local_preference(&v4, 100000) > 0);
+
+    // Gathered candidates are ranked so
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    let stream_id = agent.add_stream(2);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+    agent.gather_candidates(&stream_id, &2).unwrap();
+    let rtp = agent.get_stream_candidates(&stream_id, &1).unwrap().clone();
+    let rtcp = agent.get_stream_candidates(&stream_id, &2).unwrap().clone();
+    for (rtp, rtcp) in rtp.iter().zip(rtcp.iter()) {
+        assert_eq!(rtp.priority >> 24, 126);
+        assert_eq!(rtp.priority & 0xff, 255);
+        assert_eq!(rtcp.priority, rtp.priority - 1);
+        assert_eq!(rtcp.foundation, rtp.foundation);
+    }
+}
+
+#[test]
+fn test_foundation() {
+    use hibrido::ice::{CandidateType, Proto};
+
+    let v4: IpAddr = "192.0.2.1".parse().unwrap();
+    let server: SocketAddr = "198.51.100.1:3478".parse().unwrap();
+    let host = ice::foundation(&CandidateType::Host, &v4, None, &Proto::Udp);
+
+    assert_eq!(host, ice::foundation(&CandidateType::Host, &v4, None, &Proto::Udp));
+    assert!(host.len() >= 1 && host.len() <= 32);
+    assert!(host.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    assert!(host != ice::foundation(&CandidateType::Host, &"192.0.2.2".parse().unwrap(), None, &Proto::Udp));
+    assert!(host != ice::foundation(&CandidateType::Host, &v4, None, &Proto::Tcp));
+    let srflx = ice::foundation(&CandidateType::Srflx, &v4, Some(&server), &Proto::Udp);
+    assert!(host != srflx);
+    assert!(srflx != ice::foundation(&CandidateType::Srflx, &v4, Some(&"198.51.100.2:3478".parse().unwrap()), &Proto::Udp));
+}
+
+fn interfaces() -> Vec<(String, IpAddr)> {
+    vec![("lo".to_string(), "127.0.0.1".parse().unwrap()),
+         ("docker0".to_string(), "172.17.0.1".parse().unwrap()),
+         ("eth0".to_string(), "192.168.1.10".parse().unwrap()),
+         ("eth0".to_string(), "2001:db8::10".parse().unwrap()),
+         ("eth0".to_string(), "fe80::10".parse().unwrap()),
+         ("eth1".to_string(), "10.0.0.5".parse().unwrap()),
+         ("eth1".to_string(), "10.0.0.5".parse().unwrap())]
+}
+
+fn selected(filter: &ice::InterfaceFilter) -> Vec<String> {
+    filter.select(&interfaces()).iter().map(|&(_, ip)| ip.to_string()).collect()
+}
+
+#[test]
+fn test_interface_filter() {
+    // Every interface but loopback and link-local ones
+    let mut filter = ice::InterfaceFilter::new();
+    assert_eq!(selected(&filter), vec!["172.17.0.1", "192.168.1.10", "2001:db8::10", "10.0.0.5"]);
+
+    // Each address has its own local preference, earlier interfaces first
+    let ranked = filter.select(&interfaces());
+    let preferences: Vec<u16> = ranked.iter().map(|&(rank, ip)| ice::local_preference(&ip, rank)).collect();
+    assert_eq!(preferences, vec![65534, 65532, 65535, 65530]);
+
+    filter.exclude = ice::InterfaceFilter::parse_rules("docker*").unwrap();
+    assert_eq!(selected(&filter), vec!["192.168.1.10", "2001:db8::10", "10.0.0.5"]);
+
+    filter.include = ice::InterfaceFilter::parse_rules("eth0, 10.0.0.0/8").unwrap();
+    assert_eq!(selected(&filter), vec!["192.168.1.10", "2001:db8::10", "10.0.0.5"]);
+    filter.exclude = ice::InterfaceFilter::parse_rules("2001:db8::/32").unwrap();
+    assert_eq!(selected(&filter), vec!["192.168.1.10", "10.0.0.5"]);
+
+    filter = ice::InterfaceFilter::new();
+    filter.include = ice::InterfaceFilter::parse_rules("lo").unwrap();
+    assert!(selected(&filter).is_empty());
+    filter.loopback = true;
+    assert_eq!(selected(&filter), vec!["127.0.0.1"]);
+}
+
+#[test]
+fn test_interface_rule() {
+    use hibrido::ice::InterfaceRule;
+
+    assert_eq!("eth0".parse::<InterfaceRule>(), Ok(InterfaceRule::Name("eth0".to_string())));
+    assert_eq!("10.0.0.0/8".parse::<InterfaceRule>(), Ok(InterfaceRule::Cidr("10.0.0.0".parse().unwrap(), 8)));
+    assert_eq!("10.0.0.1".parse::<InterfaceRule>(), Ok(InterfaceRule::Cidr("10.0.0.1".parse().unwrap(), 32)));
+    assert_eq!("2001:db8::/32".parse::<InterfaceRule>().unwrap().to_string(), "2001:db8::/32");
+    assert!("10.0.0.0/33".parse::<InterfaceRule>().is_err());
+    assert!("eth0/8".parse::<InterfaceRule>().is_err());
+    assert!(ice::InterfaceFilter::parse_rules("eth0,,10.0.0.0/40").is_err());
+
+    let ip: IpAddr = "172.16.5.4".parse().unwrap();
+    assert!("172.16.0.0/12".parse::<InterfaceRule>().unwrap().matches("eth0", &ip));
+    assert!(!"172.32.0.0/12".parse::<InterfaceRule>().unwrap().matches("eth0", &ip));
+    assert!(!"::/0".parse::<InterfaceRule>().unwrap().matches("eth0", &ip));
+    assert!("0.0.0.0/0".parse::<InterfaceRule>().unwrap().matches("eth0", &ip));
+    assert!("br-*".parse::<InterfaceRule>().unwrap().matches("br-1a2b", &ip));
+    assert!(!"br".parse::<InterfaceRule>().unwrap().matches("br-1a2b", &ip));
+}
+
+#[test]
+fn test_gather_loopback() {
+    let mut filter = ice::InterfaceFilter::new();
+    filter.include = ice::InterfaceFilter::parse_rules("127.0.0.0/8").unwrap();
+    filter.loopback = true;
+
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_interface_filter(filter);
+    let stream_id = agent.add_stream(1);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+
+    let candidates = agent.get_stream_candidates(&stream_id, &1).unwrap();
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].conn.to_string(), "127.0.0.1");
+    assert_eq!(candidates[0].priority, ice::candidate_priority(&ice::CandidateType::Host, 65534, 1));
+}
@@ -0,0 +1,466 @@
+extern crate hibrido;
+
+use hibrido::sdp::{SessionDescription, ParseMode, Attr, Direction, MediaType, FmtPValue, BandwidthType};
+use hibrido::offer_answer;
+use hibrido::ice;
+use hibrido::offer_answer::LocalParams;
+use hibrido::codec::{Codec, CodecRegistry};
+
+// Examples from RFC 4317, with the hosts' addresses in place of their names
+
+const ALICE_OFFER: &'static str = "v=0
+    o=alice 2890844526 2890844526 IN IP4 192.0.2.101
+    s=
+    c=IN IP4 192.0.2.101
+    t=0 0
+    m=audio 49170 RTP/AVP 0 8 97
+    a=rtpmap:0 PCMU/8000
+    a=rtpmap:8 PCMA/8000
+    a=rtpmap:97 iLBC/8000
+    m=video 51372 RTP/AVP 31 32
+    a=rtpmap:31 H261/90000
+    a=rtpmap:32 MPV/90000";
+
+fn parse(sdp: &str) -> SessionDescription {
+    SessionDescription::parse(sdp, ParseMode::Strict).unwrap()
+}
+
+/// Codecs of the RFC 4317 examples.
+fn local() -> LocalParams {
+    let mut codecs = CodecRegistry::new();
+    codecs.add(Codec::new(MediaType::AUDIO, "PCMU", 8000, None));
+    codecs.add(Codec::new(MediaType::AUDIO, "PCMA", 8000, None));
+    codecs.add(Codec::new(MediaType::AUDIO, "iLBC", 8000, None));
+    codecs.add(Codec::new(MediaType::VIDEO, "H261", 90000, None));
+    codecs.add(Codec::new(MediaType::VIDEO, "MPV", 90000, None));
+
+    let mut local = LocalParams::new();
+    local.codecs = codecs;
+    local
+}
+
+fn answer(base: Option<&str>, offer: &str, previous: Option<&SessionDescription>) -> SessionDescription {
+    let base = base.map(parse);
+    offer_answer::negotiate_with(base.as_ref(), &parse(offer), previous, &local()).unwrap()
+}
+
+fn fmt(answer: &SessionDescription, media_idx: usize) -> Vec<&str> {
+    answer.media[media_idx].media.fmt.iter().map(|f| f.as_str()).collect()
+}
+
+#[test]
+fn test_audio_and_video_1() {
+    // RFC 4317, section 2.1
+    let bob = "v=0
+        o=bob 2808844564 2808844564 IN IP4 192.0.2.201
+        s=
+        c=IN IP4 192.0.2.201
+        t=0 0
+        m=audio 49174 RTP/AVP 0
+        a=rtpmap:0 PCMU/8000
+        m=video 49170 RTP/AVP 32
+        a=rtpmap:32 MPV/90000";
+
+    let answer = answer(Some(bob), ALICE_OFFER, None);
+
+    assert_eq!(answer.media.len(), 2);
+    assert_eq!(answer.media[0].media.media, MediaType::AUDIO);
+    assert_eq!(fmt(&answer, 0), vec!["0"]);
+    assert_eq!(answer.media[1].media.media, MediaType::VIDEO);
+    assert_eq!(fmt(&answer, 1), vec!["32"]);
+    assert!(!answer.media[0].is_rejected());
+    assert!(!answer.media[1].is_rejected());
+    assert!(answer.to_string().contains("a=rtpmap:32 MPV/90000\r\n"));
+    assert!(!answer.to_string().contains("iLBC"));
+
+    // Media aren't sent back to the offerer's address
+    assert!(answer.to_string().contains("c=IN IP4 0.0.0.0\r\n"));
+    assert!(!answer.to_string().contains("192.0.2.101"));
+    assert!(answer.media.iter().all(|m| m.conns.is_empty()));
+}
+
+#[test]
+fn test_audio_and_video_2() {
+    // RFC 4317, section 2.2: Bob doesn't do video
+    let bob = "v=0
+        o=bob 2808844564 2808844564 IN IP4 192.0.2.201
+        s=
+        c=IN IP4 192.0.2.201
+        t=0 0
+        m=audio 49172 RTP/AVP 0 8
+        a=rtpmap:0 PCMU/8000
+        a=rtpmap:8 PCMA/8000";
+
+    let answer = answer(Some(bob), ALICE_OFFER, None);
+
+    assert_eq!(answer.media.len(), 2);
+    assert_eq!(fmt(&answer, 0), vec!["0", "8"]);
+    assert!(answer.media[1].is_rejected());
+    assert_eq!(answer.media[1].media.port, 0);
+    assert_eq!(fmt(&answer, 1), vec!["31", "32"]);
+    assert!(answer.media[1].attrs.is_empty());
+    assert!(answer.to_string().contains("m=video 0 RTP/AVP 31 32\r\n"));
+}
+
+#[test]
+fn test_audio_and_video_3() {
+    // RFC 4317, section 2.3: Bob only does video
+    let bob = "v=0
+        o=bob 2808844564 2808844564 IN IP4 192.0.2.201
+        s=
+        c=IN IP4 192.0.2.201
+        t=0 0
+        m=video 49168 RTP/AVP 32
+        a=rtpmap:32 MPV/90000";
+
+    let answer = answer(Some(bob), ALICE_OFFER, None);
+
+    assert!(answer.media[0].is_rejected());
+    assert_eq!(answer.media[0].media.media, MediaType::AUDIO);
+    assert!(!answer.media[1].is_rejected());
+    assert_eq!(fmt(&answer, 1), vec!["32"]);
+}
+
+#[test]
+fn test_disabled_by_offerer() {
+    let offer = ALICE_OFFER.replace("m=video 51372", "m=video 0");
+
+    let answer = answer(None, &offer, None);
+
+    assert!(!answer.media[0].is_rejected());
+    assert_eq!(fmt(&answer, 0), vec!["0", "8", "97"]);
+    assert!(answer.media[1].is_rejected());
+}
+
+#[test]
+fn test_directions() {
+    let bob = "v=0
+        o=bob 2808844564 2808844564 IN IP4 192.0.2.201
+        s=
+        c=IN IP4 192.0.2.201
+        t=0 0
+        m=audio 49174 RTP/AVP 0
+        a=rtpmap:0 PCMU/8000
+        a=recvonly";
+
+    let cases = vec![
+        ("a=sendonly", Direction::RecvOnly),
+        ("a=recvonly", Direction::Inactive),
+        ("a=sendrecv", Direction::RecvOnly),
+        ("a=inactive", Direction::Inactive),
+    ];
+    let audio_only = ALICE_OFFER.split("\n    m=video").next().unwrap();
+    for (offered, answered) in cases {
+        let offer = format!("{}\n{}", audio_only, offered);
+        let answer = answer(Some(bob), &offer, None);
+        assert_eq!(answer.media_direction(0), answered, "offered {}", offered);
+    }
+
+    let cases = vec![
+        (Direction::SendOnly, Direction::SendRecv, Direction::RecvOnly),
+        (Direction::RecvOnly, Direction::SendRecv, Direction::SendOnly),
+        (Direction::SendOnly, Direction::SendOnly, Direction::Inactive),
+        (Direction::SendRecv, Direction::SendOnly, Direction::SendOnly),
+    ];
+    for (offered, local, answered) in cases {
+        assert_eq!(offer_answer::answer_direction(&offered, &local), answered);
+    }
+}
+
+#[test]
+fn test_session_level_direction() {
+    let offer = ALICE_OFFER.replace("t=0 0", "t=0 0\n    a=sendonly");
+
+    let answer = answer(None, &offer, None);
+
+    assert!(!answer.attrs.contains(&Attr::SendOnly));
+    assert_eq!(answer.media[0].direction(), Some(Direction::RecvOnly));
+    assert_eq!(answer.media[1].direction(), Some(Direction::RecvOnly));
+}
+
+#[test]
+fn test_hold_and_resume() {
+    // RFC 4317, section 5.1
+    let first = answer(None, ALICE_OFFER, None);
+    assert_eq!(first.media_direction(0), Direction::SendRecv);
+
+    let hold = ALICE_OFFER
+        .replace("2890844526 2890844526", "2890844526 2890844527")
+        .replace("a=rtpmap:97 iLBC/8000", "a=rtpmap:97 iLBC/8000\n    a=sendonly")
+        .replace("a=rtpmap:32 MPV/90000", "a=rtpmap:32 MPV/90000\n    a=sendonly");
+    let held = answer(None, &hold, Some(&first));
+    assert_eq!(held.media_direction(0), Direction::RecvOnly);
+    assert_eq!(held.media_direction(1), Direction::RecvOnly);
+
+    let resume = ALICE_OFFER.replace("2890844526 2890844526", "2890844526 2890844528");
+    let resumed = answer(None, &resume, Some(&held));
+    assert_eq!(resumed.media_direction(0), Direction::SendRecv);
+
+    // Each new answer is a new version of the session
+    let version = |sdp: &SessionDescription| sdp.origin.as_ref().unwrap().session_version;
+    assert_eq!(version(&held), version(&first) + 1);
+    assert_eq!(version(&resumed), version(&first) + 2);
+}
+
+#[test]
+fn test_second_session_added() {
+    // RFC 4317, section 4.1: video is added in a re-offer
+    let audio_only = ALICE_OFFER.split("\n    m=video").next().unwrap();
+    let first = answer(None, audio_only, None);
+    assert_eq!(first.media.len(), 1);
+
+    let reanswer = answer(None, ALICE_OFFER, Some(&first));
+    assert_eq!(reanswer.media.len(), 2);
+    assert_eq!(reanswer.media[0].media.media, MediaType::AUDIO);
+    assert_eq!(reanswer.media[1].media.media, MediaType::VIDEO);
+    assert!(!reanswer.media[1].is_rejected());
+}
+
+#[test]
+fn test_session_removed_and_readded() {
+    // RFC 4317, section 4.2: video is removed with port 0
+    let first = answer(None, ALICE_OFFER, None);
+
+    let removed = ALICE_OFFER.replace("m=video 51372", "m=video 0");
+    let reanswer = answer(None, &removed, Some(&first));
+    assert!(reanswer.media[1].is_rejected());
+
+    // The disabled m-line can be reused, even for other media
+    let readded = ALICE_OFFER.replace("m=video 51372 RTP/AVP 31 32", "m=audio 51372 RTP/AVP 0");
+    let reanswer = answer(None, &readded, Some(&reanswer));
+    assert_eq!(reanswer.media[1].media.media, MediaType::AUDIO);
+    assert!(!reanswer.media[1].is_rejected());
+
+    // But m-lines can't be removed nor replaced while in use
+    let audio_only = ALICE_OFFER.split("\n    m=video").next().unwrap();
+    assert!(offer_answer::negotiate_with(None, &parse(audio_only), Some(&first), &local()).is_err());
+    assert!(offer_answer::negotiate_with(None, &parse(&readded), Some(&first), &local()).is_err());
+}
+
+#[test]
+fn test_ice_credentials() {
+    let credentials = ice::Credentials::generate();
+    assert_eq!(credentials.ufrag.len(), 16);
+    assert_eq!(credentials.pwd.len(), 24);
+    assert!(credentials.pwd.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/'));
+    assert!(credentials != ice::Credentials::generate());
+
+    let offer = ALICE_OFFER
+        .replace("t=0 0", "t=0 0\n    a=ice-ufrag:8hhY\n    a=ice-pwd:asd88fgpdd777uzjYhagZg")
+        .replace("a=rtpmap:97 iLBC/8000", "a=rtpmap:97 iLBC/8000\n    a=ice-ufrag:F7gI\n    a=ice-pwd:x9cml/YzichV2+XlhiMu8g");
+    let offer = parse(&offer);
+    assert_eq!(offer.ice_credentials(0).unwrap().ufrag, "F7gI");
+    assert_eq!(offer.ice_credentials(1).unwrap().pwd, "asd88fgpdd777uzjYhagZg");
+
+    // The offerer's credentials are replaced by ours, in every media
+    let mut local = local();
+    local.ice_credentials = Some(credentials.clone());
+    let answer = offer_answer::negotiate_with(None, &offer, None, &local).unwrap();
+    assert!(answer.attrs.iter().all(|attr| match *attr {
+        Attr::IceUfrag(_) | Attr::IcePwd(_) => false,
+        _ => true,
+    }));
+    assert_eq!(answer.ice_credentials(0), Some(credentials.clone()));
+    assert_eq!(answer.ice_credentials(1), Some(credentials));
+}
+
+#[test]
+fn test_origin() {
+    let first = answer(None, ALICE_OFFER, None);
+    let origin = first.origin.clone().unwrap();
+    assert_eq!(origin.username, "-");
+    assert!(origin.session_id != "2890844526");
+    assert_eq!(origin.ip_address.to_string(), "0.0.0.0");
+    assert_eq!(first.name, Some("-".to_string()));
+
+    let mut local = local();
+    local.username = "hibrido".to_string();
+    local.session_name = "conference".to_string();
+    let named = offer_answer::negotiate_with(None, &parse(ALICE_OFFER), None, &local).unwrap();
+    assert_eq!(named.origin.unwrap().username, "hibrido");
+    assert_eq!(named.name, Some("conference".to_string()));
+
+    // Re-offers are answered within the same session
+    let second = answer(None, ALICE_OFFER, Some(&first));
+    assert_eq!(second.origin.as_ref().unwrap().session_id, origin.session_id);
+
+    // Nor are the offerer's schedule and key answered
+    let offer = ALICE_OFFER.replace("t=0 0", "t=0 0\n    r=7d 1h 0 25h\n    z=2882844526 -1h\n    k=prompt");
+    let answer = answer(None, &offer, None).to_string();
+    assert!(!answer.contains("r="));
+    assert!(!answer.contains("z="));
+    assert!(!answer.contains("k="));
+}
+
+#[test]
+fn test_ice_lite() {
+    let is_lite = |answer: &SessionDescription| answer.attrs.iter().filter(|attr| match **attr {
+        Attr::IceLite => true,
+        _ => false,
+    }).count();
+    let lite_offer = parse(&ALICE_OFFER.replace("t=0 0", "t=0 0\n    a=ice-lite"));
+
+    // Said once by lite agents, whatever the offer
+    let answer = offer_answer::negotiate_with(None, &lite_offer, None, &local()).unwrap();
+    assert_eq!(is_lite(&answer), 1);
+
+    // Never by full ones
+    let mut local = local();
+    local.ice_lite = false;
+    assert_eq!(is_lite(&offer_answer::negotiate_with(None, &parse(ALICE_OFFER), None, &local).unwrap()), 0);
+    assert_eq!(is_lite(&offer_answer::negotiate_with(None, &lite_offer, None, &local).unwrap()), 0);
+}
+
+#[test]
+fn test_codec_registry() {
+    let offer = "v=0
+        o=- 4611731400430051336 2 IN IP4 127.0.0.1
+        s=-
+        c=IN IP4 127.0.0.1
+        t=0 0
+        m=audio 9 RTP/AVP 0 111 8
+        a=rtpmap:111 OPUS/48000/2
+        m=video 9 RTP/AVP 96
+        a=rtpmap:96 VP8/90000";
+
+    // Only what the platform decodes is answered, FEC not being offered
+    let answer = offer_answer::negotiate_with(None, &parse(offer), None, &LocalParams::new()).unwrap();
+    assert_eq!(fmt(&answer, 0), vec!["111"]);
+    assert!(answer.to_string().contains("a=fmtp:111 minptime=10\r\n"));
+    assert!(answer.media[1].is_rejected());
+
+    // Static payload types are known without "a=rtpmap"
+    let mut local = local();
+    assert_eq!(offer_answer::negotiate_with(None, &parse(offer), None, &local).unwrap().media[0].media.fmt,
+               vec!["0", "8"]);
+
+    local.codecs.set_preference(&["pcma"]);
+    let answer = offer_answer::negotiate_with(None, &parse(offer), None, &local).unwrap();
+    assert_eq!(fmt(&answer, 0), vec!["8", "0"]);
+}
+
+#[test]
+fn test_fmtp() {
+    let mut fmtp = "101 0-15;maxplaybackrate=16000 ; useinbandfec=1".parse::<FmtPValue>().unwrap();
+    assert_eq!(fmtp.format, 101);
+    assert_eq!(fmtp.params.len(), 3);
+    assert_eq!(fmtp.params[0], ("0-15".to_string(), None));
+    assert_eq!(fmtp.get("maxplaybackrate"), Some("16000"));
+    assert_eq!(fmtp.get("useinbandfec"), Some("1"));
+    assert_eq!(fmtp.get("0-15"), None);
+
+    fmtp.set("useinbandfec", "0");
+    assert_eq!(fmtp.to_string(), "101 0-15;maxplaybackrate=16000;useinbandfec=0");
+}
+
+#[test]
+fn test_fmtp_negotiation() {
+    let offer = "v=0
+        o=- 4611731400430051336 2 IN IP4 127.0.0.1
+        s=-
+        c=IN IP4 127.0.0.1
+        t=0 0
+        m=audio 9 RTP/AVP 111
+        a=rtpmap:111 opus/48000/2
+        a=fmtp:111 minptime=20;useinbandfec=1;usedtx=1;stereo=1;maxplaybackrate=24000;x-google=1
+        m=video 9 RTP/AVP 96 97 98 99 101 100
+        a=rtpmap:96 H264/90000
+        a=fmtp:96 packetization-mode=0;profile-level-id=42e01f
+        a=rtpmap:97 H264/90000
+        a=fmtp:97 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e02a
+        a=rtpmap:98 H264/90000
+        a=fmtp:98 packetization-mode=1;profile-level-id=640c1f
+        a=rtpmap:99 H264/90000
+        a=fmtp:99 packetization-mode=1;profile-level-id=42000d
+        a=rtpmap:101 H264/90000
+        a=fmtp:101 packetization-mode=1;profile-level-id=4d001f
+        a=rtpmap:100 VP9/90000
+        a=fmtp:100 profile-id=2";
+
+    let mut codecs = CodecRegistry::new();
+    codecs.add(Codec::new(MediaType::AUDIO, "opus", 48000, Some(2))
+               .with_fmtp("minptime=10;useinbandfec=1;stereo=1;sprop-stereo=1"));
+    codecs.add(Codec::new(MediaType::VIDEO, "H264", 90000, None)
+               .with_fmtp("level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"));
+    codecs.add(Codec::new(MediaType::VIDEO, "VP9", 90000, None));
+    let mut local = LocalParams::new();
+    local.codecs = codecs;
+
+    let answer = offer_answer::negotiate_with(None, &parse(offer), None, &local).unwrap();
+    let answer = answer.to_string();
+
+    // Only what both sides want, and our limits on what we receive
+    assert!(answer.contains("a=fmtp:111 stereo=1;sprop-stereo=1;useinbandfec=1;minptime=10\r\n"));
+
+    // Packetization modes must match and profiles be compatible, Baseline
+    // decoding Constrained Baseline, at our level if levels may differ
+    assert_eq!(fmt(&parse(&answer), 1), vec!["97", "99"]);
+    assert!(answer.contains("a=fmtp:97 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f\r\n"));
+    assert!(answer.contains("a=fmtp:99 packetization-mode=1;profile-level-id=42000d\r\n"));
+}
+
+#[test]
+fn test_bandwidth() {
+    let offer = "v=0
+        o=alice 2890844526 2890844526 IN IP4 192.0.2.101
+        s=
+        c=IN IP4 192.0.2.101
+        b=CT:2000
+        b=X-YZ:128
+        t=0 0
+        m=audio 49170 RTP/AVP 0
+        b=AS:64
+        b=TIAS:50000
+        a=rtpmap:0 PCMU/8000
+        m=video 51372 RTP/AVP 31
+        b=AS:256
+        a=rtpmap:31 H261/90000";
+
+    let bandwidths = |desc: &SessionDescription, media_idx: Option<usize>| {
+        let bandwidths = match media_idx {
+            Some(i) => &desc.media[i].bandwidths,
+            None => &desc.bandwidths,
+        };
+        bandwidths.iter().map(|b| (b.bwtype.clone(), b.bandwidth)).collect::<Vec<(BandwidthType, u64)>>()
+    };
+
+    // Without local limits, the offer's are answered, minus unknown types
+    let answer = offer_answer::negotiate_with(None, &parse(offer), None, &local()).unwrap();
+    assert_eq!(bandwidths(&answer, None), vec![(BandwidthType::CT, 2000)]);
+    assert_eq!(bandwidths(&answer, Some(0)), vec![(BandwidthType::AS, 64), (BandwidthType::TIAS, 50000)]);
+    assert_eq!(bandwidths(&answer, Some(1)), vec![(BandwidthType::AS, 256)]);
+    assert_eq!(answer.media_bitrate(0), Some(50000));
+    assert_eq!(answer.media_bitrate(1), Some(256000));
+
+    // Otherwise the lowest wins, the local one in kilobits for b=AS, and
+    // never above what the offer allows in other lines
+    let mut local = local();
+    local.audio_bitrate = Some(32000);
+    local.video_bitrate = Some(500500);
+    local.session_bitrate = Some(400000);
+    let answer = offer_answer::negotiate_with(None, &parse(offer), None, &local).unwrap();
+    assert_eq!(bandwidths(&answer, None), vec![(BandwidthType::CT, 400), (BandwidthType::AS, 400)]);
+    assert_eq!(bandwidths(&answer, Some(0)), vec![(BandwidthType::AS, 32), (BandwidthType::TIAS, 32000)]);
+    assert_eq!(bandwidths(&answer, Some(1)), vec![(BandwidthType::AS, 256), (BandwidthType::TIAS, 256000)]);
+    assert!(answer.to_string().contains("b=AS:32\r\nb=TIAS:32000\r\n"));
+
+    // Media are held to the lowest of their own and the session's limits
+    assert_eq!(answer.media_bitrate(0), Some(32000));
+    assert_eq!(answer.media_bitrate(1), Some(256000));
+
+    // Rejected media aren't given any
+    let offer = offer.replace("m=video 51372 RTP/AVP 31", "m=video 51372 RTP/AVP 34");
+    let answer = offer_answer::negotiate_with(None, &parse(&offer), None, &local).unwrap();
+    assert!(answer.media[1].is_rejected());
+    assert!(answer.media[1].bandwidths.is_empty());
+
+    // Huge offered limits don't overflow, but leave the others to apply
+    let offer = offer.replace("b=AS:64\n        b=TIAS:50000", "b=AS:18446744073709551615");
+    let answer = offer_answer::negotiate_with(None, &parse(&offer), None, &local).unwrap();
+    assert_eq!(bandwidths(&answer, Some(0)), vec![(BandwidthType::AS, 32), (BandwidthType::TIAS, 32000)]);
+    local.audio_bitrate = None;
+    local.session_bitrate = None;
+    let answer = offer_answer::negotiate_with(None, &parse(&offer), None, &local).unwrap();
+    assert_eq!(bandwidths(&answer, Some(0)), vec![(BandwidthType::AS, 18446744073709551615)]);
+    assert_eq!(answer.media_bitrate(0), Some(2000000));
+}
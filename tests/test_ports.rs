@@ -0,0 +1,199 @@
+extern crate hibrido;
+
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::sync::Arc;
+
+use hibrido::ports::PortAllocator;
+use hibrido::ice;
+use hibrido::sdp::{SessionDescription, ParseMode};
+use hibrido::offer_answer::LocalParams;
+use hibrido::convo::session_negotiation::{Session, SessionError};
+
+fn any_ipv4() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))
+}
+
+#[test]
+fn test_allocate() {
+    let ports = PortAllocator::new(47100, 47102).unwrap();
+
+    // A port bound by someone else is skipped
+    let taken = UdpSocket::bind("0.0.0.0:47101").unwrap();
+
+    let first = ports.allocate(any_ipv4()).unwrap();
+    let second = ports.allocate(any_ipv4()).unwrap();
+    assert_eq!(first.local_addr().unwrap().port(), 47100);
+    assert_eq!(second.local_addr().unwrap().port(), 47102);
+    assert_eq!(ports.nr_allocated(), 2);
+
+    // Until the range runs out
+    let err = ports.allocate(any_ipv4()).unwrap_err();
+    assert_eq!(err, "no free port in range 47100-47102");
+
+    // Released ports are allocated again, once their socket is closed
+    drop(taken);
+    ports.release(47100);
+    assert_eq!(ports.allocate(any_ipv4()).unwrap().local_addr().unwrap().port(), 47101);
+    drop(first);
+    assert_eq!(ports.allocate(any_ipv4()).unwrap().local_addr().unwrap().port(), 47100);
+}
+
+#[test]
+fn test_range() {
+    let ports = "10000-10999".parse::<PortAllocator>().unwrap();
+    assert_eq!(ports.range(), (10000, 10999));
+    assert_eq!(" 7000 - 7000 ".parse::<PortAllocator>().unwrap().range(), (7000, 7000));
+    assert_eq!(PortAllocator::default().range(), (6000, 6999));
+
+    assert!("10999-10000".parse::<PortAllocator>().is_err());
+    assert!("0-100".parse::<PortAllocator>().is_err());
+    assert!("10000".parse::<PortAllocator>().is_err());
+    assert!("10000-70000".parse::<PortAllocator>().is_err());
+}
+
+struct NoHandler;
+
+fn loopback_filter(rules: &str) -> ice::InterfaceFilter {
+    let mut filter = ice::InterfaceFilter::new();
+    filter.include = ice::InterfaceFilter::parse_rules(rules).unwrap();
+    filter.loopback = true;
+
+    filter
+}
+
+impl ice::Handler for NoHandler {
+    fn handle_callback(&mut self, _stream_id: &str, _candidate: ice::Candidate) {}
+}
+
+#[test]
+fn test_agent_ports() {
+    let ports = Arc::new(PortAllocator::new(47200, 47201).unwrap());
+
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_port_allocator(ports.clone());
+    agent.set_interface_filter(loopback_filter("127.0.0.0/8"));
+    let stream_id = agent.add_stream(2);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+    agent.gather_candidates(&stream_id, &2).unwrap();
+
+    // Candidates are on the ports of the bound sockets
+    let candidates = agent.get_stream_candidates(&stream_id, &1).unwrap().clone();
+    let mut sockets = agent.take_sockets(&stream_id, &1);
+    assert_eq!(sockets.len(), 1);
+    let socket = sockets.remove(0);
+    assert!(candidates.iter().all(|c| c.port == socket.local_addr().unwrap().port()));
+    assert!(agent.take_sockets(&stream_id, &1).is_empty());
+
+    // Exhaustion reaches the caller
+    let other_stream = agent.add_stream(1);
+    match agent.gather_candidates(&other_stream, &1) {
+        Err(ice::GatherError::Ports(_)) => {},
+        x => panic!("unexpected {:?}", x),
+    }
+
+    // As does having no address to gather on
+    let mut filter = loopback_filter("127.0.0.0/8");
+    filter.loopback = false;
+    agent.set_interface_filter(filter);
+    assert_eq!(agent.gather_candidates(&other_stream, &1), Err(ice::GatherError::NoAddress));
+    agent.set_interface_filter(loopback_filter("127.0.0.0/8"));
+
+    // Ports are released with the agent
+    drop(socket);
+    drop(agent);
+    assert_eq!(ports.nr_allocated(), 0);
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_port_allocator(ports.clone());
+    agent.set_interface_filter(loopback_filter("127.0.0.0/8"));
+    let stream_id = agent.add_stream(1);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+}
+
+#[test]
+fn test_agent_ports_per_family() {
+    let ports = Arc::new(PortAllocator::new(47400, 47409).unwrap());
+
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_port_allocator(ports.clone());
+    agent.set_interface_filter(loopback_filter("127.0.0.0/8,::1/128"));
+    let stream_id = agent.add_stream(1);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+
+    // A socket is bound for each family, its candidates on its port
+    let candidates = agent.get_stream_candidates(&stream_id, &1).unwrap().clone();
+    let sockets = agent.take_sockets(&stream_id, &1);
+    assert_eq!(sockets.len(), 2);
+    assert_eq!(ports.nr_allocated(), 2);
+    for socket in sockets.iter() {
+        let addr = socket.local_addr().unwrap();
+        assert!(addr.ip().is_unspecified());
+        assert!(candidates.iter().any(|c| c.port == addr.port()));
+        assert!(candidates.iter().all(|c| (c.port == addr.port()) == (c.socket_addr().unwrap().is_ipv6() == addr.is_ipv6())));
+    }
+}
+
+#[test]
+fn test_session_ports() {
+    let ports = Arc::new(PortAllocator::new(47300, 47301).unwrap());
+    let offer = "v=0
+        o=- 4611731400430051336 2 IN IP4 127.0.0.1
+        s=-
+        c=IN IP4 127.0.0.1
+        t=0 0
+        m=audio 9 UDP/TLS/RTP/SAVPF 111
+        a=rtcp-mux
+        a=rtpmap:111 opus/48000/2
+        a=ice-ufrag:8hhY
+        a=ice-pwd:asd88fgpdd777uzjYhagZg";
+
+    let filter = loopback_filter("127.0.0.0/8");
+
+    let session = Session::new(SessionDescription::parse(offer, ParseMode::Strict).unwrap());
+    session.set_port_allocator(ports.clone());
+    session.set_interface_filter(filter.clone());
+    session.negotiate_with_base_sdp(None, &LocalParams::new()).unwrap();
+    session.process_offer().unwrap();
+    session.process_answer().unwrap();
+    assert_eq!(ports.nr_allocated(), 1);
+
+    // Ports are released once their sockets are closed
+    session.close();
+    assert_eq!(ports.nr_allocated(), 0);
+
+    // RTCP offered past the last port is left to ICE
+    let no_rtcp = offer.replace("m=audio 9", "m=audio 65535").replace("a=rtcp-mux\n        ", "");
+    let session = Session::new(SessionDescription::parse(&no_rtcp, ParseMode::Strict).unwrap());
+    session.set_port_allocator(ports.clone());
+    session.set_interface_filter(filter.clone());
+    session.negotiate_with_base_sdp(None, &LocalParams::new()).unwrap();
+    session.process_offer().unwrap();
+    session.process_answer().unwrap();
+    assert_eq!(ports.nr_allocated(), 2);
+    session.close();
+    assert_eq!(ports.nr_allocated(), 0);
+
+    // So they can be bound again
+    let _sockets = (ports.allocate(any_ipv4()).unwrap(), ports.allocate(any_ipv4()).unwrap());
+
+    // Running out of them is told apart from failing to negotiate
+    let session = Session::new(SessionDescription::parse(offer, ParseMode::Strict).unwrap());
+    session.set_port_allocator(ports.clone());
+    session.set_interface_filter(filter);
+    session.negotiate_with_base_sdp(None, &LocalParams::new()).unwrap();
+    match session.process_offer() {
+        Err(SessionError::Ports(_)) => {},
+        x => panic!("unexpected {:?}", x),
+    }
+
+    // Or from having no address to gather candidates on
+    let mut no_address = loopback_filter("127.0.0.0/8");
+    no_address.loopback = false;
+    let session = Session::new(SessionDescription::parse(offer, ParseMode::Strict).unwrap());
+    session.set_port_allocator(Arc::new(PortAllocator::new(47310, 47319).unwrap()));
+    session.set_interface_filter(no_address);
+    session.negotiate_with_base_sdp(None, &LocalParams::new()).unwrap();
+    match session.process_offer() {
+        Err(SessionError::Candidates(_)) => {},
+        x => panic!("unexpected {:?}", x),
+    }
+}
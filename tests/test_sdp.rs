@@ -0,0 +1,530 @@
+extern crate hibrido;
+
+use hibrido::sdp;
+use hibrido::sdp::{SessionDescription, ParseMode, SdpErrorKind, Attr};
+use hibrido::offer_answer;
+use hibrido::offer_answer::LocalParams;
+use hibrido::codec::{Codec, CodecRegistry};
+use hibrido::sdp::{FingerprintValue, HashFunction, SetupValue};
+use hibrido::dtls::Certificate;
+
+/// Parameters answering with the codecs of the SDPs below.
+fn local_params() -> LocalParams {
+    let mut codecs = CodecRegistry::platform();
+    codecs.add(Codec::new(sdp::MediaType::AUDIO, "PCMU", 8000, None));
+    codecs.add(Codec::new(sdp::MediaType::VIDEO, "VP8", 90000, None));
+    codecs.add(Codec::new(sdp::MediaType::VIDEO, "VP9", 90000, None));
+
+    let mut local = LocalParams::new();
+    local.codecs = codecs;
+    local
+}
+
+const OFFER: &'static str = "v=0
+    o=jdoe 2890844526 2890842807 IN IP4 10.47.16.5
+    s=-
+    c=IN IP4 10.47.16.5
+    t=0 0
+    m=audio 49170 RTP/AVP 0 101
+    a=rtpmap:0 PCMU/8000
+    a=rtpmap:101 opus/48000/2
+    a=ptime:20
+    a=sendrecv";
+
+#[test]
+fn test_parse_strict() {
+    let desc = OFFER.parse::<SessionDescription>().unwrap();
+
+    assert_eq!(desc.ver, Some(0));
+    assert_eq!(desc.media.len(), 1);
+    assert_eq!(desc.media[0].media.port, 49170);
+    assert_eq!(desc.media[0].attrs.len(), 4);
+}
+
+#[test]
+fn test_parse_strict_reports_line() {
+    let sdp = OFFER.replace("a=ptime:20", "a=ptime:twenty");
+
+    let err = SessionDescription::parse(&sdp, ParseMode::Strict).unwrap_err();
+
+    assert_eq!(err.line, 9);
+    assert_eq!(err.line_type, Some('a'));
+    match err.kind {
+        SdpErrorKind::InvalidValue(_) => {},
+        _ => panic!("Unexpected error kind {:?}", err.kind),
+    }
+}
+
+#[test]
+fn test_parse_strict_missing_line() {
+    let sdp = OFFER.replace("t=0 0", "");
+
+    let err = SessionDescription::parse(&sdp, ParseMode::Strict).unwrap_err();
+
+    assert_eq!(err.line_type, Some('t'));
+    assert_eq!(err.kind, SdpErrorKind::Missing);
+}
+
+#[test]
+fn test_parse_lenient_skips_lines() {
+    let sdp = OFFER.replace("m=audio 49170", "m=audio 491700")
+                   .replace("a=ptime:20", "a=ptime:");
+
+    let res = SessionDescription::new().from_sdp(&sdp);
+
+    assert_eq!(res.unparsed_lines.len(), 2);
+    assert_eq!(res.errors.len(), 2);
+    assert_eq!(res.errors[0].line, 6);
+    assert_eq!(res.errors[0].line_type, Some('m'));
+    // Attributes of the dropped media end up at the session level
+    assert_eq!(res.desc.media.len(), 0);
+    assert!(SessionDescription::parse(&sdp, ParseMode::Lenient).is_ok());
+}
+
+#[test]
+fn test_parse_never_panics() {
+    let garbage = [
+        "",
+        "=",
+        "v",
+        "v=x",
+        "o=",
+        "c=IN IP4",
+        "c=IN IP4 10.0.0.1/1/2/3",
+        "c=XX IP9 10.0.0.1",
+        "t=1",
+        "m=audio",
+        "m=audio x RTP/AVP 0",
+        "m=foo 9 RTP/AVP 0",
+        "a=rtpmap:",
+        "a=rtpmap:x opus/48000",
+        "a=rtpmap:101 opus",
+        "a=fmtp:101",
+        "a=candidate:1 1 udp",
+        "a=candidate:1 x udp 1 10.0.0.1 9 typ host",
+        "a=candidate:1 1 udp 1 10.0.0.1 9 typ host raddr x rport y",
+        "a=ice-ufrag",
+        "a=ptime",
+        "\u{1F600}=\u{1F600}",
+    ];
+
+    for line in garbage.iter() {
+        let sdp = format!("{}\n{}", OFFER, line);
+
+        let _ = SessionDescription::parse(&sdp, ParseMode::Strict);
+        let _ = SessionDescription::parse(&sdp, ParseMode::Lenient);
+        let _ = SessionDescription::parse(line, ParseMode::Strict);
+    }
+}
+
+#[test]
+fn test_parse_all_line_types() {
+    let sdp = "v=0
+o=jdoe 2890844526 2890842807 IN IP4 10.47.16.5
+s=SDP Seminar
+i=A Seminar on the session description protocol
+u=http://www.example.com/seminars/sdp.pdf
+e=j.doe@example.com (Jane Doe)
+p=+1 617 555-6011
+c=IN IP4 10.47.16.5
+b=CT:128
+t=2873397496 2873404696
+r=7d 1h 0 25h
+z=2882844526 -1h 2898848070 0
+k=prompt
+a=recvonly
+m=audio 49170 RTP/AVP 0
+i=Audio stream
+c=IN IP4 10.47.16.6
+b=AS:64
+k=base64:c2VjcmV0
+m=video 51372/2 RTP/AVP 99
+b=X-YZ:256
+a=rtpmap:99 h263-1998/90000
+";
+
+    let desc = SessionDescription::parse(sdp, ParseMode::Strict).unwrap();
+
+    assert_eq!(desc.uri, Some("http://www.example.com/seminars/sdp.pdf".to_string()));
+    assert_eq!(desc.emails.len(), 1);
+    assert_eq!(desc.phones, vec!["+1 617 555-6011".to_string()]);
+    assert_eq!(desc.bandwidths[0].bandwidth, 128);
+    assert_eq!(desc.times[0].repeat_times.len(), 1);
+    assert_eq!(desc.times[0].repeat_times[0].interval.as_seconds(), 604800);
+    assert_eq!(desc.time_zones.len(), 2);
+    assert_eq!(desc.time_zones[0].offset.as_seconds(), -3600);
+    assert_eq!(desc.encrypt_key.as_ref().unwrap().method, "prompt");
+    assert_eq!(desc.media[0].title, Some("Audio stream".to_string()));
+    assert_eq!(desc.media[0].conns.len(), 1);
+    assert_eq!(desc.media[0].bandwidths[0].bandwidth, 64);
+    assert_eq!(desc.media[0].encrypt_key.as_ref().unwrap().key, Some("c2VjcmV0".to_string()));
+    assert_eq!(desc.media[1].media.num_ports, Some(2));
+
+    assert_eq!(desc.to_string(), sdp.replace("\n", "\r\n"));
+}
+
+#[test]
+fn test_parse_strict_misplaced_line() {
+    let sdp = format!("{}\nt=0 0", OFFER);
+    let err = SessionDescription::parse(&sdp, ParseMode::Strict).unwrap_err();
+    assert_eq!(err.line, 11);
+    assert_eq!(err.kind, SdpErrorKind::Unexpected);
+
+    let res = SessionDescription::new().from_sdp(&sdp);
+    assert_eq!(res.ignored_lines.len(), 1);
+}
+
+#[test]
+fn test_unknown_attrs_preserved() {
+    let sdp = format!("{}\na=extmap-allow-mixed\na=x-vendor:some:value\na=ssrc:1 cname:x\na=rtcp-rsize\na=content:slides",
+                      OFFER.replace("t=0 0", "t=0 0\na=msid-semantic: WMS *\na=ice-options:trickle"));
+
+    let desc = SessionDescription::parse(&sdp, ParseMode::Strict).unwrap();
+
+    assert_eq!(desc.attrs[0], Attr::Other {
+        name: "msid-semantic".to_string(),
+        value: Some(" WMS *".to_string()),
+    });
+    assert_eq!(desc.media[0].attrs[4], Attr::Other {
+        name: "extmap-allow-mixed".to_string(),
+        value: None,
+    });
+    assert!(desc.to_string().contains("a=x-vendor:some:value\r\n"));
+
+    // Only the unknown attributes known to hold in the answer are passed
+    // through
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local_params()).unwrap();
+    let answer = answer.to_string();
+    assert!(answer.contains("a=content:slides\r\n"));
+    assert!(!answer.contains("a=msid-semantic"));
+    assert!(!answer.contains("a=ice-options"));
+    assert!(!answer.contains("a=extmap-allow-mixed"));
+    assert!(!answer.contains("a=rtcp-rsize"));
+    assert!(!answer.contains("a=x-vendor"));
+    assert!(!answer.contains("a=ssrc:"));
+}
+
+#[test]
+fn test_dtls_attrs() {
+    let sdp = OFFER.replace("RTP/AVP", "UDP/TLS/RTP/SAVPF")
+        .replace("a=sendrecv", "a=sendrecv\na=setup:actpass\na=fingerprint:sha-256 \
+                  0A:1B:2C:3D:4E:5F:60:71:82:93:A4:B5:C6:D7:E8:F9:0A:1B:2C:3D:4E:5F:60:71:82:93:A4:B5:C6:D7:E8:F9");
+
+    let desc = SessionDescription::parse(&sdp, ParseMode::Strict).unwrap();
+
+    let fingerprint = match desc.media[0].attrs[5] {
+        Attr::Fingerprint(ref x) => x.clone(),
+        ref x => panic!("Unexpected attribute {:?}", x),
+    };
+    assert_eq!(fingerprint.hash_fn, HashFunction::Sha256);
+    assert_eq!(fingerprint.digest.len(), 32);
+    assert_eq!(fingerprint.digest[2], 0x2C);
+    assert_eq!(desc.media[0].attrs[4], Attr::Setup(SetupValue::ActPass));
+    assert!(desc.to_string().contains("a=fingerprint:sha-256 0A:1B:2C:3D:"));
+
+    // We answer with our own fingerprint and take the active role
+    let mut local = local_params();
+    local.fingerprint = Some(Certificate::from_der(vec![0x30, 0x00]).fingerprint());
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local).unwrap();
+
+    assert!(answer.media[0].attrs.contains(&Attr::Setup(SetupValue::Active)));
+    assert!(answer.media[0].attrs.contains(&Attr::Fingerprint(local.fingerprint.clone().unwrap())));
+    assert!(!answer.media[0].attrs.contains(&Attr::Fingerprint(fingerprint)));
+}
+
+#[test]
+fn test_dtls_setup_roles() {
+    assert_eq!(SetupValue::answer_to(Some(&SetupValue::ActPass)), SetupValue::Active);
+    assert_eq!(SetupValue::answer_to(Some(&SetupValue::Active)), SetupValue::Passive);
+    assert_eq!(SetupValue::answer_to(Some(&SetupValue::Passive)), SetupValue::Active);
+    assert_eq!(SetupValue::answer_to(Some(&SetupValue::HoldConn)), SetupValue::HoldConn);
+    assert_eq!(SetupValue::answer_to(None), SetupValue::Active);
+
+    assert!("sha-256 0A:1".parse::<FingerprintValue>().is_err());
+    assert!("sha-256".parse::<FingerprintValue>().is_err());
+}
+
+const BUNDLE_OFFER: &'static str = "v=0
+    o=- 4611731400430051336 2 IN IP4 127.0.0.1
+    s=-
+    t=0 0
+    a=group:BUNDLE 0 1
+    m=audio 9 UDP/TLS/RTP/SAVPF 111
+    c=IN IP4 0.0.0.0
+    a=mid:0
+    a=rtpmap:111 opus/48000/2
+    a=sendrecv
+    m=video 9 UDP/TLS/RTP/SAVPF 96
+    c=IN IP4 0.0.0.0
+    a=mid:1
+    a=rtpmap:96 VP8/90000
+    a=sendrecv
+    m=audio 9 UDP/TLS/RTP/SAVPF 0
+    c=IN IP4 0.0.0.0
+    a=mid:2
+    a=rtpmap:0 PCMU/8000";
+
+#[test]
+fn test_bundle() {
+    let desc = SessionDescription::parse(BUNDLE_OFFER, ParseMode::Strict).unwrap();
+
+    assert_eq!(desc.bundle_groups(), vec![vec!["0".to_string(), "1".to_string()]]);
+    assert_eq!(desc.media[1].mid(), Some("1"));
+    assert_eq!(desc.transport_owners(), vec![0, 0, 2]);
+
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local_params()).unwrap();
+    assert_eq!(answer.bundle_groups(), desc.bundle_groups());
+    assert_eq!(answer.media[2].mid(), Some("2"));
+    assert!(answer.to_string().contains("a=group:BUNDLE 0 1\r\n"));
+
+    let mut local = local_params();
+    local.bundle = false;
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local).unwrap();
+    assert!(answer.bundle_groups().is_empty());
+    assert_eq!(answer.transport_owners(), vec![0, 1, 2]);
+}
+
+#[test]
+fn test_bundle_rejected_media() {
+    let desc = SessionDescription::parse(BUNDLE_OFFER, ParseMode::Strict).unwrap();
+    let mut base = SessionDescription::parse(BUNDLE_OFFER, ParseMode::Strict).unwrap();
+    base.attrs.clear();
+    base.media.remove(0);
+    base.media.remove(0);
+    for media in base.media.iter_mut() {
+        media.media.proto = sdp::MediaProto::RtpAvp;
+    }
+
+    // Only the media outside the group is accepted, so nothing is bundled
+    let mut offer = desc.clone();
+    for media in offer.media.iter_mut() {
+        media.media.proto = sdp::MediaProto::RtpAvp;
+    }
+    let answer = offer_answer::negotiate_with(Some(&base), &offer, None, &local_params()).unwrap();
+    assert!(answer.bundle_groups().is_empty());
+}
+
+const RTCP_MUX_OFFER: &'static str = "v=0
+    o=- 20518 0 IN IP4 203.0.113.1
+    s=-
+    t=0 0
+    c=IN IP4 203.0.113.1
+    m=audio 54609 RTP/AVP 0
+    a=rtpmap:0 PCMU/8000
+    a=rtcp:53020 IN IP4 126.16.64.4
+    a=rtcp-mux";
+
+#[test]
+fn test_rtcp_mux() {
+    let desc = SessionDescription::parse(RTCP_MUX_OFFER, ParseMode::Strict).unwrap();
+
+    assert!(desc.media[0].rtcp_mux());
+    match desc.media[0].attrs[1] {
+        Attr::Rtcp(ref rtcp) => {
+            assert_eq!(rtcp.port, 53020);
+            assert_eq!(rtcp.conn.as_ref().unwrap().ip_address.to_string(), "126.16.64.4");
+        },
+        ref attr => panic!("unexpected attr {:?}", attr),
+    }
+    assert!(desc.to_string().contains("a=rtcp:53020 IN IP4 126.16.64.4\r\na=rtcp-mux\r\n"));
+
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local_params()).unwrap();
+    assert!(answer.media[0].rtcp_mux());
+    assert!(!answer.media[0].attrs.iter().any(|a| match *a { Attr::Rtcp(_) => true, _ => false }));
+
+    let mut local = local_params();
+    local.rtcp_mux = false;
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local).unwrap();
+    assert!(!answer.media[0].rtcp_mux());
+}
+
+const SSRC_OFFER: &'static str = "v=0
+    o=- 4611731400430051336 2 IN IP4 127.0.0.1
+    s=-
+    t=0 0
+    a=msid-semantic: WMS stream0
+    m=video 9 UDP/TLS/RTP/SAVPF 96 97
+    c=IN IP4 0.0.0.0
+    a=rtpmap:96 VP8/90000
+    a=rtpmap:97 rtx/90000
+    a=sendrecv
+    a=msid:stream0 track0
+    a=ssrc-group:FID 1111 2222
+    a=ssrc:1111 cname:abcd
+    a=ssrc:1111 msid:stream0 track1
+    a=ssrc:2222 cname:abcd";
+
+#[test]
+fn test_ssrc_attrs() {
+    let desc = SessionDescription::parse(SSRC_OFFER, ParseMode::Strict).unwrap();
+    let media = &desc.media[0];
+
+    match media.attrs[4] {
+        Attr::SsrcGroup(ref group) => {
+            assert_eq!(group.semantics, sdp::SsrcGroupSemantics::FID);
+            assert_eq!(group.ssrcs, vec![1111, 2222]);
+        },
+        ref attr => panic!("unexpected attr {:?}", attr),
+    }
+    match media.attrs[5] {
+        Attr::Ssrc(ref ssrc) => {
+            assert_eq!(ssrc.ssrc, 1111);
+            assert_eq!(ssrc.attribute, "cname");
+            assert_eq!(ssrc.value, Some("abcd".to_string()));
+        },
+        ref attr => panic!("unexpected attr {:?}", attr),
+    }
+    assert!(desc.to_string().contains("a=ssrc-group:FID 1111 2222\r\na=ssrc:1111 cname:abcd\r\n"));
+
+    let sources = media.sources();
+    assert_eq!(sources.len(), 2);
+    assert_eq!(sources[0].0, 1111);
+    assert_eq!(sources[0].1.as_ref().unwrap().track_id, Some("track1".to_string()));
+    assert_eq!(sources[1].0, 2222);
+    assert_eq!(sources[1].1.as_ref().unwrap().track_id, Some("track0".to_string()));
+
+    // The offerer's sources are never echoed
+    let mut answer = offer_answer::negotiate_with(None, &desc, None, &local_params()).unwrap();
+    assert!(answer.media[0].sources().is_empty());
+
+    let msid = sdp::MsidValue { stream_id: "hibrido".to_string(), track_id: Some("audio0".to_string()) };
+    answer.media[0].add_source(3333, "cname0", &msid);
+    assert_eq!(answer.media[0].sources(), vec![(3333, Some(msid))]);
+}
+
+const RTCP_FB_OFFER: &'static str = "v=0
+    o=- 4611731400430051336 2 IN IP4 127.0.0.1
+    s=-
+    t=0 0
+    m=video 9 UDP/TLS/RTP/SAVPF 96 98
+    c=IN IP4 0.0.0.0
+    a=rtpmap:96 VP8/90000
+    a=rtpmap:98 VP9/90000
+    a=rtcp-fb:96 goog-remb
+    a=rtcp-fb:96 transport-cc
+    a=rtcp-fb:96 ccm fir
+    a=rtcp-fb:96 nack
+    a=rtcp-fb:96 nack pli
+    a=rtcp-fb:98 nack
+    a=rtcp-fb:* trr-int 100";
+
+#[test]
+fn test_rtcp_fb() {
+    let desc = SessionDescription::parse(RTCP_FB_OFFER, ParseMode::Strict).unwrap();
+
+    assert_eq!(desc.media[0].attrs[6], Attr::RtcpFb(sdp::RtcpFbValue {
+        payload_type: Some(96),
+        kind: sdp::RtcpFbKind::NackPli,
+    }));
+    assert_eq!(desc.media[0].attrs[8], Attr::RtcpFb(sdp::RtcpFbValue {
+        payload_type: None,
+        kind: sdp::RtcpFbKind::Other("trr-int 100".to_string()),
+    }));
+    assert!(desc.to_string().contains("a=rtcp-fb:96 ccm fir\r\n"));
+
+    // Nothing is answered unless implemented
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local_params()).unwrap();
+    assert!(!answer.to_string().contains("a=rtcp-fb"));
+
+    let mut local = local_params();
+    local.rtcp_fb = vec![sdp::RtcpFbKind::Nack, sdp::RtcpFbKind::TransportCc];
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local).unwrap();
+    let rtcp_fbs = answer.media[0].attrs.iter().filter_map(|attr| match *attr {
+        Attr::RtcpFb(ref x) => Some(x.to_string()),
+        _ => None,
+    }).collect::<Vec<String>>();
+    assert_eq!(rtcp_fbs, vec!["96 transport-cc", "96 nack", "98 nack"]);
+
+    // Only the feedback of the payload types kept is answered
+    let mut base = desc.clone();
+    base.media[0].media.fmt = vec!["98".to_string()];
+    base.media[0].attrs.remove(0);
+    let answer = offer_answer::negotiate_with(Some(&base), &desc, None, &local).unwrap();
+    assert_eq!(answer.media[0].media.fmt, vec!["98".to_string()]);
+    assert!(answer.to_string().contains("a=rtcp-fb:98 nack\r\n"));
+    assert!(!answer.to_string().contains("a=rtcp-fb:96"));
+}
+
+const EXTMAP_OFFER: &'static str = "v=0
+    o=- 4611731400430051336 2 IN IP4 127.0.0.1
+    s=-
+    t=0 0
+    m=audio 9 UDP/TLS/RTP/SAVPF 111
+    c=IN IP4 0.0.0.0
+    a=rtpmap:111 opus/48000/2
+    a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level
+    a=extmap:2/sendonly http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time
+    a=extmap:3 urn:ietf:params:rtp-hdrext:toffset
+    a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid";
+
+#[test]
+fn test_extmap() {
+    let desc = SessionDescription::parse(EXTMAP_OFFER, ParseMode::Strict).unwrap();
+
+    assert_eq!(desc.media[0].attrs[2], Attr::ExtMap {
+        id: 2,
+        direction: Some(sdp::Direction::SendOnly),
+        uri: sdp::ABS_SEND_TIME_URI.to_string(),
+        attrs: None,
+    });
+    assert!(desc.to_string().contains("a=extmap:2/sendonly http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r\n"));
+
+    // Unsupported extensions are dropped, the offerer's IDs kept
+    let answer = offer_answer::negotiate_with(None, &desc, None, &local_params()).unwrap();
+    let extmaps = answer.media[0].extmaps();
+    assert_eq!(extmaps.len(), 3);
+    assert_eq!(extmaps[&1], sdp::AUDIO_LEVEL_URI);
+    assert_eq!(extmaps[&4], sdp::SDES_MID_URI);
+    assert!(answer.to_string().contains("a=extmap:2/recvonly "));
+}
+
+const SIMULCAST_OFFER: &'static str = "v=0
+    o=- 4611731400430051336 2 IN IP4 127.0.0.1
+    s=-
+    t=0 0
+    m=video 9 UDP/TLS/RTP/SAVPF 96 98
+    c=IN IP4 0.0.0.0
+    a=rtpmap:96 VP8/90000
+    a=rtpmap:98 VP9/90000
+    a=sendonly
+    a=rid:h send pt=96;max-width=1280;max-height=720
+    a=rid:m send pt=96,98;max-width=640
+    a=rid:l send pt=98
+    a=simulcast:send h;m,~l";
+
+#[test]
+fn test_simulcast() {
+    let desc = SessionDescription::parse(SIMULCAST_OFFER, ParseMode::Strict).unwrap();
+    let media = &desc.media[0];
+
+    let h = media.rid("h").unwrap();
+    assert_eq!(h.direction, sdp::RidDirection::Send);
+    assert_eq!(h.payload_types, vec![96]);
+    assert_eq!(h.restriction("max-height"), Some("720"));
+
+    let simulcast = media.simulcast().unwrap();
+    assert_eq!(simulcast.send.len(), 2);
+    assert!(simulcast.send[1][1].paused);
+    assert!(simulcast.recv.is_empty());
+    assert!(desc.to_string().contains("a=rid:m send pt=96,98;max-width=640\r\na=rid:l send pt=98\r\na=simulcast:send h;m,~l\r\n"));
+
+    let layers = media.simulcast_layers().iter().map(|rid| rid.id.clone()).collect::<Vec<String>>();
+    assert_eq!(layers, vec!["h", "m"]);
+
+    // The layers are received, those without a payload type left dropped
+    let mut base = desc.clone();
+    base.media[0].media.fmt = vec!["96".to_string()];
+    base.media[0].attrs.remove(1);
+    for media in base.media.iter_mut() {
+        media.media.proto = sdp::MediaProto::RtpAvp;
+    }
+    let answer = offer_answer::negotiate_with(Some(&base), &desc, None, &local_params()).unwrap();
+    let answer_media = &answer.media[0];
+    assert_eq!(answer_media.rid("m").unwrap().payload_types, vec![96]);
+    assert_eq!(answer_media.rid("m").unwrap().direction, sdp::RidDirection::Recv);
+    assert!(answer_media.rid("l").is_none());
+    assert!(answer.to_string().contains("a=simulcast:recv h;m\r\n"));
+
+    let layers = answer_media.simulcast_layers().iter().map(|rid| rid.id.clone()).collect::<Vec<String>>();
+    assert_eq!(layers, vec!["h", "m"]);
+}
@@ -0,0 +1,377 @@
+extern crate hibrido;
+
+use std::net::IpAddr;
+
+use hibrido::sdp::{SessionDescription, ParseMode, MediaType, MediaProto, Direction, Origin, Connection};
+use hibrido::sdp::{Address, AddrType, Attr};
+use hibrido::ice::Candidate;
+use hibrido::sdp::{SessionDescriptionBuilder, MediaDescriptionBuilder};
+
+// Offers of real browsers, as they put them on the wire
+
+const CHROME_OFFER: &'static str = "v=0\r
+o=- 4611731400430051336 2 IN IP4 127.0.0.1\r
+s=-\r
+t=0 0\r
+a=group:BUNDLE 0 1 2\r
+a=extmap-allow-mixed\r
+a=msid-semantic: WMS 6sEHoA2rkrO4mJjU\r
+m=audio 9 UDP/TLS/RTP/SAVPF 111 63 9 0 8 13 110 126\r
+c=IN IP4 0.0.0.0\r
+a=rtcp:9 IN IP4 0.0.0.0\r
+a=candidate:1467250027 1 udp 2122260223 192.168.0.196 46243 typ host generation 0 network-id 1\r
+a=candidate:435653019 1 tcp 1845501695 203.0.113.7 9 typ srflx raddr 192.168.0.196 rport 9 generation 0 network-id 1\r
+a=ice-ufrag:Oyef\r
+a=ice-pwd:7ouQfDy5sSb8Jc0uNK7YO1OT\r
+a=ice-options:trickle\r
+a=fingerprint:sha-256 D6:9E:6E:2E:36:41:DC:12:7B:5E:7E:2C:5A:30:7E:A9:CE:7D:6A:3F:12:B8:6C:D5:21:1F:AC:11:56:2E:3C:A7\r
+a=setup:actpass\r
+a=mid:0\r
+a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r
+a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r
+a=extmap:3 http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01\r
+a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid\r
+a=sendrecv\r
+a=msid:6sEHoA2rkrO4mJjU 2d3e7a8b-0c2b-4a54-9c6e-4f0a9b1d2e3f\r
+a=rtcp-mux\r
+a=rtpmap:111 opus/48000/2\r
+a=rtcp-fb:111 transport-cc\r
+a=fmtp:111 minptime=10;useinbandfec=1\r
+a=rtpmap:63 red/48000/2\r
+a=fmtp:63 111/111\r
+a=rtpmap:9 G722/8000\r
+a=rtpmap:0 PCMU/8000\r
+a=rtpmap:8 PCMA/8000\r
+a=rtpmap:13 CN/8000\r
+a=rtpmap:110 telephone-event/48000\r
+a=rtpmap:126 telephone-event/8000\r
+a=ssrc:3735928559 cname:uGzbBzTGnWlbWzqL\r
+a=ssrc:3735928559 msid:6sEHoA2rkrO4mJjU 2d3e7a8b-0c2b-4a54-9c6e-4f0a9b1d2e3f\r
+m=video 9 UDP/TLS/RTP/SAVPF 96 97 102 103\r
+c=IN IP4 0.0.0.0\r
+a=rtcp:9 IN IP4 0.0.0.0\r
+a=ice-ufrag:Oyef\r
+a=ice-pwd:7ouQfDy5sSb8Jc0uNK7YO1OT\r
+a=ice-options:trickle\r
+a=fingerprint:sha-256 D6:9E:6E:2E:36:41:DC:12:7B:5E:7E:2C:5A:30:7E:A9:CE:7D:6A:3F:12:B8:6C:D5:21:1F:AC:11:56:2E:3C:A7\r
+a=setup:actpass\r
+a=mid:1\r
+a=extmap:14 urn:ietf:params:rtp-hdrext:toffset\r
+a=extmap:2 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r
+a=extmap:4 urn:ietf:params:rtp-hdrext:sdes:mid\r
+a=extmap:10 urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id\r
+a=extmap:11 urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id\r
+a=sendrecv\r
+a=msid:6sEHoA2rkrO4mJjU 9f1e2d3c-4b5a-6978-8a9b-0c1d2e3f4a5b\r
+a=rtcp-mux\r
+a=rtcp-rsize\r
+a=rtpmap:96 VP8/90000\r
+a=rtcp-fb:96 goog-remb\r
+a=rtcp-fb:96 transport-cc\r
+a=rtcp-fb:96 ccm fir\r
+a=rtcp-fb:96 nack\r
+a=rtcp-fb:96 nack pli\r
+a=rtpmap:97 rtx/90000\r
+a=fmtp:97 apt=96\r
+a=rtpmap:102 H264/90000\r
+a=rtcp-fb:102 nack pli\r
+a=fmtp:102 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001f\r
+a=rtpmap:103 rtx/90000\r
+a=fmtp:103 apt=102\r
+a=ssrc-group:FID 1111111111 2222222222\r
+a=ssrc:1111111111 cname:uGzbBzTGnWlbWzqL\r
+a=ssrc:1111111111 msid:6sEHoA2rkrO4mJjU 9f1e2d3c-4b5a-6978-8a9b-0c1d2e3f4a5b\r
+a=ssrc:2222222222 cname:uGzbBzTGnWlbWzqL\r
+a=ssrc:2222222222 msid:6sEHoA2rkrO4mJjU 9f1e2d3c-4b5a-6978-8a9b-0c1d2e3f4a5b\r
+m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r
+c=IN IP4 0.0.0.0\r
+a=ice-ufrag:Oyef\r
+a=ice-pwd:7ouQfDy5sSb8Jc0uNK7YO1OT\r
+a=ice-options:trickle\r
+a=fingerprint:sha-256 D6:9E:6E:2E:36:41:DC:12:7B:5E:7E:2C:5A:30:7E:A9:CE:7D:6A:3F:12:B8:6C:D5:21:1F:AC:11:56:2E:3C:A7\r
+a=setup:actpass\r
+a=mid:2\r
+a=sctp-port:5000\r
+a=max-message-size:262144\r
+";
+
+const FIREFOX_OFFER: &'static str = "v=0\r
+o=mozilla...THIS_IS_SDPARTA-99.0 5393264283538893117 0 IN IP4 0.0.0.0\r
+s=-\r
+t=0 0\r
+a=fingerprint:sha-256 3B:4F:8B:1C:5E:22:9A:07:EE:4D:91:60:AA:31:0C:79:58:B2:6E:D4:13:47:9F:2A:C8:05:6B:E1:74:3D:90:1E\r
+a=group:BUNDLE 0 1\r
+a=ice-options:trickle\r
+a=msid-semantic:WMS *\r
+m=audio 9 UDP/TLS/RTP/SAVPF 109 9 0 8 101\r
+c=IN IP4 0.0.0.0\r
+a=sendrecv\r
+a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r
+a=extmap:2/recvonly urn:ietf:params:rtp-hdrext:csrc-audio-level\r
+a=extmap:3 urn:ietf:params:rtp-hdrext:sdes:mid\r
+a=fmtp:109 maxplaybackrate=48000;stereo=1;useinbandfec=1\r
+a=fmtp:101 0-15\r
+a=ice-pwd:b6a7c1e0d4f2a9b8c3e5d7f1a2b4c6d8\r
+a=ice-ufrag:4f8a2c1e\r
+a=mid:0\r
+a=msid:{5d6c7b8a-1e2f-4a3b-9c8d-7e6f5a4b3c2d} {0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d}\r
+a=rtcp-mux\r
+a=rtpmap:109 opus/48000/2\r
+a=rtpmap:9 G722/8000/1\r
+a=rtpmap:0 PCMU/8000\r
+a=rtpmap:8 PCMA/8000\r
+a=rtpmap:101 telephone-event/8000\r
+a=setup:actpass\r
+a=ssrc:2655508255 cname:{6f1a2b3c-4d5e-4f6a-8b7c-9d0e1f2a3b4c}\r
+m=video 9 UDP/TLS/RTP/SAVPF 120 124 126 127\r
+c=IN IP4 0.0.0.0\r
+a=recvonly\r
+a=extmap:3 urn:ietf:params:rtp-hdrext:sdes:mid\r
+a=extmap:4 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time\r
+a=extmap:5 urn:ietf:params:rtp-hdrext:toffset\r
+a=fmtp:126 profile-level-id=42e01f;level-asymmetry-allowed=1;packetization-mode=1\r
+a=fmtp:120 max-fs=12288;max-fr=60\r
+a=fmtp:124 apt=120\r
+a=fmtp:127 apt=126\r
+a=ice-pwd:b6a7c1e0d4f2a9b8c3e5d7f1a2b4c6d8\r
+a=ice-ufrag:4f8a2c1e\r
+a=mid:1\r
+a=rtcp-fb:120 nack\r
+a=rtcp-fb:120 nack pli\r
+a=rtcp-fb:120 ccm fir\r
+a=rtcp-fb:120 goog-remb\r
+a=rtcp-fb:126 nack\r
+a=rtcp-fb:126 nack pli\r
+a=rtcp-mux\r
+a=rtpmap:120 VP8/90000\r
+a=rtpmap:124 rtx/90000\r
+a=rtpmap:126 H264/90000\r
+a=rtpmap:127 rtx/90000\r
+a=setup:actpass\r
+a=ssrc:3019147862 cname:{6f1a2b3c-4d5e-4f6a-8b7c-9d0e1f2a3b4c}\r
+";
+
+const SAFARI_OFFER: &'static str = "v=0\r
+o=- 2208913937461425389 2 IN IP4 127.0.0.1\r
+s=-\r
+t=0 0\r
+a=group:BUNDLE 0\r
+a=msid-semantic: WMS\r
+m=audio 58620 UDP/TLS/RTP/SAVPF 111 103 9 0 8 105 13 110 113 126\r
+c=IN IP4 198.51.100.24\r
+a=rtcp:9 IN IP4 0.0.0.0\r
+a=candidate:2999745851 1 udp 2122262783 2001:db8::1c2f 58621 typ host generation 0 network-id 2\r
+a=candidate:1829861745 1 udp 2122194687 10.0.1.3 58620 typ host generation 0 network-id 1\r
+a=candidate:842163049 1 udp 1686052607 198.51.100.24 58620 typ srflx raddr 10.0.1.3 rport 58620 generation 0 network-id 1\r
+a=ice-ufrag:ZmPq\r
+a=ice-pwd:h3wY1ZTmn7GJd9gDR8Fj0+Qs\r
+a=ice-options:trickle\r
+a=fingerprint:sha-256 8A:2E:1F:93:47:C0:5B:66:D8:3A:0E:F1:72:4B:9C:58:E3:17:A6:2D:C4:90:5F:BB:61:08:7E:D2:3C:A5:49:F7\r
+a=setup:actpass\r
+a=mid:0\r
+a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level\r
+a=recvonly\r
+a=rtcp-mux\r
+a=rtpmap:111 opus/48000/2\r
+a=rtcp-fb:111 transport-cc\r
+a=fmtp:111 minptime=10;useinbandfec=1\r
+a=rtpmap:103 ISAC/16000\r
+a=rtpmap:9 G722/8000\r
+a=rtpmap:0 PCMU/8000\r
+a=rtpmap:8 PCMA/8000\r
+a=rtpmap:105 CN/16000\r
+a=rtpmap:13 CN/8000\r
+a=rtpmap:110 telephone-event/48000\r
+a=rtpmap:113 telephone-event/16000\r
+a=rtpmap:126 telephone-event/8000\r
+";
+
+const CORPUS: [&'static str; 3] = [CHROME_OFFER, FIREFOX_OFFER, SAFARI_OFFER];
+
+#[test]
+fn test_round_trip() {
+    for sdp in CORPUS.iter() {
+        let desc = SessionDescription::parse(sdp, ParseMode::Strict).unwrap();
+        let serialized = desc.to_string();
+
+        // Browsers already produce canonical descriptions
+        assert_eq!(serialized, *sdp);
+
+        let reparsed = SessionDescription::parse(&serialized, ParseMode::Strict).unwrap();
+        assert_eq!(reparsed.to_string(), serialized);
+        assert_eq!(reparsed.media.len(), desc.media.len());
+    }
+}
+
+#[test]
+fn test_round_trip_line_endings() {
+    // LF only, as typed by hand, comes out with CRLF
+    let lf = CHROME_OFFER.replace("\r\n", "\n");
+    let desc = SessionDescription::parse(&lf, ParseMode::Strict).unwrap();
+    assert_eq!(desc.to_string(), CHROME_OFFER);
+}
+
+#[test]
+fn test_connection_forms() {
+    let lines = [
+        ("IN IP4 198.51.100.1", true),
+        ("IN IP4 198.51.100.1/127", false),
+        ("IN IP4 224.2.36.42/127", true),
+        ("IN IP4 224.2.1.1/127/3", true),
+        ("IN IP4 224.2.1.1", false),
+        ("IN IP6 2001:db8::1", true),
+        ("IN IP6 2001:db8::1/3", false),
+        ("IN IP6 ff15::101", true),
+        ("IN IP6 ff15::101/3", true),
+        ("IN IP6 ff15::101/127/3", false),
+        ("IN IP4 2001:db8::1", false),
+        ("IN IP6 198.51.100.1", false),
+        ("IN IP4 host.example.com", true),
+        ("IN IP6 host.example.com", true),
+        ("IN IP4 host.example.com/127", false),
+        ("IN IP4 -host.example.com", false),
+        ("IN IP4 198.51.100", false),
+    ];
+
+    for &(line, valid) in lines.iter() {
+        let sdp = format!("v=0\r\no=- 1 1 IN IP4 127.0.0.1\r\ns=-\r\nc={}\r\nt=0 0\r\n", line);
+        let parsed = SessionDescription::parse(&sdp, ParseMode::Strict);
+        assert_eq!(parsed.is_ok(), valid, "c={}", line);
+
+        if valid {
+            assert_eq!(parsed.unwrap().to_string(), sdp);
+        }
+    }
+
+    // TTL and number of addresses only go where they belong
+    let mut conn = Connection::new("198.51.100.1".parse::<IpAddr>().unwrap());
+    conn.ttl = Some(127);
+    conn.nr_addrs = Some(3);
+    assert_eq!(conn.to_string(), "c=IN IP4 198.51.100.1\r\n");
+
+    let mut conn = Connection::new("ff15::101".parse::<IpAddr>().unwrap());
+    conn.ttl = Some(127);
+    conn.nr_addrs = Some(3);
+    assert_eq!(conn.to_string(), "c=IN IP6 ff15::101/3\r\n");
+}
+
+#[test]
+fn test_builder() {
+    let ip = "127.0.0.1".parse::<IpAddr>().unwrap();
+    let desc = SessionDescriptionBuilder::new(Origin::new("-", "4611731400430051336", 2, ip))
+        .connection(Connection::new(ip))
+        .media(MediaDescriptionBuilder::new(MediaType::AUDIO, 9, MediaProto::RtpAvp)
+               .rtpmap(111, "opus", 48000, Some(2))
+               .fmtp(111, "minptime=10;useinbandfec=1")
+               .format("0")
+               .mid("0")
+               .direction(Direction::SendOnly)
+               .build())
+        .build();
+
+    let sdp = "v=0\r
+o=- 4611731400430051336 2 IN IP4 127.0.0.1\r
+s=-\r
+c=IN IP4 127.0.0.1\r
+t=0 0\r
+m=audio 9 RTP/AVP 111 0\r
+a=rtpmap:111 opus/48000/2\r
+a=fmtp:111 minptime=10;useinbandfec=1\r
+a=mid:0\r
+a=sendonly\r
+";
+    assert_eq!(desc.to_string(), sdp);
+    assert_eq!(SessionDescription::parse(sdp, ParseMode::Strict).unwrap().to_string(), sdp);
+}
+
+#[test]
+fn test_json_round_trip() {
+    for sdp in CORPUS.iter() {
+        let desc = SessionDescription::parse(sdp, ParseMode::Strict).unwrap();
+        let json = desc.to_json();
+
+        let from_json = SessionDescription::from_json(&json).unwrap();
+        assert_eq!(from_json.to_string(), *sdp);
+        assert_eq!(from_json.to_json(), json);
+    }
+}
+
+#[test]
+fn test_json_schema() {
+    let sdp = "v=0\r
+o=- 1 2 IN IP4 127.0.0.1\r
+s=-\r
+c=IN IP4 127.0.0.1\r
+t=0 0\r
+a=group:BUNDLE 0\r
+m=audio 9 UDP/TLS/RTP/SAVPF 111\r
+a=rtpmap:111 opus/48000/2\r
+a=fmtp:111 minptime=10;useinbandfec=1\r
+a=candidate:1 1 udp 2122260223 192.168.0.196 46243 typ host generation 0\r
+a=sendrecv\r
+a=rtcp-mux\r
+a=x-custom\r
+";
+    let json = SessionDescription::parse(sdp, ParseMode::Strict).unwrap().to_json();
+
+    assert!(json.contains(r#""media":{"media":"audio","port":9,"num_ports":null,"proto":"UDP/TLS/RTP/SAVPF","fmt":["111"]}"#));
+    assert!(json.contains(r#"{"type":"group","value":{"semantics":"BUNDLE","mids":["0"]}}"#));
+    assert!(json.contains(r#"{"type":"rtpmap","value":{"payload_type":111,"encoding_name":"opus","clock_rate":48000,"encoding_params":2}}"#));
+    assert!(json.contains(r#"{"type":"fmtp","value":{"format":111,"params":[["minptime","10"],["useinbandfec","1"]]}}"#));
+    assert!(json.contains(r#""conn":"192.168.0.196","port":46243,"proto":"udp""#));
+    assert!(json.contains(r#""extensions":[["generation","0"]]"#));
+    assert!(json.contains(r#"{"type":"sendrecv"},{"type":"rtcp-mux"}"#));
+    assert!(json.contains(r#"{"type":"other","value":{"name":"x-custom","value":null}}"#));
+
+    // Tweaked in JSON, and back to SDP
+    let json = json.replace(r#""port":9"#, r#""port":0"#);
+    let desc = SessionDescription::from_json(&json).unwrap();
+    assert!(desc.to_string().contains("m=audio 0 UDP/TLS/RTP/SAVPF 111\r\n"));
+
+    assert!(SessionDescription::from_json(r#"{"ver":0}"#).is_err());
+    assert!(SessionDescription::from_json(&json.replace("UDP/TLS/RTP/SAVPF", "FOO")).is_err());
+}
+
+#[test]
+fn test_addresses() {
+    // Dual-stack and mDNS candidates, of a name in place of the origin's
+    // address
+    let sdp = "v=0\r
+o=- 1 2 IN IP6 host.example.com\r
+s=-\r
+c=IN IP6 2001:db8::1\r
+t=0 0\r
+m=audio 9 UDP/TLS/RTP/SAVPF 111\r
+a=rtpmap:111 opus/48000/2\r
+a=candidate:1 1 udp 2122260223 192.168.0.196 46243 typ host\r
+a=candidate:2 1 udp 2122262783 2001:db8::2 46244 typ host\r
+a=candidate:3 1 udp 2122194687 4b30a4a7-d5f3-46c0-b457-4d7d9b0b3a19.local 46245 typ host\r
+a=candidate:4 1 udp 1686052607 2001:db8::3 46246 typ srflx raddr 2001:db8::2 rport 46244\r
+";
+    let desc = SessionDescription::parse(sdp, ParseMode::Strict).unwrap();
+    assert_eq!(desc.to_string(), sdp);
+
+    let origin = desc.origin.as_ref().unwrap();
+    assert_eq!(origin.ip_address, Address::Fqdn("host.example.com".to_string()));
+    assert_eq!(origin.addr_type, AddrType::IP6);
+    assert_eq!(desc.conn.as_ref().unwrap().ip_address.ip(), Some("2001:db8::1".parse::<IpAddr>().unwrap()));
+
+    let candidates = desc.media[0].attrs.iter().filter_map(|attr| match *attr {
+        Attr::Candidate(ref c) => Some(c.ice_candidate.clone()),
+        _ => None,
+    }).collect::<Vec<Candidate>>();
+    assert_eq!(candidates[1].socket_addr(), Some("[2001:db8::2]:46244".parse().unwrap()));
+    assert_eq!(candidates[2].conn, Address::Fqdn("4b30a4a7-d5f3-46c0-b457-4d7d9b0b3a19.local".to_string()));
+    assert_eq!(candidates[2].socket_addr(), None);
+    assert_eq!(candidates[3].rel_addr, Some(Address::Ip("2001:db8::2".parse().unwrap())));
+
+    // Names survive JSON as well
+    assert_eq!(SessionDescription::from_json(&desc.to_json()).unwrap().to_string(), sdp);
+
+    // Origin and connection built from names
+    let desc = SessionDescriptionBuilder::new(Origin::new("-", "1", 2, Address::Fqdn("host.example.com".to_string())))
+        .connection(Connection::new("2001:db8::1".parse::<IpAddr>().unwrap()))
+        .build();
+    assert!(desc.to_string().contains("o=- 1 2 IN IP4 host.example.com\r\n"));
+    assert!(desc.to_string().contains("c=IN IP6 2001:db8::1\r\n"));
+}
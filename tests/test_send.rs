@@ -47,7 +47,7 @@ fn test_send() {
         session_version: 11,
         net_type: sdp::NetType::IN,
         addr_type: sdp::AddrType::IP4,
-        ip_address: IpAddr::V6(Ipv6Addr::new(0,0,0,0,0,0xffff,5,2))
+        ip_address: sdp::Address::Ip(IpAddr::V6(Ipv6Addr::new(0,0,0,0,0,0xffff,5,2)))
     });
     let s1_exp = format!("{:?}", s1);
 
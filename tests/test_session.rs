@@ -0,0 +1,131 @@
+extern crate hibrido;
+
+use std::sync::Arc;
+
+use hibrido::ice;
+use hibrido::ports::PortAllocator;
+use hibrido::sdp::{SessionDescription, ParseMode, MediaType};
+use hibrido::offer_answer::LocalParams;
+use hibrido::codec::{Codec, CodecRegistry};
+use hibrido::convo::session_negotiation::{Session, MediaStream};
+
+const OFFER: &'static str = "v=0
+    o=- 4611731400430051336 2 IN IP4 127.0.0.1
+    s=-
+    c=IN IP4 127.0.0.1
+    t=0 0
+    a=group:BUNDLE a0 v0
+    m=audio 9 UDP/TLS/RTP/SAVPF 111
+    a=mid:a0
+    a=rtcp-mux
+    a=rtpmap:111 opus/48000/2
+    a=ice-ufrag:8hhY
+    a=ice-pwd:asd88fgpdd777uzjYhagZg
+    m=video 9 UDP/TLS/RTP/SAVPF 96
+    a=mid:v0
+    a=rtcp-mux
+    a=rtpmap:96 VP8/90000
+    a=ice-ufrag:8hhY
+    a=ice-pwd:asd88fgpdd777uzjYhagZg
+    m=audio 9 RTP/AVP 0
+    a=rtpmap:0 PCMU/8000
+    a=ice-ufrag:8hhY
+    a=ice-pwd:asd88fgpdd777uzjYhagZg";
+
+fn negotiate(offer: &str, local: &LocalParams) -> Session {
+    let session = Session::new(SessionDescription::parse(offer, ParseMode::Strict).unwrap());
+    session.negotiate_with_base_sdp(None, local).unwrap();
+    session.process_offer().unwrap();
+
+    session
+}
+
+#[test]
+fn test_media_streams() {
+    let mut codecs = CodecRegistry::platform();
+    codecs.add(Codec::new(MediaType::AUDIO, "PCMU", 8000, None));
+    codecs.add(Codec::new(MediaType::VIDEO, "VP8", 90000, None));
+    let mut local = LocalParams::new();
+    local.codecs = codecs;
+
+    let session = negotiate(OFFER, &local);
+    let streams = session.media_streams();
+
+    // Each media gets its own stream, unless bundled
+    assert_eq!(streams.iter().map(|s| s.mid.as_str()).collect::<Vec<&str>>(), vec!["a0", "v0", "2"]);
+    assert_eq!(streams.iter().map(|s| s.media_type.clone()).collect::<Vec<MediaType>>(),
+               vec![MediaType::AUDIO, MediaType::VIDEO, MediaType::AUDIO]);
+    assert_eq!(streams[0].stream_id, streams[1].stream_id);
+    assert!(streams[0].stream_id != streams[2].stream_id);
+    assert!(streams.iter().all(|s| s.sending));
+    assert!(streams.iter().all(|s| s.bitrate.is_none()));
+
+    // Each sent media has its own source
+    assert!(session.local_ssrc(0).is_some());
+    assert!(session.local_ssrc(0) != session.local_ssrc(2));
+    assert_eq!(session.mid(2), Some("2".to_string()));
+}
+
+#[test]
+fn test_media_streams_reoffer() {
+    let mut codecs = CodecRegistry::platform();
+    codecs.add(Codec::new(MediaType::AUDIO, "PCMU", 8000, None));
+    codecs.add(Codec::new(MediaType::VIDEO, "VP8", 90000, None));
+    let mut local = LocalParams::new();
+    local.codecs = codecs;
+
+    let ports = Arc::new(PortAllocator::new(47400, 47409).unwrap());
+    let mut filter = ice::InterfaceFilter::new();
+    filter.include = ice::InterfaceFilter::parse_rules("127.0.0.0/8").unwrap();
+    filter.loopback = true;
+
+    let session = Session::new(SessionDescription::parse(OFFER, ParseMode::Strict).unwrap());
+    session.set_port_allocator(ports.clone());
+    session.set_interface_filter(filter);
+    session.negotiate_with_base_sdp(None, &local).unwrap();
+    session.process_offer().unwrap();
+    let streams = session.media_streams();
+    // A port for the bundle, and one per component of the other media
+    assert_eq!(ports.nr_allocated(), 3);
+
+    // Media keep their streams across re-offers
+    session.negotiate_with_base_sdp(None, &local).unwrap();
+    session.process_offer().unwrap();
+    let stream_ids = |streams: &[MediaStream]| streams.iter().map(|s| s.stream_id.clone()).collect::<Vec<String>>();
+    assert_eq!(stream_ids(&session.media_streams()), stream_ids(&streams));
+    assert_eq!(ports.nr_allocated(), 3);
+
+    // And those no media is carried on anymore are removed
+    session.negotiate_with_base_sdp(None, &LocalParams::new()).unwrap();
+    session.process_offer().unwrap();
+    assert_eq!(stream_ids(&session.media_streams()), stream_ids(&streams[..1]));
+    assert_eq!(ports.nr_allocated(), 1);
+}
+
+#[test]
+fn test_media_streams_rejected() {
+    // Video isn't supported by the platform
+    let session = negotiate(OFFER, &LocalParams::new());
+    let streams = session.media_streams();
+
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].mid, "a0");
+    assert_eq!(streams[0].media_type, MediaType::AUDIO);
+    assert!(session.local_ssrc(1).is_none());
+}
+
+#[test]
+fn test_media_streams_bitrate() {
+    let mut local = LocalParams::new();
+    local.audio_bitrate = Some(24000);
+
+    let offer = OFFER.replace("a=mid:a0", "b=AS:64\n    a=mid:a0");
+
+    // The encoder of each audio media is set from these
+    let session = negotiate(&offer, &local);
+    assert_eq!(session.media_streams()[0].bitrate, Some(24000));
+
+    local.audio_bitrate = Some(96000);
+    let session = negotiate(&offer, &local);
+    assert_eq!(session.media_streams()[0].bitrate, Some(64000));
+}
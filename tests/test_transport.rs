@@ -0,0 +1,160 @@
+extern crate hibrido;
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use hibrido::ice;
+use hibrido::stun;
+use hibrido::transport::{Transport, Checker};
+
+struct NoHandler;
+
+impl ice::Handler for NoHandler {
+    fn handle_callback(&mut self, _stream_id: &str, _candidate: ice::Candidate) {}
+}
+
+fn bind() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    socket
+}
+
+fn recv(socket: &UdpSocket) -> (Vec<u8>, SocketAddr) {
+    let mut buf = [0; 1500];
+    let (size, source) = socket.recv_from(&mut buf).unwrap();
+
+    (buf[..size].to_vec(), source)
+}
+
+#[test]
+fn test_transport_checks() {
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_remote_credentials(ice::Credentials { ufrag: "peer".to_string(), pwd: "peerpassword".to_string() });
+    let stream_id = agent.add_stream(1);
+    let local = agent.local_credentials().clone();
+    let ice = Arc::new(Mutex::new(agent));
+
+    let conn = bind();
+    let addr = conn.local_addr().unwrap();
+    let (_transport, _media) = Transport::open(&stream_id, ice.clone(), vec![conn], vec![]).unwrap();
+
+    // Checks reaching the socket are answered by the agent
+    let peer = bind();
+    let mut request = stun::Message::new(stun::BINDING_REQUEST, [1; 12]);
+    request.add(stun::ATTR_USERNAME, format!("{}:peer", local.ufrag).into_bytes());
+    request.add(stun::ATTR_PRIORITY, vec![0x6e, 0x00, 0x01, 0xff]);
+    peer.send_to(&request.encode(Some(&stun::short_term_key(&local.pwd)), true), addr).unwrap();
+
+    let (packet, source) = recv(&peer);
+    assert_eq!(source, addr);
+    let response = stun::Message::parse(&packet).unwrap();
+    assert_eq!(response.msg_type, stun::BINDING_SUCCESS);
+    assert_eq!(response.xor_mapped_address(), Some(peer.local_addr().unwrap()));
+
+    // And the peer is learnt from them
+    let offered = ice.lock().unwrap().get_offer_candidates(&stream_id, &1).cloned().unwrap();
+    assert_eq!(offered[0].socket_addr(), peer.local_addr().ok());
+}
+
+#[test]
+fn test_transport_relay() {
+    let ice = Arc::new(Mutex::new(ice::Agent::new(Box::new(NoHandler))));
+    let (rtp_conn, rtcp_conn) = (bind(), bind());
+    let (rtp_addr, rtcp_addr) = (rtp_conn.local_addr().unwrap(), rtcp_conn.local_addr().unwrap());
+    let (transport, media) = Transport::open("stream", ice, vec![rtp_conn], vec![rtcp_conn]).unwrap();
+    let (rtp_relay, rtcp_relay) = transport.relay_addrs();
+
+    let peer = bind();
+    transport.set_peer(ice::RTP_COMPONENT_ID, peer.local_addr().unwrap());
+    transport.set_peer(ice::RTCP_COMPONENT_ID, peer.local_addr().unwrap());
+
+    // Media of each component goes out on its own socket
+    let rtp = [0x80, 0x60, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1];
+    media.rtp.send_to(&rtp, rtp_relay).unwrap();
+    assert_eq!(recv(&peer), (rtp.to_vec(), rtp_addr));
+
+    let rtcp = [0x80, 0xc8, 0, 1, 0, 0, 0, 1];
+    media.rtcp.send_to(&rtcp, rtcp_relay).unwrap();
+    assert_eq!(recv(&peer), (rtcp.to_vec(), rtcp_addr));
+
+    // And what the peer sends reaches the media session
+    media.rtp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    media.rtcp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    peer.send_to(&rtp, rtp_addr).unwrap();
+    assert_eq!(recv(&media.rtp), (rtp.to_vec(), rtp_relay));
+    peer.send_to(&rtcp, rtcp_addr).unwrap();
+    assert_eq!(recv(&media.rtcp), (rtcp.to_vec(), rtcp_relay));
+
+    // Closing it wakes up the media session's readers
+    drop(transport);
+    assert_eq!(recv(&media.rtp), (vec![], rtp_relay));
+    assert_eq!(recv(&media.rtcp), (vec![], rtcp_relay));
+    assert!(UdpSocket::bind(rtp_addr).is_ok());
+}
+
+#[test]
+fn test_checker() {
+    let peer = bind();
+    let mut filter = ice::InterfaceFilter::new();
+    filter.include = ice::InterfaceFilter::parse_rules("127.0.0.0/8").unwrap();
+    filter.loopback = true;
+
+    // A full agent, with a candidate on loopback paired with the peer's
+    let mut agent = ice::Agent::new(Box::new(NoHandler));
+    agent.set_lite(false);
+    agent.set_interface_filter(filter);
+    agent.set_remote_credentials(ice::Credentials { ufrag: "peer".to_string(), pwd: "peerpassword".to_string() });
+    let stream_id = agent.add_stream(1);
+    agent.gather_candidates(&stream_id, &1).unwrap();
+    let value = format!("1 1 udp 2122260223 127.0.0.1 {} typ host", peer.local_addr().unwrap().port());
+    agent.add_offer_candidate(&stream_id, &1, value.parse::<hibrido::sdp::CandidateValue>().unwrap().ice_candidate);
+    agent.start(Box::new(NoHandler));
+    let conn = agent.take_sockets(&stream_id, &1).remove(0);
+    let addr = conn.local_addr().unwrap();
+    let ufrag = agent.local_credentials().ufrag.clone();
+    let ice = Arc::new(Mutex::new(agent));
+
+    let transports = Arc::new(RwLock::new(HashMap::new()));
+    let (transport, _media) = Transport::open(&stream_id, ice.clone(), vec![conn], vec![]).unwrap();
+    transports.write().unwrap().insert(stream_id.clone(), transport);
+
+    // Checks are sent from the component's socket
+    let _checker = Checker::start(ice, transports);
+    let (packet, source) = recv(&peer);
+    assert_eq!(source.port(), addr.port());
+    let request = stun::Message::parse(&packet).unwrap();
+    assert_eq!(request.msg_type, stun::BINDING_REQUEST);
+    assert_eq!(request.username(), Some(format!("peer:{}", ufrag)));
+    assert!(stun::check_integrity(&packet, &request, &stun::short_term_key("peerpassword")));
+}
+
+#[test]
+fn test_transport_rtcp_mux() {
+    let ice = Arc::new(Mutex::new(ice::Agent::new(Box::new(NoHandler))));
+    let conn = bind();
+    let addr = conn.local_addr().unwrap();
+    let (transport, media) = Transport::open("stream", ice, vec![conn], vec![]).unwrap();
+    let (rtp_relay, rtcp_relay) = transport.relay_addrs();
+    media.rtp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    media.rtcp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    let peer = bind();
+    transport.set_peer(ice::RTP_COMPONENT_ID, peer.local_addr().unwrap());
+
+    // RTCP received on the single socket is told apart from RTP
+    let rtp = [0x80, 0x6f, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1];
+    let rtcp = [0x81, 0xc9, 0, 1, 0, 0, 0, 1];
+    assert!(!hibrido::transport::is_rtcp(&rtp));
+    assert!(hibrido::transport::is_rtcp(&rtcp));
+    peer.send_to(&rtcp, addr).unwrap();
+    peer.send_to(&rtp, addr).unwrap();
+    assert_eq!(recv(&media.rtcp), (rtcp.to_vec(), rtcp_relay));
+    assert_eq!(recv(&media.rtp), (rtp.to_vec(), rtp_relay));
+
+    // And both go out on it
+    media.rtcp.send_to(&rtcp, rtcp_relay).unwrap();
+    assert_eq!(recv(&peer), (rtcp.to_vec(), addr));
+}